@@ -61,6 +61,117 @@ pub struct Measurement {
     pub raw: Vec<f32>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// 1-based line number
+    pub line: usize,
+    pub reason: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Measurement {
+    /// Parse a frequency response in the formats commonly used to share measurements and targets:
+    /// - AutoEQ CSV with a `frequency,raw,...` header, the `raw` column is used
+    /// - squig.link and other headerless text files with frequency and level columns
+    /// - REW exports, where lines starting with `*` are comments and the second column is the SPL
+    ///
+    /// Columns may be separated by commas, semicolons or whitespace.
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut measurement = Measurement::default();
+        // Column of the level, the frequency is always the first
+        let mut level_column = 1;
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['*', '#']) {
+                continue;
+            }
+
+            let fields = line
+                .split([',', ';', '\t', ' '])
+                .filter(|field| !field.is_empty())
+                .collect::<Vec<_>>();
+
+            let err = |reason: String| ParseError {
+                line: i + 1,
+                reason,
+            };
+
+            let Ok(frequency) = fields[0].parse::<f32>() else {
+                // A header is only allowed before the data
+                if !measurement.frequency.is_empty() {
+                    return Err(err(format!("invalid frequency `{}`", fields[0])));
+                }
+
+                if let Some(column) = fields
+                    .iter()
+                    .position(|field| field.eq_ignore_ascii_case("raw"))
+                {
+                    level_column = column;
+                }
+                continue;
+            };
+
+            let level = fields
+                .get(level_column)
+                .ok_or_else(|| err("missing level column".to_string()))?;
+            let level = level
+                .parse::<f32>()
+                .map_err(|_| err(format!("invalid level `{level}`")))?;
+
+            if !frequency.is_finite() || frequency <= 0.0 || !level.is_finite() {
+                return Err(err(format!("invalid point {frequency}, {level}")));
+            }
+
+            if measurement
+                .frequency
+                .last()
+                .is_some_and(|&last| frequency <= last)
+            {
+                return Err(err("frequencies must be increasing".to_string()));
+            }
+
+            measurement.frequency.push(frequency);
+            measurement.raw.push(level);
+        }
+
+        if measurement.frequency.is_empty() {
+            return Err(ParseError {
+                line: text.lines().count(),
+                reason: "no data points".to_string(),
+            });
+        }
+
+        Ok(measurement)
+    }
+
+    /// Level at the given frequency, interpolated linearly on a log frequency axis.
+    /// Frequencies outside of the measured range take the level of the nearest end.
+    pub fn level_at(&self, frequency: f64) -> f64 {
+        let idx = self.frequency.partition_point(|&f| (f as f64) < frequency);
+
+        if idx == 0 {
+            return self.raw.first().map_or(0.0, |&level| level as f64);
+        }
+
+        if idx == self.frequency.len() {
+            return self.raw.last().map_or(0.0, |&level| level as f64);
+        }
+
+        let (f0, f1) = (self.frequency[idx - 1] as f64, self.frequency[idx] as f64);
+        let (l0, l1) = (self.raw[idx - 1] as f64, self.raw[idx] as f64);
+        let t = (frequency / f0).ln() / (f1 / f0).ln();
+        l0 + t * (l1 - l0)
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, serde::Serialize)]
 pub struct ResponseRequirements {
     pub fr_f_step: f64,
@@ -160,3 +271,52 @@ pub async fn equalize(
         .await?;
     Ok(res.parametric_eq)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Measurement;
+
+    #[test]
+    fn test_parse_autoeq_csv() {
+        let measurement =
+            Measurement::parse("frequency,raw,smoothed\n20.0,1.5,1.0\n200,-0.5,0.0\n2000,3,2\n")
+                .unwrap();
+        assert_eq!(measurement.frequency, [20.0, 200.0, 2000.0]);
+        assert_eq!(measurement.raw, [1.5, -0.5, 3.0]);
+
+        // Columns other than the second one are picked by the header
+        let measurement = Measurement::parse("frequency,smoothed,raw\n20,1,2\n").unwrap();
+        assert_eq!(measurement.raw, [2.0]);
+
+        assert!((measurement.level_at(10.0) - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_parse_rew_export() {
+        let text = "\
+* Measurement data measured by REW V5.20
+* Freq(Hz), SPL(dB), Phase(degrees)
+20.000, 80.0, 10.0
+200.000\t90.0\t-5.0
+2000.000 100.0 0.0
+";
+        let measurement = Measurement::parse(text).unwrap();
+        assert_eq!(measurement.raw, [80.0, 90.0, 100.0]);
+        // Halfway between 200 Hz and 2 kHz on a log axis
+        assert!((measurement.level_at(200.0 * 10f64.sqrt()) - 95.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = Measurement::parse("20 1\n10 2\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: frequencies must be increasing");
+
+        let err = Measurement::parse("20 1\nfrequency raw\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: invalid frequency `frequency`");
+
+        let err = Measurement::parse("20\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: missing level column");
+
+        assert!(Measurement::parse("* only comments\n").is_err());
+    }
+}
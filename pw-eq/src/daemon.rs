@@ -0,0 +1,998 @@
+//! Background daemon that hosts the EQ modules and routes streams to them, so that they outlive
+//! the TUI. Clients send JSON-RPC 2.0 requests over a Unix socket, one message per line.
+//!
+//! ```text
+//! --> {"jsonrpc": "2.0", "id": 1, "method": "set-band", "params": {"name": "pw-eq", "band": "2", "gain": -3.0}}
+//! <-- {"jsonrpc": "2.0", "id": 1, "result": {"name": "pw-eq", ...}}
+//! --> {"jsonrpc": "2.0", "id": 2, "method": "subscribe"}
+//! <-- {"jsonrpc": "2.0", "id": 2, "result": null}
+//! <-- {"jsonrpc": "2.0", "method": "changed", "params": {"name": "pw-eq", ...}}
+//! ```
+
+use std::collections::BTreeMap;
+use std::io;
+use std::num::NonZero;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::Context as _;
+use pw_util::module::{FREQUENCY_RANGE, TargetObject};
+use pw_util::{NodeInfo, pipewire};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader, Lines};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+use crate::meter::Meter;
+use crate::preset::{self, Profile};
+use crate::pw::{self, pw_thread};
+use crate::spectrum::{self, Capture};
+use crate::tui::eq::Eq;
+use crate::tui::{self, Notif, devices};
+use crate::{FilterId, find_eq_node, update_filters};
+
+const JSONRPC_VERSION: &str = "2.0";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+/// Requests that were understood but failed, the range is reserved for the implementation
+const REQUEST_FAILED: i64 = -32000;
+
+/// Where the daemon listens, `$XDG_RUNTIME_DIR/pw-eq/daemon.sock`
+pub fn socket_path() -> anyhow::Result<PathBuf> {
+    let dir = dirs::runtime_dir()
+        .context("could not find the runtime directory, is XDG_RUNTIME_DIR set?")?;
+    Ok(dir.join("pw-eq").join("daemon.sock"))
+}
+
+/// Methods of the protocol. EQs are referred to by their name, the name of their module or the
+/// id of their sink node.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "kebab-case")]
+pub enum Request {
+    /// Replace the profile of an EQ, creating the EQ if there is none with the name
+    LoadProfile {
+        name: String,
+        profile: Profile,
+    },
+    /// Change the preamp or a band of an EQ
+    SetBand {
+        name: String,
+        band: FilterId,
+        #[serde(default)]
+        frequency: Option<f64>,
+        #[serde(default)]
+        gain: Option<f64>,
+        #[serde(default)]
+        q: Option<f64>,
+    },
+    Bypass {
+        name: String,
+        bypassed: bool,
+    },
+    /// Mute every band of an EQ but one, or none if `band` is unset. It is not part of the profile.
+    Solo {
+        name: String,
+        #[serde(default)]
+        band: Option<NonZero<usize>>,
+    },
+    /// Route all streams to an EQ
+    Use {
+        name: String,
+    },
+    Get {
+        name: String,
+    },
+    List,
+    /// Route an application stream to an EQ or around it, overriding the routing rules until the
+    /// stream goes away
+    RouteStream {
+        node_id: u32,
+        routed: bool,
+        /// EQ to route the stream to, the active EQ if unset
+        #[serde(default)]
+        eq: Option<String>,
+    },
+    /// Send a `changed` notification with the state of an EQ whenever it changes
+    Subscribe,
+}
+
+/// Notifications sent to subscribed clients
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "kebab-case")]
+pub enum Notification {
+    Changed(EqState),
+}
+
+/// An EQ hosted by the daemon
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EqState {
+    pub name: String,
+    pub profile: Profile,
+    pub bypassed: bool,
+    /// Media name of the loaded module, the name suffixed with the band count
+    pub module: Option<String>,
+    /// Id of the sink node of the loaded module
+    pub node_id: Option<u32>,
+    /// Whether streams are routed to this EQ
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Result(Value),
+    Error(RpcError),
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Envelope<T> {
+    jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<Value>,
+    #[serde(flatten)]
+    body: T,
+}
+
+impl<T> Envelope<T> {
+    fn new(id: Option<Value>, body: T) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            body,
+        }
+    }
+}
+
+fn error(id: Option<Value>, code: i64, message: impl Into<String>) -> Envelope<Outcome> {
+    Envelope::new(
+        id,
+        Outcome::Error(RpcError {
+            code,
+            message: message.into(),
+        }),
+    )
+}
+
+/// Decode a request, or the error response to send back
+fn decode(line: &str) -> Result<Envelope<Request>, Box<Envelope<Outcome>>> {
+    let value = serde_json::from_str::<Value>(line)
+        .map_err(|err| error(None, PARSE_ERROR, err.to_string()))?;
+    let id = value.get("id").cloned();
+    let request = serde_json::from_value::<Envelope<Request>>(value)
+        .map_err(|err| Box::new(error(id.clone(), INVALID_REQUEST, err.to_string())))?;
+    if request.jsonrpc != JSONRPC_VERSION {
+        return Err(Box::new(error(
+            id,
+            INVALID_REQUEST,
+            format!("unsupported jsonrpc version: {}", request.jsonrpc),
+        )));
+    }
+    Ok(request)
+}
+
+async fn write_line(
+    writer: &mut OwnedWriteHalf,
+    message: &impl serde::Serialize,
+) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await
+}
+
+/// Request with the channel its result is sent back on
+type Call = (Request, oneshot::Sender<anyhow::Result<Value>>);
+
+async fn serve(
+    listener: UnixListener,
+    calls: mpsc::Sender<Call>,
+    changes: broadcast::Sender<EqState>,
+) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let calls = calls.clone();
+                let changes = changes.subscribe();
+                tokio::spawn(async move {
+                    if let Err(err) = connection(stream, calls, changes).await {
+                        tracing::warn!(error = &*err, "client connection failed");
+                    }
+                });
+            }
+            Err(err) => tracing::error!(
+                error = &err as &dyn std::error::Error,
+                "failed to accept client"
+            ),
+        }
+    }
+}
+
+/// Serve the requests of a client. Changes are only forwarded once it subscribed.
+async fn connection(
+    stream: UnixStream,
+    calls: mpsc::Sender<Call>,
+    mut changes: broadcast::Receiver<EqState>,
+) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut subscribed = false;
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    return Ok(());
+                };
+
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let response = match decode(&line) {
+                    Err(response) => *response,
+                    // Notifications are requests without an id, they get no response
+                    Ok(Envelope { id: None, body, .. }) => {
+                        match body {
+                            Request::Subscribe => {
+                                changes = changes.resubscribe();
+                                subscribed = true;
+                            }
+                            body => {
+                                let (tx, _) = oneshot::channel();
+                                let _ = calls.send((body, tx)).await;
+                            }
+                        }
+                        continue;
+                    }
+                    Ok(Envelope { id, body: Request::Subscribe, .. }) => {
+                        // Only changes from now on are of interest
+                        changes = changes.resubscribe();
+                        subscribed = true;
+                        Envelope::new(id, Outcome::Result(Value::Null))
+                    }
+                    Ok(Envelope { id, body, .. }) => {
+                        let (tx, rx) = oneshot::channel();
+                        let result = match calls.send((body, tx)).await {
+                            Ok(()) => rx.await.ok(),
+                            Err(_) => None,
+                        };
+                        match result {
+                            Some(Ok(result)) => Envelope::new(id, Outcome::Result(result)),
+                            Some(Err(err)) => error(id, REQUEST_FAILED, format!("{err:#}")),
+                            None => error(id, REQUEST_FAILED, "the daemon is shutting down"),
+                        }
+                    }
+                };
+                write_line(&mut writer, &response).await?;
+            }
+            change = changes.recv(), if subscribed => match change {
+                Ok(state) => {
+                    write_line(&mut writer, &Envelope::new(None, Notification::Changed(state))).await?;
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "client is too slow to keep up with changes");
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
+
+/// Connection to a running daemon
+pub struct Client {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+}
+
+impl Client {
+    pub async fn connect(path: &Path) -> anyhow::Result<Self> {
+        let stream = UnixStream::connect(path)
+            .await
+            .with_context(|| format!("failed to connect to the daemon at {}", path.display()))?;
+        Ok(Self::from_stream(stream))
+    }
+
+    fn from_stream(stream: UnixStream) -> Self {
+        let (reader, writer) = stream.into_split();
+        Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+            next_id: 1,
+        }
+    }
+
+    /// Connect to the daemon if it is running
+    pub async fn connect_running() -> anyhow::Result<Option<Self>> {
+        let path = socket_path()?;
+        match UnixStream::connect(&path).await {
+            Ok(stream) => Ok(Some(Self::from_stream(stream))),
+            // The socket of a daemon that didn't exit cleanly may be left behind
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err)
+                .with_context(|| format!("failed to connect to the daemon at {}", path.display())),
+        }
+    }
+
+    pub async fn call<T: DeserializeOwned>(&mut self, request: &Request) -> anyhow::Result<T> {
+        let id = self.next_id;
+        self.next_id += 1;
+        write_line(&mut self.writer, &Envelope::new(Some(id.into()), request)).await?;
+
+        loop {
+            let line = self
+                .lines
+                .next_line()
+                .await?
+                .context("the daemon closed the connection")?;
+            let response = serde_json::from_str::<Envelope<Outcome>>(&line)
+                .with_context(|| format!("invalid response from the daemon: {line}"))?;
+            // Skip notifications and responses to requests that are no longer awaited
+            if response.id != Some(id.into()) {
+                continue;
+            }
+
+            return match response.body {
+                Outcome::Result(result) => Ok(serde_json::from_value(result)?),
+                Outcome::Error(err) => Err(anyhow::anyhow!("{}", err.message)),
+            };
+        }
+    }
+
+    /// Whether the daemon hosts the EQ with the given name, module name or sink node id
+    pub async fn hosts(&mut self, name: &str) -> anyhow::Result<bool> {
+        let states = self.call::<Vec<EqState>>(&Request::List).await?;
+        let node_id = name.parse::<u32>().ok();
+        Ok(states.iter().any(|state| {
+            state.name == name
+                || state.module.as_deref() == Some(name)
+                || node_id.is_some_and(|id| state.node_id == Some(id))
+        }))
+    }
+
+    /// Receive changes on this connection, it can't be used for other requests afterwards
+    pub async fn subscribe(mut self) -> anyhow::Result<Subscription> {
+        self.call::<()>(&Request::Subscribe).await?;
+        Ok(Subscription { lines: self.lines })
+    }
+}
+
+pub struct Subscription {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+}
+
+impl Subscription {
+    pub async fn next(&mut self) -> anyhow::Result<Notification> {
+        let line = self
+            .lines
+            .next_line()
+            .await?
+            .context("the daemon closed the connection")?;
+        let notification = serde_json::from_str::<Envelope<Notification>>(&line)
+            .with_context(|| format!("invalid notification from the daemon: {line}"))?;
+        Ok(notification.body)
+    }
+}
+
+/// Listen on the socket, unless another daemon already does
+async fn bind(path: &Path) -> anyhow::Result<UnixListener> {
+    if UnixStream::connect(path).await.is_ok() {
+        anyhow::bail!("the daemon is already running at {}", path.display());
+    }
+
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    // Left behind by a daemon that didn't exit cleanly
+    let _ = tokio::fs::remove_file(path).await;
+    UnixListener::bind(path).with_context(|| format!("failed to listen on {}", path.display()))
+}
+
+/// Run the daemon until it is interrupted, loading `initial` on startup
+pub async fn run(
+    config: tui::Config,
+    path: &Path,
+    initial: Option<(String, Profile)>,
+) -> anyhow::Result<()> {
+    let listener = bind(path).await?;
+    tracing::info!(path = %path.display(), "daemon listening");
+
+    let default_audio_sink = pw_util::get_default_audio_sink()
+        .await
+        .inspect_err(|err| tracing::error!(error = &**err, "failed to get default audio sink"))
+        .ok();
+
+    let (pw_tx, rx) = pipewire::channel::channel();
+    let (notifs_tx, mut notifs) = mpsc::channel(100);
+    // Nothing is captured or metered, but the PipeWire thread expects somewhere to put it
+    let capture = Arc::new(Mutex::new(Capture::new(spectrum::MAX_FFT_SIZE)));
+    let meter = Arc::new(Mutex::new(Meter::default()));
+    let routing = config.routing.clone();
+    let pw_handle = thread::spawn(move || {
+        pw_thread(notifs_tx, rx, default_audio_sink, capture, meter, routing)
+    });
+
+    let (calls_tx, mut calls) = mpsc::channel::<Call>(100);
+    let (changes, _) = broadcast::channel(100);
+    let server = tokio::spawn(serve(listener, calls_tx, changes.clone()));
+
+    let mut host = Host {
+        config,
+        pw_tx: pw_tx.clone(),
+        eqs: BTreeMap::new(),
+        active: None,
+        sample_rate: 48000,
+        output_device: None,
+        changes,
+    };
+    if let Some((name, profile)) = initial {
+        host.load_profile(name, profile).await?;
+    }
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    loop {
+        tokio::select! {
+            Some((request, reply)) = calls.recv() => {
+                let _ = reply.send(host.handle(request).await);
+            }
+            Some(notif) = notifs.recv() => host.on_notif(notif).await,
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+    }
+
+    tracing::info!("daemon shutting down");
+    server.abort();
+    let _ = pw_tx.send(pw::Message::Terminate);
+    match pw_handle.join() {
+        Ok(result) => result?,
+        Err(err) => tracing::error!(error = ?err, "PipeWire thread panicked"),
+    }
+    let _ = tokio::fs::remove_file(path).await;
+    Ok(())
+}
+
+struct Instance {
+    eq: Eq,
+    /// Sink node of the loaded module, `None` while it is loading
+    node: Option<NodeInfo>,
+}
+
+/// The EQs of the daemon, only touched by the task running it so requests apply in order
+struct Host {
+    config: tui::Config,
+    pw_tx: pipewire::channel::Sender<pw::Message>,
+    eqs: BTreeMap<String, Instance>,
+    /// EQ that streams are routed to
+    active: Option<String>,
+    sample_rate: u32,
+    output_device: Option<NodeInfo>,
+    changes: broadcast::Sender<EqState>,
+}
+
+impl Host {
+    async fn handle(&mut self, request: Request) -> anyhow::Result<Value> {
+        let name = match request {
+            Request::LoadProfile { name, profile } => {
+                self.load_profile(name.clone(), profile).await?;
+                name
+            }
+            Request::SetBand {
+                name,
+                band,
+                frequency,
+                gain,
+                q,
+            } => {
+                let name = self.find(&name)?;
+                self.set_band(&name, band, frequency, gain, q).await?;
+                name
+            }
+            Request::Bypass { name, bypassed } => {
+                let name = self.find(&name)?;
+                self.eqs.get_mut(&name).unwrap().eq.bypassed = bypassed;
+                self.sync(&name).await?;
+                self.notify(&name);
+                name
+            }
+            Request::Solo { name, band } => {
+                let name = self.find(&name)?;
+                let eq = &mut self.eqs.get_mut(&name).unwrap().eq;
+                if let Some(band) = band {
+                    anyhow::ensure!(
+                        band.get() <= eq.filters.len(),
+                        "EQ '{name}' has no band {band}"
+                    );
+                }
+                eq.solo_idx = band.map(|band| band.get() - 1);
+                self.sync(&name).await?;
+                name
+            }
+            Request::Use { name } => {
+                let name = self.find(&name)?;
+                let previous = self.active.replace(name.clone());
+                self.route_streams();
+                if let Some(previous) = previous.filter(|previous| *previous != name) {
+                    self.notify(&previous);
+                }
+                self.notify(&name);
+                name
+            }
+            Request::Get { name } => self.find(&name)?,
+            Request::List => {
+                let states = self
+                    .eqs
+                    .keys()
+                    .map(|name| self.state(name))
+                    .collect::<Vec<_>>();
+                return Ok(serde_json::to_value(states)?);
+            }
+            Request::RouteStream {
+                node_id,
+                routed,
+                eq,
+            } => {
+                let eq = eq.map(|name| self.find(&name)).transpose()?;
+                let _ = self.pw_tx.send(pw::Message::SetStreamRouted {
+                    node_id,
+                    routed,
+                    eq,
+                });
+                return Ok(Value::Null);
+            }
+            // Subscriptions are per connection
+            Request::Subscribe => return Ok(Value::Null),
+        };
+
+        Ok(serde_json::to_value(self.state(&name))?)
+    }
+
+    /// Name of the EQ with the given name, module name or sink node id
+    fn find(&self, name: &str) -> anyhow::Result<String> {
+        let node_id = name.parse::<u32>().ok();
+        self.eqs
+            .iter()
+            .find(|(eq_name, instance)| {
+                *eq_name == name
+                    || instance.eq.module_name() == name
+                    || node_id.is_some_and(|id| {
+                        instance
+                            .node
+                            .as_ref()
+                            .is_some_and(|node| node.node_id == id)
+                    })
+            })
+            .map(|(eq_name, _)| eq_name.clone())
+            .ok_or_else(|| anyhow::anyhow!("EQ '{name}' not found"))
+    }
+
+    fn state(&self, name: &str) -> EqState {
+        let instance = &self.eqs[name];
+        EqState {
+            name: name.to_string(),
+            profile: instance.eq.profile(),
+            bypassed: instance.eq.bypassed,
+            module: instance.node.as_ref().map(|node| node.node_name.clone()),
+            node_id: instance.node.as_ref().map(|node| node.node_id),
+            active: self.active.as_deref() == Some(name),
+        }
+    }
+
+    fn notify(&self, name: &str) {
+        // There may be no subscribers
+        let _ = self.changes.send(self.state(name));
+    }
+
+    async fn load_profile(&mut self, name: String, profile: Profile) -> anyhow::Result<()> {
+        let max_filters = Eq::new(&name, []).max_filters;
+        anyhow::ensure!(
+            (1..=max_filters).contains(&profile.filters.len()),
+            "a profile must have between 1 and {max_filters} bands, got {}",
+            profile.filters.len()
+        );
+        anyhow::ensure!(
+            profile.preamp.is_finite(),
+            "invalid preamp: {}",
+            profile.preamp
+        );
+        for (idx, filter) in profile.filters.iter().enumerate() {
+            filter
+                .validate()
+                .with_context(|| format!("invalid band {}", idx + 1))?;
+            anyhow::ensure!(
+                FREQUENCY_RANGE.contains(&filter.frequency),
+                "invalid band {}: frequency {} Hz is outside {} to {} Hz",
+                idx + 1,
+                filter.frequency,
+                FREQUENCY_RANGE.start(),
+                FREQUENCY_RANGE.end()
+            );
+        }
+
+        let instance = self.eqs.entry(name.clone()).or_insert_with(|| Instance {
+            eq: Eq::new(&name, []),
+            node: None,
+        });
+
+        let band_count = instance.eq.filters.len();
+        instance.eq.preamp = profile.preamp;
+        instance.eq.filters = profile.filters;
+        instance.eq.graphic = profile.graphic;
+        instance.eq.formula = self.config.biquad_formula;
+        let reload = instance.node.is_none() || band_count != instance.eq.filters.len();
+
+        if self.active.is_none() {
+            self.active = Some(name.clone());
+        }
+
+        if reload {
+            self.load_module(&name);
+        } else if let Err(err) = self.sync(&name).await {
+            tracing::error!(error = &*err, eq = name, "failed to update EQ");
+        }
+        self.notify(&name);
+        Ok(())
+    }
+
+    async fn set_band(
+        &mut self,
+        name: &str,
+        band: FilterId,
+        frequency: Option<f64>,
+        gain: Option<f64>,
+        q: Option<f64>,
+    ) -> anyhow::Result<()> {
+        let eq = &mut self.eqs.get_mut(name).unwrap().eq;
+        let update = match band {
+            FilterId::Preamp => {
+                anyhow::ensure!(
+                    frequency.is_none() && q.is_none(),
+                    "only the gain of the preamp can be set"
+                );
+                if let Some(gain) = gain {
+                    anyhow::ensure!(gain.is_finite(), "invalid preamp: {gain}");
+                    eq.preamp = gain;
+                }
+                eq.build_preamp_update()
+            }
+            FilterId::Index(idx) => {
+                let idx = idx.get() - 1;
+                let graphic = eq.graphic;
+                let filter = eq
+                    .filters
+                    .get_mut(idx)
+                    .with_context(|| format!("EQ '{name}' has no band {}", idx + 1))?;
+                if graphic.is_some() {
+                    anyhow::ensure!(
+                        frequency.is_none() && q.is_none(),
+                        "only the gains can be changed in graphic mode"
+                    );
+                }
+
+                // Applied as given like a `set` without the daemon, unless unusable
+                let mut updated = *filter;
+                updated.frequency = frequency.unwrap_or(filter.frequency);
+                updated.gain = gain.unwrap_or(filter.gain);
+                updated.q = q.unwrap_or(filter.q);
+                updated
+                    .validate()
+                    .with_context(|| format!("invalid band {}", idx + 1))?;
+                if let Some(graphic) = graphic {
+                    graphic.conform(&mut updated);
+                }
+                *filter = updated;
+                eq.build_filter_update(idx, self.sample_rate)
+            }
+        };
+
+        if let Some(node) = &self.eqs[name].node {
+            update_filters(node.node_id, [(band, update)]).await?;
+        }
+        self.notify(name);
+        Ok(())
+    }
+
+    fn load_module(&mut self, name: &str) {
+        let instance = self.eqs.get_mut(name).unwrap();
+        // Edits are applied once the module is loaded
+        instance.node = None;
+
+        let mut rates = self.config.sample_rates.clone();
+        rates.push(self.sample_rate);
+        rates.sort_unstable();
+        rates.dedup();
+
+        let mut args = instance.eq.to_module_args(rates);
+        if let Some(device) = &self.output_device {
+            args.playback_props.target_object = Some(TargetObject::Serial(device.object_serial));
+        }
+
+        let _ = self.pw_tx.send(pw::Message::LoadModule {
+            name: "libpipewire-module-filter-chain".into(),
+            args: Box::new(args),
+        });
+    }
+
+    /// Bring the node of an EQ in line with its bands
+    async fn sync(&self, name: &str) -> anyhow::Result<()> {
+        let instance = &self.eqs[name];
+        if let Some(node) = &instance.node {
+            update_filters(node.node_id, instance.eq.build_updates(self.sample_rate)).await?;
+        }
+        Ok(())
+    }
+
+    fn route_streams(&self) {
+        let node = self
+            .active
+            .as_ref()
+            .and_then(|name| self.eqs[name].node.clone());
+        if let Some(node) = node
+            && let Err(err) = self.pw_tx.send(pw::Message::SetActiveNode(node))
+        {
+            tracing::error!(error = ?err, "failed to set active node");
+        }
+    }
+
+    async fn on_notif(&mut self, notif: Notif) {
+        match notif {
+            Notif::PwModuleLoaded { media_name, .. } => {
+                let Some(name) = self
+                    .eqs
+                    .iter()
+                    .find(|(_, instance)| instance.eq.module_name() == media_name)
+                    .map(|(name, _)| name.clone())
+                else {
+                    return;
+                };
+
+                let node = match find_eq_node(&media_name).await {
+                    Ok(node) => node,
+                    Err(err) => {
+                        tracing::error!(error = &*err, "failed to find EQ node");
+                        return;
+                    }
+                };
+
+                let Some(object_serial) = node
+                    .info
+                    .props
+                    .get("object.serial")
+                    .and_then(|serial| serial.as_i64())
+                else {
+                    tracing::error!(media_name, "object.serial missing or malformed");
+                    return;
+                };
+
+                tracing::info!(eq = name, node_id = node.id, "module loaded");
+                let node = NodeInfo {
+                    node_id: node.id,
+                    node_name: media_name,
+                    object_serial,
+                };
+                let _ = self.pw_tx.send(pw::Message::SetEqNode {
+                    name: name.clone(),
+                    node: node.clone(),
+                    source: false,
+                });
+                self.eqs.get_mut(&name).unwrap().node = Some(node);
+                if let Err(err) = self.sync(&name).await {
+                    tracing::error!(error = &*err, eq = name, "failed to update EQ");
+                }
+                if self.active.as_ref() == Some(&name) {
+                    self.route_streams();
+                }
+                self.notify(&name);
+            }
+            Notif::SampleRateChanged(rate) => {
+                self.sample_rate = rate;
+                for name in self.eqs.keys() {
+                    if let Err(err) = self.sync(name).await {
+                        tracing::error!(error = &*err, eq = name, "failed to update EQ");
+                    }
+                }
+            }
+            Notif::OutputDeviceChanged(device) => self.on_output_device_changed(device).await,
+            Notif::Error(err) => tracing::error!(error = &*err, "error from PipeWire thread"),
+            _ => {}
+        }
+    }
+
+    /// Play the EQs into the new device and load the preset of the first rule matching it into
+    /// the active EQ
+    async fn on_output_device_changed(&mut self, device: pw::OutputDevice) {
+        self.output_device = Some(NodeInfo {
+            node_id: device.node_id,
+            node_name: device.node_name.clone(),
+            object_serial: device.object_serial,
+        });
+
+        for name in self.eqs.keys() {
+            let _ = self.pw_tx.send(pw::Message::SetOutputTarget {
+                name: name.clone(),
+                object_serial: device.object_serial,
+            });
+        }
+
+        let Some(rule) = devices::matching(&self.config.device_profiles, &device) else {
+            return;
+        };
+        let Some(name) = self.active.clone() else {
+            return;
+        };
+
+        let preset = rule.preset.clone();
+        let result = match async { preset::Library::user()?.load(&preset).await }.await {
+            Ok(profile) => {
+                tracing::info!(preset, eq = name, "loading preset for device");
+                self.load_profile(name, profile).await
+            }
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            tracing::error!(error = &*err, preset, "failed to load preset");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+    use tokio::net::UnixListener;
+    use tokio::sync::{broadcast, mpsc};
+
+    use super::{Call, Client, EqState, Notification, Request, decode, serve};
+    use crate::FilterId;
+    use crate::preset::Profile;
+
+    #[test]
+    fn test_decode() {
+        let request = decode(
+            r#"{"jsonrpc": "2.0", "id": 7, "method": "set-band", "params": {"name": "pw-eq", "band": "preamp", "gain": -3}}"#,
+        )
+        .unwrap();
+        assert_eq!(request.id, Some(json!(7)));
+        assert_eq!(
+            request.body,
+            Request::SetBand {
+                name: "pw-eq".to_string(),
+                band: FilterId::Preamp,
+                frequency: None,
+                gain: Some(-3.0),
+                q: None,
+            }
+        );
+        assert_eq!(
+            decode(r#"{"jsonrpc": "2.0", "id": 1, "method": "list"}"#)
+                .unwrap()
+                .body,
+            Request::List
+        );
+
+        let error = |line| serde_json::to_value(decode(line).unwrap_err()).unwrap();
+        assert_eq!(error("{")["error"]["code"], json!(-32700));
+        let unknown = error(r#"{"jsonrpc": "2.0", "id": 2, "method": "reboot"}"#);
+        assert_eq!(unknown["id"], json!(2));
+        assert_eq!(unknown["error"]["code"], json!(-32600));
+        let version = error(r#"{"jsonrpc": "1.0", "id": 3, "method": "list"}"#);
+        assert_eq!(version["id"], json!(3));
+        assert_eq!(version["error"]["code"], json!(-32600));
+        let missing = error(r#"{"id": 4, "method": "list"}"#);
+        assert_eq!(missing["id"], json!(4));
+        assert_eq!(missing["error"]["code"], json!(-32600));
+    }
+
+    #[tokio::test]
+    async fn test_client() {
+        let path = std::env::temp_dir().join(format!("pw-eq-daemon-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let (calls_tx, mut calls) = mpsc::channel::<Call>(1);
+        let (changes, _) = broadcast::channel(1);
+        tokio::spawn(serve(listener, calls_tx, changes.clone()));
+
+        let state = EqState {
+            name: "pw-eq".to_string(),
+            profile: Profile {
+                preamp: -1.0,
+                filters: vec![],
+                graphic: None,
+            },
+            bypassed: false,
+            module: None,
+            node_id: None,
+            active: true,
+        };
+        // Stands in for the host, answering the single request
+        tokio::spawn({
+            let state = state.clone();
+            async move {
+                let (request, reply) = calls.recv().await.unwrap();
+                let result = match request {
+                    Request::Get { name } if name == "pw-eq" => {
+                        Ok(serde_json::to_value(state).unwrap())
+                    }
+                    _ => Err(anyhow::anyhow!("EQ not found")),
+                };
+                let _ = reply.send(result);
+            }
+        });
+
+        let mut client = Client::connect(&path).await.unwrap();
+        let got = client
+            .call::<EqState>(&Request::Get {
+                name: "pw-eq".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(got, state);
+
+        let mut subscription = Client::connect(&path)
+            .await
+            .unwrap()
+            .subscribe()
+            .await
+            .unwrap();
+        changes.send(state.clone()).unwrap();
+        assert_eq!(
+            subscription.next().await.unwrap(),
+            Notification::Changed(state)
+        );
+
+        // The host is gone, the connection reports it instead of hanging
+        let err = client.call::<Value>(&Request::List).await.unwrap_err();
+        assert!(err.to_string().contains("shutting down"), "{err}");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_notifications_get_no_response() {
+        use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader};
+
+        let path = std::env::temp_dir().join(format!("pw-eq-notify-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let (calls_tx, mut calls) = mpsc::channel::<Call>(2);
+        let (changes, _) = broadcast::channel(1);
+        tokio::spawn(serve(listener, calls_tx, changes));
+        tokio::spawn(async move {
+            while let Some((_, reply)) = calls.recv().await {
+                let _ = reply.send(Ok(Value::Null));
+            }
+        });
+
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        writer
+            .write_all(
+                b"{\"jsonrpc\": \"2.0\", \"method\": \"list\"}\n\
+                  {\"jsonrpc\": \"2.0\", \"id\": 3, \"method\": \"list\"}\n",
+            )
+            .await
+            .unwrap();
+
+        // The first response is to the request with an id
+        let line = BufReader::new(reader).lines().next_line().await.unwrap();
+        let response = serde_json::from_str::<Value>(&line.unwrap()).unwrap();
+        assert_eq!(response["id"], json!(3));
+        let _ = std::fs::remove_file(&path);
+    }
+}
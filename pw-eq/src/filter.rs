@@ -1,27 +1,34 @@
+use num_complex::Complex64;
 use pw_util::{
     apo,
-    module::{BiquadCoefficients, FilterType},
+    module::{BiquadCoefficients, FREQUENCY_RANGE, FilterType},
 };
 use std::f64::consts::PI;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+pub use pw_util::module::FilterError;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct Filter {
     pub frequency: f64,
     pub gain: f64,
     pub q: f64,
     pub filter_type: FilterType,
+    #[serde(default)]
     pub muted: bool,
+    /// Locked filters are left alone by the optimizer
+    #[serde(default)]
+    pub locked: bool,
 }
 
-impl From<apo::Filter> for Filter {
-    fn from(f: apo::Filter) -> Self {
-        Self {
-            frequency: f.frequency,
-            gain: f.gain,
-            q: f.q,
-            filter_type: f.filter_type,
+impl TryFrom<apo::Filter> for Filter {
+    type Error = FilterError;
+
+    fn try_from(f: apo::Filter) -> Result<Self, Self::Error> {
+        Ok(Self {
             muted: !f.enabled,
-        }
+            ..Self::new(f.filter_type, f.frequency, f.gain, f.q)?
+        })
     }
 }
 
@@ -33,15 +40,73 @@ impl Default for Filter {
             q: 1.0 / 1.414,
             filter_type: FilterType::Peaking,
             muted: false,
+            locked: false,
         }
     }
 }
 
+/// Formulas used to derive biquad coefficients from a filter's parameters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BiquadFormula {
+    /// Port of pipewire's `spa/plugins/audioconvert/biquad.c`.
+    /// This matches what the `param_eq` and `bq_*` nodes compute from a saved configuration.
+    #[default]
+    #[serde(rename = "pipewire")]
+    PipeWire,
+    /// The Audio EQ Cookbook formulas by Robert Bristow-Johnson.
+    Rbj,
+}
+
 impl Filter {
-    /// Calculate biquad coefficients based on filter type
+    /// Create an unmuted filter, rejecting parameters that biquad coefficients cannot be computed for.
+    pub fn new(
+        filter_type: FilterType,
+        frequency: f64,
+        gain: f64,
+        q: f64,
+    ) -> Result<Self, FilterError> {
+        filter_type.validate(frequency, gain, q)?;
+        Ok(Self {
+            frequency,
+            gain,
+            q,
+            filter_type,
+            muted: false,
+            locked: false,
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), FilterError> {
+        self.filter_type.validate(self.frequency, self.gain, self.q)
+    }
+
+    /// Clamp the parameters into the editable ranges of the filter type.
+    /// Gain is left alone for filter types that ignore it.
+    pub fn clamp(&mut self) {
+        self.frequency = self
+            .frequency
+            .clamp(*FREQUENCY_RANGE.start(), *FREQUENCY_RANGE.end());
+        let q_range = self.filter_type.q_range();
+        self.q = self.q.clamp(*q_range.start(), *q_range.end());
+        if let Some(gain_range) = self.filter_type.gain_range() {
+            self.gain = self.gain.clamp(*gain_range.start(), *gain_range.end());
+        }
+    }
+
+    /// Calculate biquad coefficients based on filter type using the default formula.
     /// Returns normalized (b0, b1, b2, a0, a1, a2) where a0 = 1.0
     /// If muted, returns identity coefficients (pass-through)
     pub fn biquad_coeffs(&self, sample_rate: f64) -> BiquadCoefficients {
+        self.biquad_coeffs_with(BiquadFormula::default(), sample_rate)
+    }
+
+    /// Calculate biquad coefficients based on filter type using the given formula.
+    pub fn biquad_coeffs_with(
+        &self,
+        formula: BiquadFormula,
+        sample_rate: f64,
+    ) -> BiquadCoefficients {
         // When muted, return identity filter (pass-through)
         if self.muted {
             return BiquadCoefficients {
@@ -53,6 +118,28 @@ impl Filter {
             };
         }
 
+        match formula {
+            BiquadFormula::PipeWire => self.pipewire_coeffs(sample_rate),
+            BiquadFormula::Rbj => self.rbj_coeffs(sample_rate),
+        }
+    }
+
+    fn pipewire_coeffs(&self, sample_rate: f64) -> BiquadCoefficients {
+        // pipewire normalizes the frequency to the nyquist frequency before computing coefficients
+        let freq = self.frequency * 2.0 / sample_rate;
+        let (q, gain) = (self.q, self.gain);
+        match self.filter_type {
+            FilterType::LowPass => pipewire::lowpass(freq, q),
+            FilterType::HighPass => pipewire::highpass(freq, q),
+            FilterType::BandPass => pipewire::bandpass(freq, q),
+            FilterType::LowShelf => pipewire::lowshelf(freq, q, gain),
+            FilterType::HighShelf => pipewire::highshelf(freq, q, gain),
+            FilterType::Peaking => pipewire::peaking(freq, q, gain),
+            FilterType::Notch => pipewire::notch(freq, q),
+        }
+    }
+
+    fn rbj_coeffs(&self, sample_rate: f64) -> BiquadCoefficients {
         if self.q <= 0.0 {
             match self.filter_type {
                 // Special case, high-shelf with Q <= 0 is just pure gain
@@ -65,18 +152,16 @@ impl Filter {
                         a2: 0.0,
                     };
                 }
-                _ => panic!(
-                    "Q factor must be positive for filter type {:?}",
-                    self.filter_type
-                ),
+                _ => return self.pipewire_coeffs(sample_rate),
             }
         }
 
-        assert!(self.q > 0.0, "Q factor must be positive");
-        assert!(self.frequency > 0.0);
-
         let w0 = 2.0 * PI * self.frequency / sample_rate;
-        assert!(w0 > 0.0 && w0 < PI, "Frequency must be in (0, Nyquist)");
+        if !(w0 > 0.0 && w0 < PI) {
+            // The cookbook formulas are only defined in (0, Nyquist), e.g. a 20 kHz band at 32 kHz is not.
+            // Use the limits of the z-transform that pipewire computes instead.
+            return self.pipewire_coeffs(sample_rate);
+        }
 
         let cos_w0 = w0.cos();
         let sin_w0 = w0.sin();
@@ -84,9 +169,6 @@ impl Filter {
 
         let a = 10_f64.powf(self.gain / 40.0); // dB to amplitude
 
-        // These are not identical to pipewire's implementation, but the results are very close.
-        // Can copy their implementation directly if exact match is needed.
-        // pipewire/spa/plugins/audioconvert/biquad.c
         let (b0, b1, b2, a0, a1, a2) = match self.filter_type {
             FilterType::Peaking => {
                 let b0 = 1.0 + alpha * a;
@@ -168,25 +250,488 @@ impl Filter {
 
     /// Calculate magnitude response in dB at a given frequency
     pub fn magnitude_db_at(&self, freq: f64, sample_rate: f64) -> f64 {
+        self.magnitude_db_at_with(BiquadFormula::default(), freq, sample_rate)
+    }
+
+    /// Calculate magnitude response in dB at a given frequency using the given formula
+    pub fn magnitude_db_at_with(&self, formula: BiquadFormula, freq: f64, sample_rate: f64) -> f64 {
         // When muted, filter has no effect (0 dB)
         if self.muted {
             return 0.0;
         }
 
-        let BiquadCoefficients { b0, b1, b2, a1, a2 } = self.biquad_coeffs(sample_rate);
-        let w = 2.0 * PI * freq / sample_rate;
+        20.0 * self
+            .response_at_with(formula, freq, sample_rate)
+            .norm()
+            .log10()
+    }
+
+    /// Complex frequency response H(e^jw) at a given frequency
+    pub fn response_at_with(
+        &self,
+        formula: BiquadFormula,
+        freq: f64,
+        sample_rate: f64,
+    ) -> Complex64 {
+        let BiquadCoefficients { b0, b1, b2, a1, a2 } =
+            self.biquad_coeffs_with(formula, sample_rate);
+        let z1 = Complex64::from_polar(1.0, -2.0 * PI * freq / sample_rate);
+        let z2 = z1 * z1;
+
+        (b0 + b1 * z1 + b2 * z2) / (1.0 + a1 * z1 + a2 * z2)
+    }
+
+    /// Phase response in radians at a given frequency, in (-pi, pi]
+    pub fn phase_at_with(&self, formula: BiquadFormula, freq: f64, sample_rate: f64) -> f64 {
+        self.response_at_with(formula, freq, sample_rate).arg()
+    }
+
+    /// Group delay in seconds at a given frequency, i.e. the negative derivative of the phase
+    /// with respect to angular frequency.
+    pub fn group_delay_at_with(&self, formula: BiquadFormula, freq: f64, sample_rate: f64) -> f64 {
+        let BiquadCoefficients { b0, b1, b2, a1, a2 } =
+            self.biquad_coeffs_with(formula, sample_rate);
+        let z1 = Complex64::from_polar(1.0, -2.0 * PI * freq / sample_rate);
+        let z2 = z1 * z1;
+
+        // The group delay of a polynomial in z^-1 is Re(sum(k * c_k * z^-k) / sum(c_k * z^-k)) samples.
+        // It is undefined on a zero of the polynomial (e.g. the center of a notch), treat it as 0 there.
+        let delay = |c0: f64, c1: f64, c2: f64| {
+            let poly = c0 + c1 * z1 + c2 * z2;
+            if poly.norm() < 1e-12 {
+                return 0.0;
+            }
+            ((c1 * z1 + 2.0 * c2 * z2) / poly).re
+        };
+
+        (delay(b0, b1, b2) - delay(1.0, a1, a2)) / sample_rate
+    }
+}
+
+/// Port of pipewire/spa/plugins/audioconvert/biquad.c
+/// All frequencies are normalized to the nyquist frequency, i.e. in [0, 1].
+/// The edge cases (frequency of 0 or 1, Q <= 0) follow the limits of the z-transform as pipewire does.
+mod pipewire {
+    use super::BiquadCoefficients;
+    use std::f64::consts::PI;
+
+    /// pipewire stores coefficients as single precision floats
+    fn set_coefficient(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> BiquadCoefficients {
+        let a0_inv = 1.0 / a0;
+        BiquadCoefficients {
+            b0: (b0 * a0_inv) as f32 as f64,
+            b1: (b1 * a0_inv) as f32 as f64,
+            b2: (b2 * a0_inv) as f32 as f64,
+            a1: (a1 * a0_inv) as f32 as f64,
+            a2: (a2 * a0_inv) as f32 as f64,
+        }
+    }
+
+    pub fn lowpass(cutoff: f64, q: f64) -> BiquadCoefficients {
+        let cutoff = cutoff.clamp(0.0, 1.0);
+
+        if cutoff >= 1.0 {
+            // When cutoff is 1, the z-transform is 1.
+            return set_coefficient(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        if cutoff <= 0.0 || q <= 0.0 {
+            // When cutoff is zero, nothing gets through the filter.
+            // As Q -> 0, the z-transform also approaches 0.
+            return set_coefficient(0.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        let w0 = PI * cutoff;
+        let alpha = w0.sin() / (2.0 * q);
+        let k = w0.cos();
+        let beta = (1.0 - k) / 2.0;
+
+        set_coefficient(beta, 2.0 * beta, beta, 1.0 + alpha, -2.0 * k, 1.0 - alpha)
+    }
+
+    pub fn highpass(cutoff: f64, q: f64) -> BiquadCoefficients {
+        let cutoff = cutoff.clamp(0.0, 1.0);
+
+        if cutoff >= 1.0 || q <= 0.0 {
+            // When cutoff is one, the z-transform is 0.
+            // As Q -> 0, the z-transform also approaches 0.
+            return set_coefficient(0.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        if cutoff <= 0.0 {
+            // When cutoff is zero, we need to be careful because the above
+            // gives a quadratic divided by the same quadratic, with poles
+            // and zeros on the unit circle in the same place. When cutoff
+            // is zero, the z-transform is 1.
+            return set_coefficient(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        let w0 = PI * cutoff;
+        let alpha = w0.sin() / (2.0 * q);
+        let k = w0.cos();
+        let beta = (1.0 + k) / 2.0;
+
+        set_coefficient(beta, -2.0 * beta, beta, 1.0 + alpha, -2.0 * k, 1.0 - alpha)
+    }
+
+    pub fn bandpass(freq: f64, q: f64) -> BiquadCoefficients {
+        // No negative frequencies allowed.
+        let freq = freq.max(0.0);
+        // Don't let Q go negative, which causes an unstable filter.
+        let q = q.max(0.0);
+
+        if freq <= 0.0 || freq >= 1.0 {
+            // When the cutoff is zero, the z-transform approaches 0, if Q
+            // > 0. When both Q and cutoff are zero, the z-transform is
+            // pretty much undefined. What to do in this case? For now,
+            // just make the filter 0. When the cutoff is 1, the
+            // z-transform also approaches 0.
+            return set_coefficient(0.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        if q <= 0.0 {
+            // When Q = 0, the above formulas have problems. If we look at
+            // the z-transform, we can see that the limit as Q->0 is 1, so
+            // set the filter that way.
+            return set_coefficient(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        let w0 = PI * freq;
+        let alpha = w0.sin() / (2.0 * q);
+        let k = w0.cos();
+
+        set_coefficient(alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * k, 1.0 - alpha)
+    }
+
+    pub fn lowshelf(freq: f64, q: f64, db_gain: f64) -> BiquadCoefficients {
+        // Clip frequencies to between 0 and 1, inclusive.
+        let freq = freq.clamp(0.0, 1.0);
+
+        let a = 10_f64.powf(db_gain / 40.0);
+
+        if freq >= 1.0 {
+            // The z-transform is a constant gain.
+            return set_coefficient(a * a, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        if freq <= 0.0 {
+            // When frequency is 0, the z-transform is 1.
+            return set_coefficient(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        if q <= 0.0 {
+            // The limit as Q->0 is A.
+            return set_coefficient(a, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        let w0 = PI * freq;
+        let alpha = w0.sin() / (2.0 * q);
+        let k = w0.cos();
+        let k2 = 2.0 * a.sqrt() * alpha;
+        let a_plus_one = a + 1.0;
+        let a_minus_one = a - 1.0;
+
+        set_coefficient(
+            a * (a_plus_one - a_minus_one * k + k2),
+            2.0 * a * (a_minus_one - a_plus_one * k),
+            a * (a_plus_one - a_minus_one * k - k2),
+            a_plus_one + a_minus_one * k + k2,
+            -2.0 * (a_minus_one + a_plus_one * k),
+            a_plus_one + a_minus_one * k - k2,
+        )
+    }
+
+    pub fn highshelf(freq: f64, q: f64, db_gain: f64) -> BiquadCoefficients {
+        // Clip frequencies to between 0 and 1, inclusive.
+        let freq = freq.clamp(0.0, 1.0);
+
+        let a = 10_f64.powf(db_gain / 40.0);
+
+        if freq >= 1.0 {
+            // The z-transform is 1.
+            return set_coefficient(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        if freq <= 0.0 {
+            // When frequency = 0, the filter is just a gain, A^2.
+            // pw-eq relies on this to implement the preamp.
+            return set_coefficient(a * a, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        if q <= 0.0 {
+            // The limit as Q->0 is A.
+            return set_coefficient(a, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        let w0 = PI * freq;
+        let alpha = w0.sin() / (2.0 * q);
+        let k = w0.cos();
+        let k2 = 2.0 * a.sqrt() * alpha;
+        let a_plus_one = a + 1.0;
+        let a_minus_one = a - 1.0;
+
+        set_coefficient(
+            a * (a_plus_one + a_minus_one * k + k2),
+            -2.0 * a * (a_minus_one + a_plus_one * k),
+            a * (a_plus_one + a_minus_one * k - k2),
+            a_plus_one - a_minus_one * k + k2,
+            2.0 * (a_minus_one - a_plus_one * k),
+            a_plus_one - a_minus_one * k - k2,
+        )
+    }
+
+    pub fn peaking(freq: f64, q: f64, db_gain: f64) -> BiquadCoefficients {
+        // Clip frequencies to between 0 and 1, inclusive.
+        let freq = freq.clamp(0.0, 1.0);
+
+        // Don't let Q go negative, which causes an unstable filter.
+        let q = q.max(0.0);
+
+        let a = 10_f64.powf(db_gain / 40.0);
+
+        if freq <= 0.0 || freq >= 1.0 {
+            // When frequency is 0 or 1, the z-transform is 1.
+            return set_coefficient(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        if q <= 0.0 {
+            // When Q = 0, the above formulas have problems. If we look at
+            // the z-transform, we can see that the limit as Q->0 is A^2, so
+            // set the filter that way.
+            return set_coefficient(a * a, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        let w0 = PI * freq;
+        let alpha = w0.sin() / (2.0 * q);
+        let k = w0.cos();
 
-        // Numerator (zeros)
-        let re_num = b0 + b1 * w.cos() + b2 * (2.0 * w).cos();
-        let im_num = b1 * w.sin() + b2 * (2.0 * w).sin();
+        set_coefficient(
+            1.0 + alpha * a,
+            -2.0 * k,
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * k,
+            1.0 - alpha / a,
+        )
+    }
+
+    pub fn notch(freq: f64, q: f64) -> BiquadCoefficients {
+        // Clip frequencies to between 0 and 1, inclusive.
+        let freq = freq.clamp(0.0, 1.0);
 
-        // Denominator (poles)
-        let re_den = 1.0 + a1 * w.cos() + a2 * (2.0 * w).cos();
-        let im_den = a1 * w.sin() + a2 * (2.0 * w).sin();
+        // Don't let Q go negative, which causes an unstable filter.
+        let q = q.max(0.0);
 
-        let mag_num = (re_num * re_num + im_num * im_num).sqrt();
-        let mag_den = (re_den * re_den + im_den * im_den).sqrt();
+        if freq <= 0.0 || freq >= 1.0 {
+            // When frequency is 0 or 1, the z-transform is 1.
+            return set_coefficient(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        if q <= 0.0 {
+            // When Q = 0, the above formulas have problems. If we look at
+            // the z-transform, we can see that the limit as Q->0 is 0, so
+            // set the filter that way.
+            return set_coefficient(0.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        }
+
+        let w0 = PI * freq;
+        let alpha = w0.sin() / (2.0 * q);
+        let k = w0.cos();
+
+        set_coefficient(1.0, -2.0 * k, 1.0, 1.0 + alpha, -2.0 * k, 1.0 - alpha)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::expect;
+    use std::fmt::Write as _;
+    use strum::IntoEnumIterator;
+
+    const SAMPLE_RATE: f64 = 48000.0;
+
+    fn filter(filter_type: FilterType, frequency: f64, gain: f64, q: f64) -> Filter {
+        Filter {
+            frequency,
+            gain,
+            q,
+            filter_type,
+            muted: false,
+            locked: false,
+        }
+    }
+
+    #[test]
+    fn test_formulas_agree_within_nyquist() {
+        for filter_type in FilterType::iter() {
+            for frequency in [20.0, 100.0, 1000.0, 5000.0, 15000.0, 22000.0] {
+                for q in [0.3, 0.707, 2.0, 8.0] {
+                    for gain in [-6.0, 0.0, 6.0] {
+                        let f = filter(filter_type, frequency, gain, q);
+                        let pw = f.biquad_coeffs_with(BiquadFormula::PipeWire, SAMPLE_RATE);
+                        let rbj = f.biquad_coeffs_with(BiquadFormula::Rbj, SAMPLE_RATE);
+                        for (a, b) in [
+                            (pw.b0, rbj.b0),
+                            (pw.b1, rbj.b1),
+                            (pw.b2, rbj.b2),
+                            (pw.a1, rbj.a1),
+                            (pw.a2, rbj.a2),
+                        ] {
+                            assert!(
+                                (a - b).abs() < 1e-6,
+                                "{f:?}: pipewire {pw:?} differs from rbj {rbj:?}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pipewire_golden_values() {
+        let cases = [
+            filter(FilterType::Peaking, 1000.0, 6.0, 1.0),
+            filter(FilterType::Peaking, 100.0, -3.5, 4.0),
+            filter(FilterType::LowShelf, 105.0, -0.3, 0.667),
+            filter(FilterType::HighShelf, 10000.0, 4.0, 0.707),
+            filter(FilterType::LowPass, 8000.0, 0.0, 0.707),
+            filter(FilterType::HighPass, 30.0, 0.0, 0.707),
+            filter(FilterType::BandPass, 2000.0, 0.0, 2.0),
+            filter(FilterType::Notch, 60.0, 0.0, 10.0),
+            // Edge cases where the cookbook formulas are undefined
+            filter(FilterType::HighShelf, 0.0, -4.2, 0.0),
+            filter(FilterType::Peaking, 30000.0, 6.0, 1.0),
+            filter(FilterType::Peaking, 1000.0, 6.0, 0.0),
+            filter(FilterType::LowPass, 24000.0, 0.0, 0.707),
+            filter(FilterType::HighPass, 1000.0, 0.0, 0.0),
+            filter(FilterType::Notch, 1000.0, 0.0, 0.0),
+        ];
+
+        let mut out = String::new();
+        for f in cases {
+            let BiquadCoefficients { b0, b1, b2, a1, a2 } =
+                f.biquad_coeffs_with(BiquadFormula::PipeWire, SAMPLE_RATE);
+            writeln!(
+                out,
+                "{:?} {} Hz {} dB Q {}: {b0:.9} {b1:.9} {b2:.9} {a1:.9} {a2:.9}",
+                f.filter_type, f.frequency, f.gain, f.q
+            )
+            .unwrap();
+        }
+
+        expect![[r#"
+            Peaking 1000 Hz 6 dB Q 1: 1.043953061 -1.895320773 0.867722273 -1.895320773 0.911675394
+            Peaking 100 Hz -3.5 dB Q 4: 0.999337554 -1.995834112 0.996667624 -1.995834112 0.996005177
+            LowShelf 105 Hz -0.3 dB Q 0.667: 0.999822319 -1.979242325 0.979603827 -1.979239106 0.979429364
+            HighShelf 10000 Hz 4 dB Q 0.707: 1.304224849 -0.570225835 0.268019468 -0.174894392 0.176912829
+            LowPass 8000 Hz 0 dB Q 0.707: 0.155042127 0.310084254 0.155042127 -0.620168507 0.240337059
+            HighPass 30 Hz 0 dB Q 0.707: 0.997226655 -1.994453311 0.997226655 -1.994445562 0.994460940
+            BandPass 2000 Hz 0 dB Q 2: 0.060772493 0.000000000 -0.060772493 -1.814448237 0.878455043
+            Notch 60 Hz 0 dB Q 10: 0.999607444 -1.999153256 0.999607444 -1.999153256 0.999214947
+            HighShelf 0 Hz -4.2 dB Q 0: 0.616595030 0.000000000 0.000000000 0.000000000 0.000000000
+            Peaking 30000 Hz 6 dB Q 1: 1.000000000 0.000000000 0.000000000 0.000000000 0.000000000
+            Peaking 1000 Hz 6 dB Q 0: 1.995262265 0.000000000 0.000000000 0.000000000 0.000000000
+            LowPass 24000 Hz 0 dB Q 0.707: 1.000000000 0.000000000 0.000000000 0.000000000 0.000000000
+            HighPass 1000 Hz 0 dB Q 0: 0.000000000 0.000000000 0.000000000 0.000000000 0.000000000
+            Notch 1000 Hz 0 dB Q 0: 0.000000000 0.000000000 0.000000000 0.000000000 0.000000000
+        "#]].assert_eq(&out);
+    }
+
+    #[test]
+    fn test_magnitude_response() {
+        let f = filter(FilterType::Peaking, 1000.0, 6.0, 1.0);
+        for formula in [BiquadFormula::PipeWire, BiquadFormula::Rbj] {
+            assert!((f.magnitude_db_at_with(formula, 1000.0, SAMPLE_RATE) - 6.0).abs() < 1e-3);
+            assert!(f.magnitude_db_at_with(formula, 20.0, SAMPLE_RATE).abs() < 0.1);
+        }
+
+        let preamp = filter(FilterType::HighShelf, 0.0, -4.2, 0.0);
+        for freq in [20.0, 1000.0, 20000.0] {
+            assert!((preamp.magnitude_db_at(freq, SAMPLE_RATE) + 4.2).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_out_of_domain_parameters_do_not_panic() {
+        for formula in [BiquadFormula::PipeWire, BiquadFormula::Rbj] {
+            for filter_type in FilterType::iter() {
+                // Above nyquist at 32 kHz, and a hand-edited `Q 0`
+                for f in [
+                    filter(filter_type, 20000.0, 3.0, 1.0),
+                    filter(filter_type, 1000.0, 3.0, 0.0),
+                ] {
+                    let coeffs = f.biquad_coeffs_with(formula, 32000.0);
+                    assert!(
+                        [coeffs.b0, coeffs.b1, coeffs.b2, coeffs.a1, coeffs.a2]
+                            .iter()
+                            .all(|c| c.is_finite()),
+                        "{formula:?} {f:?}: {coeffs:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_validated_construction() {
+        assert!(Filter::new(FilterType::Peaking, 1000.0, 3.0, 1.0).is_ok());
+        assert_eq!(
+            Filter::new(FilterType::Peaking, 1000.0, 3.0, 0.0),
+            Err(FilterError::InvalidQ {
+                filter_type: FilterType::Peaking,
+                q: 0.0
+            })
+        );
+        assert_eq!(
+            Filter::new(FilterType::LowPass, -5.0, 0.0, 0.7),
+            Err(FilterError::InvalidFrequency(-5.0))
+        );
+        assert!(matches!(
+            Filter::new(FilterType::HighShelf, 8000.0, f64::NAN, 0.7),
+            Err(FilterError::NonFinite { param: "gain", .. })
+        ));
+    }
+
+    #[test]
+    fn test_clamp() {
+        let mut f = filter(FilterType::HighPass, 30000.0, 20.0, 0.01);
+        f.clamp();
+        // Gain has no effect on passes so it is left alone
+        assert_eq!(f, filter(FilterType::HighPass, 20000.0, 20.0, 0.1));
+
+        let mut f = filter(FilterType::Peaking, 5.0, -20.0, 0.01);
+        f.clamp();
+        assert_eq!(f, filter(FilterType::Peaking, 20.0, -12.0, 0.01));
+    }
+
+    #[test]
+    fn test_group_delay_matches_phase_derivative() {
+        for filter_type in FilterType::iter() {
+            let f = filter(filter_type, 1000.0, 6.0, 1.4);
+            for formula in [BiquadFormula::PipeWire, BiquadFormula::Rbj] {
+                for freq in [50.0, 700.0, 3000.0, 12000.0] {
+                    let df = 0.01;
+                    let dphase = f.phase_at_with(formula, freq + df, SAMPLE_RATE)
+                        - f.phase_at_with(formula, freq - df, SAMPLE_RATE);
+                    let dphase = (dphase + PI).rem_euclid(2.0 * PI) - PI;
+                    let expected = -dphase / (2.0 * PI * 2.0 * df);
+                    let actual = f.group_delay_at_with(formula, freq, SAMPLE_RATE);
+                    assert!(
+                        (actual - expected).abs() < 1e-6,
+                        "{filter_type:?} {formula:?} at {freq} Hz: {actual} != {expected}"
+                    );
+                }
+            }
+        }
 
-        20.0 * (mag_num / mag_den).log10()
+        // Peaking filters are minimum phase, the phase crosses zero at the center frequency
+        let f = filter(FilterType::Peaking, 1000.0, 6.0, 1.0);
+        assert!(
+            f.phase_at_with(BiquadFormula::Rbj, 1000.0, SAMPLE_RATE)
+                .abs()
+                < 1e-9
+        );
     }
 }
@@ -0,0 +1,323 @@
+//! Graphic EQ with fixed ISO band centres, where only the gain of each band can be changed.
+
+use std::{fmt, str::FromStr};
+
+use pw_util::module::FilterType;
+
+use crate::filter::Filter;
+
+/// Slider range of each band in dB
+pub const MAX_GAIN: f64 = 12.0;
+
+/// Points of a `GraphicEQ:` line are matched to band centres up to this many octaves away
+const MATCH_TOLERANCE: f64 = 1.0 / 12.0;
+
+const ISO_10: [f64; 10] = [
+    31.5, 63.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+
+const ISO_15: [f64; 15] = [
+    25.0, 40.0, 63.0, 100.0, 160.0, 250.0, 400.0, 630.0, 1000.0, 1600.0, 2500.0, 4000.0, 6300.0,
+    10000.0, 16000.0,
+];
+
+const ISO_31: [f64; 31] = [
+    20.0, 25.0, 31.5, 40.0, 50.0, 63.0, 80.0, 100.0, 125.0, 160.0, 200.0, 250.0, 315.0, 400.0,
+    500.0, 630.0, 800.0, 1000.0, 1250.0, 1600.0, 2000.0, 2500.0, 3150.0, 4000.0, 5000.0, 6300.0,
+    8000.0, 10000.0, 12500.0, 16000.0, 20000.0,
+];
+
+/// ISO 266 band layouts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Bands {
+    /// Octave bands
+    #[serde(rename = "10")]
+    Ten,
+    /// Two-thirds octave bands
+    #[serde(rename = "15")]
+    Fifteen,
+    /// Third octave bands
+    #[serde(rename = "31")]
+    ThirtyOne,
+}
+
+impl Bands {
+    pub fn centres(self) -> &'static [f64] {
+        match self {
+            Bands::Ten => &ISO_10,
+            Bands::Fifteen => &ISO_15,
+            Bands::ThirtyOne => &ISO_31,
+        }
+    }
+
+    /// Distance between adjacent centres in octaves
+    pub fn octaves(self) -> f64 {
+        match self {
+            Bands::Ten => 1.0,
+            Bands::Fifteen => 2.0 / 3.0,
+            Bands::ThirtyOne => 1.0 / 3.0,
+        }
+    }
+
+    /// The layout whose centres are the given frequencies
+    fn matching(frequencies: &[f64]) -> Option<Self> {
+        [Bands::Ten, Bands::Fifteen, Bands::ThirtyOne]
+            .into_iter()
+            .find(|bands| {
+                bands.centres().len() == frequencies.len()
+                    && bands
+                        .centres()
+                        .iter()
+                        .zip(frequencies)
+                        .all(|(centre, freq)| (freq / centre).log2().abs() < MATCH_TOLERANCE)
+            })
+    }
+}
+
+impl FromStr for Bands {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "10" => Ok(Bands::Ten),
+            "15" => Ok(Bands::Fifteen),
+            "31" => Ok(Bands::ThirtyOne),
+            _ => anyhow::bail!("graphic EQ bands must be 10, 15 or 31, got {s}"),
+        }
+    }
+}
+
+/// How the bandwidth of a band depends on its gain
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QMode {
+    /// The bandwidth is the spacing of the bands regardless of the gain
+    #[default]
+    Constant,
+    /// Small boosts and cuts are wider, the bandwidth narrows to the spacing of the bands at
+    /// full range like an analog proportional-Q graphic EQ
+    Proportional,
+}
+
+impl FromStr for QMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "constant" => Ok(QMode::Constant),
+            "proportional" => Ok(QMode::Proportional),
+            _ => anyhow::bail!("graphic EQ Q must be constant or proportional, got {s}"),
+        }
+    }
+}
+
+impl fmt::Display for QMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QMode::Constant => write!(f, "constant-Q"),
+            QMode::Proportional => write!(f, "proportional-Q"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Graphic {
+    pub bands: Bands,
+    pub q_mode: QMode,
+}
+
+impl Graphic {
+    pub fn new(bands: Bands, q_mode: QMode) -> Self {
+        Self { bands, q_mode }
+    }
+
+    /// Q of a band with the given gain
+    pub fn q_at(&self, gain: f64) -> f64 {
+        let ratio = 2f64.powf(self.bands.octaves());
+        let q = ratio.sqrt() / (ratio - 1.0);
+        match self.q_mode {
+            QMode::Constant => q,
+            QMode::Proportional => q * (0.5 + 0.5 * (gain.abs() / MAX_GAIN).min(1.0)),
+        }
+    }
+
+    /// Peaking filters at the band centres with the given gains, missing gains are 0 dB
+    pub fn filters(&self, gains: impl IntoIterator<Item = f64>) -> Vec<Filter> {
+        let mut gains = gains.into_iter();
+        self.bands
+            .centres()
+            .iter()
+            .map(|&frequency| {
+                let mut filter = Filter {
+                    frequency,
+                    gain: gains.next().unwrap_or(0.0),
+                    ..Default::default()
+                };
+                self.conform(&mut filter);
+                filter
+            })
+            .collect()
+    }
+
+    pub fn flat(&self) -> Vec<Filter> {
+        self.filters([])
+    }
+
+    /// Clamp the gain of a band to the slider range and derive its Q from it
+    pub fn conform(&self, filter: &mut Filter) {
+        filter.filter_type = FilterType::Peaking;
+        filter.gain = filter.gain.clamp(-MAX_GAIN, MAX_GAIN);
+        filter.q = self.q_at(filter.gain);
+    }
+
+    /// Points of a `GraphicEQ:` line, muted bands are exported flat
+    pub fn points(filters: &[Filter]) -> Vec<(f64, f64)> {
+        filters
+            .iter()
+            .map(|filter| {
+                let gain = if filter.muted { 0.0 } else { filter.gain };
+                (filter.frequency, gain)
+            })
+            .collect()
+    }
+
+    /// Bands for the points of a `GraphicEQ:` line. Points at the centres of one of the layouts
+    /// are used as is, any other curve is sampled at the third octave centres.
+    pub fn from_points(points: &[(f64, f64)], q_mode: QMode) -> (Self, Vec<Filter>) {
+        let frequencies = points.iter().map(|&(freq, _)| freq).collect::<Vec<_>>();
+        match Bands::matching(&frequencies) {
+            Some(bands) => {
+                let graphic = Self::new(bands, q_mode);
+                (
+                    graphic,
+                    graphic.filters(points.iter().map(|&(_, gain)| gain)),
+                )
+            }
+            None => {
+                let graphic = Self::new(Bands::ThirtyOne, q_mode);
+                let gains = ISO_31.iter().map(|&freq| interpolate(points, freq));
+                (graphic, graphic.filters(gains))
+            }
+        }
+    }
+}
+
+/// How [`Graphic::from_points`] changes a curve that doesn't fit any layout, `None` if it is
+/// represented as is
+pub fn approximation(points: &[(f64, f64)]) -> Option<String> {
+    let frequencies = points.iter().map(|&(freq, _)| freq).collect::<Vec<_>>();
+    let gains = match Bands::matching(&frequencies) {
+        Some(_) => points.iter().map(|&(_, gain)| gain).collect::<Vec<_>>(),
+        None => ISO_31
+            .iter()
+            .map(|&freq| interpolate(points, freq))
+            .collect(),
+    };
+    let clamped = gains.iter().filter(|gain| gain.abs() > MAX_GAIN).count();
+
+    let mut changes = Vec::new();
+    if Bands::matching(&frequencies).is_none() {
+        changes.push(format!(
+            "{} points were resampled onto the 31 third octave bands",
+            points.len()
+        ));
+    }
+    if clamped > 0 {
+        changes.push(format!("{clamped} gains were clamped to ±{MAX_GAIN} dB"));
+    }
+    (!changes.is_empty()).then(|| format!("GraphicEQ approximated: {}", changes.join(", ")))
+}
+
+/// Gain of a curve at a frequency, linear in log frequency and flat beyond its ends
+fn interpolate(points: &[(f64, f64)], freq: f64) -> f64 {
+    match points.iter().position(|&(f, _)| f >= freq) {
+        None => points.last().map_or(0.0, |&(_, gain)| gain),
+        Some(0) => points[0].1,
+        Some(i) => {
+            let ((f0, g0), (f1, g1)) = (points[i - 1], points[i]);
+            let t = (freq / f0).ln() / (f1 / f0).ln();
+            g0 + t * (g1 - g0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bands, Graphic, MAX_GAIN, QMode, approximation};
+
+    #[test]
+    fn test_q_modes() {
+        let constant = Graphic::new(Bands::Ten, QMode::Constant);
+        // One octave is a Q of about 1.41
+        assert!((constant.q_at(0.0) - 2f64.sqrt()).abs() < 1e-9);
+        assert_eq!(constant.q_at(0.0), constant.q_at(MAX_GAIN));
+
+        let proportional = Graphic::new(Bands::ThirtyOne, QMode::Proportional);
+        let third_octave = Graphic::new(Bands::ThirtyOne, QMode::Constant).q_at(0.0);
+        assert!((third_octave - 4.318).abs() < 1e-3, "{third_octave}");
+        assert!(proportional.q_at(3.0) < proportional.q_at(-6.0));
+        assert!((proportional.q_at(MAX_GAIN) - third_octave).abs() < 1e-9);
+        assert!((proportional.q_at(2.0 * MAX_GAIN) - third_octave).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_filters_are_clamped() {
+        let graphic = Graphic::new(Bands::Fifteen, QMode::Constant);
+        let filters = graphic.filters([3.0, -20.0]);
+        assert_eq!(filters.len(), 15);
+        assert_eq!(filters[0].frequency, 25.0);
+        assert_eq!(filters[0].gain, 3.0);
+        assert_eq!(filters[1].gain, -MAX_GAIN);
+        assert!(filters[2..].iter().all(|filter| filter.gain == 0.0));
+    }
+
+    #[test]
+    fn test_from_points() {
+        // Points at the octave centres, rounded as some tools write them
+        let points = [
+            31.0, 62.5, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, &freq)| (freq, i as f64))
+        .collect::<Vec<_>>();
+        let (graphic, filters) = Graphic::from_points(&points, QMode::Constant);
+        assert_eq!(graphic.bands, Bands::Ten);
+        assert_eq!(filters[0].frequency, 31.5);
+        assert_eq!(Graphic::points(&filters)[9], (16000.0, 9.0));
+
+        // Any other curve is sampled at the third octave centres
+        let (graphic, filters) = Graphic::from_points(
+            &[(100.0, 0.0), (1000.0, 6.0), (10000.0, -6.0)],
+            QMode::Proportional,
+        );
+        assert_eq!(graphic.bands, Bands::ThirtyOne);
+        assert_eq!(filters[0].gain, 0.0);
+        assert_eq!(filters[17].gain, 6.0);
+        assert!((filters[14].gain - 6.0 * 5f64.log10()).abs() < 1e-9);
+        assert_eq!(filters[30].gain, -6.0);
+    }
+
+    #[test]
+    fn test_approximation() {
+        let octaves = [
+            31.0, 62.5, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+        ];
+        let points = octaves.map(|freq| (freq, 1.0));
+        assert_eq!(approximation(&points), None);
+
+        let mut points = points;
+        points[3].1 = -20.0;
+        assert_eq!(
+            approximation(&points).unwrap(),
+            "GraphicEQ approximated: 1 gains were clamped to ±12 dB"
+        );
+
+        let approximated = approximation(&[(100.0, 0.0), (1000.0, 6.0)]).unwrap();
+        assert!(
+            approximated.contains("2 points were resampled"),
+            "{approximated}"
+        );
+    }
+}
@@ -1,8 +1,17 @@
 #![recursion_limit = "256"]
 
+pub mod daemon;
 pub mod filter;
+pub mod graphic;
+pub mod meter;
+pub mod optimize;
+pub mod preset;
 mod pw;
+pub mod render;
+pub mod routing;
+pub mod spectrum;
 pub mod tui;
+pub mod verify;
 
 use std::num::NonZero;
 
@@ -15,6 +24,9 @@ use tokio::process::Command;
 pub struct EqMeta {
     id: u32,
     name: String,
+    /// `node.name` of the sink, which applications play into
+    node: String,
+    description: String,
 }
 
 pub async fn list_eqs() -> anyhow::Result<Vec<EqMeta>> {
@@ -26,13 +38,18 @@ pub async fn list_eqs() -> anyhow::Result<Vec<EqMeta>> {
         .filter(|obj| matches!(obj.object_type, pw_util::PwObjectType::Node))
         .map(|obj| {
             let props = &obj.info.props;
-            let name = props
-                .get("media.name")
-                .and_then(|v| v.as_str())
-                .unwrap_or("Unknown");
+            let prop = |key| {
+                props
+                    .get(key)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("Unknown")
+                    .to_string()
+            };
             EqMeta {
                 id: obj.id,
-                name: name.to_string(),
+                name: prop("media.name"),
+                node: prop("node.name"),
+                description: prop("node.description"),
             }
         })
         .collect();
@@ -97,6 +114,20 @@ impl std::fmt::Display for FilterId {
     }
 }
 
+/// Serialized as `preamp` or the band number, like on the command line
+impl serde::Serialize for FilterId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for FilterId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 impl std::str::FromStr for FilterId {
     type Err = anyhow::Error;
 
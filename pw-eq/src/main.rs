@@ -1,18 +1,21 @@
 use anyhow::Context as _;
+use autoeq_api::Measurement;
 use clap::Parser;
 use crossterm::event::EventStream;
 use futures_util::StreamExt as _;
-use pw_eq::filter::Filter;
-use pw_eq::tui;
+use pw_eq::filter::{Filter, FilterError};
+use pw_eq::graphic::{self, Graphic};
+use pw_eq::preset::{self, Profile};
 use pw_eq::{FilterId, find_eq_node};
+use pw_eq::{daemon, tui};
+use pw_eq::{optimize, render, verify};
 use pw_util::apo::{self, FilterType};
 use pw_util::module::{self, FILTER_PREFIX};
 use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::PathBuf;
-use std::str::FromStr;
-use tabled::Table;
+use std::path::{Path, PathBuf};
+use tabled::{Table, Tabled};
 use tokio::fs;
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::layer::SubscriberExt;
@@ -39,6 +42,12 @@ struct CreateArgs {
     /// Overwrite existing EQ configuration if it exists
     #[arg(short, long)]
     force: bool,
+    /// Create a microphone EQ, exposed as a virtual source, instead of a sink
+    #[arg(long)]
+    source: bool,
+    /// `node.name` of the capture device for a microphone EQ, the default source if omitted
+    #[arg(long, requires = "source")]
+    device: Option<String>,
 }
 
 #[derive(Parser)]
@@ -79,90 +88,116 @@ struct SetArgs {
 }
 
 #[derive(Debug, Parser)]
-/// Set an EQ as the default sink
+/// Fit parametric EQ bands to a measurement and target offline and print the .apo profile
+struct FitArgs {
+    /// Frequency response to correct (AutoEQ CSV, squig.link text or REW export)
+    #[arg(short, long)]
+    measurement: PathBuf,
+    /// Frequency response to correct towards, in the same formats
+    #[arg(short, long)]
+    target: PathBuf,
+    /// Number of bands to fit
+    #[arg(short, long, default_value_t = 10)]
+    bands: usize,
+    /// Maximum boost or cut of a band in dB
+    #[arg(long, default_value_t = 12.0)]
+    max_gain: f64,
+    #[arg(long, default_value_t = 0.2)]
+    min_q: f64,
+    #[arg(long, default_value_t = 6.0)]
+    max_q: f64,
+    /// Lowest frequency in Hz that is corrected
+    #[arg(long, default_value_t = 20.0)]
+    min_freq: f64,
+    /// Highest frequency in Hz that is corrected
+    #[arg(long, default_value_t = 16000.0)]
+    max_freq: f64,
+    /// Only fit peaking filters, without a low and high shelf
+    #[arg(long)]
+    no_shelves: bool,
+    /// Write the profile to a file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+/// Apply an EQ profile to a WAV file without PipeWire
+struct RenderArgs {
+    /// Profile to apply (.apo, .txt or pipewire module .conf)
+    #[arg(short, long)]
+    profile: PathBuf,
+    /// WAV file to read, 16, 24 or 32-bit integer or 32-bit float at any sample rate
+    input: PathBuf,
+    /// WAV file to write in the format of the input
+    output: PathBuf,
+}
+
+#[derive(Parser)]
+/// Play a sweep through a running EQ and compare the measured response with its filters.
+/// The sweep is audible on the output device of the EQ.
+struct VerifyArgs {
+    /// EQ name or ID
+    profile: String,
+    /// Profile the EQ was loaded from (.apo, .txt or pipewire module .conf),
+    /// defaults to the configuration written by `create`
+    #[arg(short, long)]
+    file: Option<PathBuf>,
+    /// Duration of the sweep in seconds
+    #[arg(long, default_value_t = 5.0)]
+    duration: f64,
+    /// Level of the sweep in dBFS
+    #[arg(long, default_value_t = -20.0, allow_hyphen_values = true)]
+    level: f64,
+    /// Sample rate the sweep is played at
+    #[arg(long, default_value_t = 48000)]
+    rate: u32,
+    /// Fail if any band deviates by more than this many dB
+    #[arg(long, default_value_t = 1.0)]
+    tolerance: f64,
+}
+
+#[derive(Debug, Parser)]
+/// Route audio through an EQ. With the daemon running all streams are routed to it, otherwise
+/// it is made the default sink.
 struct UseArgs {
     /// EQ name or ID
     profile: String,
 }
 
+#[derive(Parser)]
+/// Host EQs in the background, controlled by `pw-eq tui`, `set` and `use` over a Unix socket
+/// in `$XDG_RUNTIME_DIR/pw-eq`
+struct DaemonArgs {
+    /// Name of the EQ loaded on startup
+    #[arg(short, long, default_value = tui::EQ_NAME)]
+    name: String,
+    /// Profile to load on startup (.apo, .txt or pipewire module .conf)
+    #[arg(short, long, conflicts_with = "preset")]
+    file: Option<PathBuf>,
+    /// Preset to load on startup, see `pw-eq preset list`
+    #[arg(short, long)]
+    preset: Option<String>,
+}
+
 #[derive(Debug, Default, Parser)]
 struct TuiArgs {
     /// Load a specific EQ profile on startup
     /// Currently supports .apo and .conf pipewire module files
     #[arg(short, long, conflicts_with = "preset")]
     file: Option<PathBuf>,
-    /// Apply a pre-existing preset filter configuration on startup
+    /// Apply a built-in or user preset on startup, see `pw-eq preset list`.
+    /// `flat<N>` and graphic EQs `graphic<10|15|31>[-proportional]` are also available.
     #[arg(short, long)]
-    preset: Option<Preset>,
-}
-
-#[derive(Debug, Clone)]
-enum Preset {
-    Flat { bands: usize },
-}
-
-impl FromStr for Preset {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let s = s.to_lowercase();
-        if let Some(num_str) = s.strip_prefix("flat") {
-            let bands = num_str
-                .trim_start_matches('-')
-                .parse::<usize>()
-                .with_context(|| format!("invalid flat preset number: {num_str}"))?;
-            if bands == 0 || bands > 31 {
-                return Err(anyhow::anyhow!(
-                    "flat preset bands must be between 1 and 31, got {bands}",
-                ));
-            }
-            Ok(Preset::Flat { bands })
-        } else {
-            Err(anyhow::anyhow!("unknown preset: {s}"))
-        }
-    }
-}
-
-impl Preset {
-    fn make_filters(&self) -> Vec<Filter> {
-        match self {
-            Preset::Flat { bands } => {
-                let n = *bands as f64;
-                let f_min = 50.0f64;
-                let f_max = 10000.0;
-
-                (0..*bands)
-                    .map(|i| {
-                        // Calculate frequency logarithmically
-                        let frequency = if *bands > 1 {
-                            f_min * (f_max / f_min).powf(i as f64 / (n - 1.0))
-                        } else {
-                            1000.0
-                        };
-
-                        let q = if *bands == 1 {
-                            1.0
-                        } else {
-                            // Calculate the octave distance between each band
-                            // log2(f_max / f_min) gives total octaves (~9.96 for 20-20k)
-                            let total_octaves = (f_max / f_min).log2();
-                            let bandwidth = total_octaves / (n - 1.0);
-
-                            2f64.powf(bandwidth).sqrt() / (2f64.powf(bandwidth) - 1.0)
-                        };
-
-                        Filter {
-                            frequency,
-                            q,
-                            filter_type: FilterType::Peaking,
-                            gain: 0.0,
-                            muted: false,
-                        }
-                    })
-                    .collect()
-            }
-        }
-    }
+    preset: Option<String>,
+    /// Host the EQ in the TUI even if the daemon is running
+    #[arg(long)]
+    no_daemon: bool,
+    /// Equalize a microphone instead of the output, the EQ is hosted by the TUI
+    #[arg(long)]
+    source: bool,
+    /// `node.name` of the capture device to equalize, the default source if omitted
+    #[arg(long, requires = "source")]
+    device: Option<String>,
 }
 
 #[derive(Parser)]
@@ -175,6 +210,28 @@ pub enum ConfigArgs {
     },
 }
 
+#[derive(Parser)]
+pub enum PresetArgs {
+    /// List built-in and user presets
+    #[clap(alias = "ls")]
+    List,
+    /// Save a profile as a user preset in `$XDG_DATA_HOME/pw-eq/presets`
+    Save {
+        /// Name of the preset
+        name: String,
+        /// Profile to save (.apo, .txt or pipewire module .conf)
+        file: PathBuf,
+        /// Overwrite an existing user preset
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Print a preset in .apo format
+    Show {
+        /// Name of the preset
+        name: String,
+    },
+}
+
 #[derive(Parser)]
 enum Cmd {
     /// Configuration commands
@@ -187,8 +244,16 @@ enum Cmd {
     #[clap(alias = "desc")]
     Describe(DescribeArgs),
     Set(SetArgs),
+    Use(UseArgs),
+    Fit(FitArgs),
+    Render(RenderArgs),
+    Verify(VerifyArgs),
+    /// Preset commands
+    #[clap(subcommand)]
+    Preset(PresetArgs),
     /// Interactive TUI mode
     Tui(TuiArgs),
+    Daemon(DaemonArgs),
 }
 
 #[tokio::main]
@@ -229,7 +294,13 @@ async fn main() -> anyhow::Result<()> {
             }
             Cmd::Describe(describe) => describe_eq(&describe).await?,
             Cmd::Set(set) => set_filter(set).await?,
+            Cmd::Use(args) => use_eq(args).await?,
+            Cmd::Fit(fit) => fit_eq(fit).await?,
+            Cmd::Render(render) => render_eq(render).await?,
+            Cmd::Verify(verify) => verify_eq(verify).await?,
+            Cmd::Preset(preset) => manage_presets(preset).await?,
             Cmd::Tui(tui) => run_tui(tui).await?,
+            Cmd::Daemon(daemon) => run_daemon(daemon).await?,
         },
     }
 
@@ -270,55 +341,51 @@ fn extract_pw_module_filters(conf: &module::Config) -> anyhow::Result<(f64, Vec<
     let mut fs = vec![];
     let mut preamp = 0.0;
 
-    fn mk(control: &module::Control, filter_type: FilterType) -> Filter {
-        Filter {
-            frequency: control.freq,
-            q: control.q,
-            gain: control.gain,
-            filter_type,
-            muted: false,
-        }
+    fn mk(control: &module::Control, filter_type: FilterType) -> Result<Filter, FilterError> {
+        Filter::new(filter_type, control.freq, control.gain, control.q)
     }
 
     use FilterType::*;
     for node in conf.context_modules[0].args.filter_graph.nodes.iter() {
+        let invalid = || format!("invalid band in node `{}`", node.name);
         match &node.kind {
             module::NodeKind::Peaking { control } => {
-                fs.push(mk(control, Peaking));
+                fs.push(mk(control, Peaking).with_context(invalid)?);
             }
             module::NodeKind::LowShelf { control } => {
-                fs.push(mk(control, LowShelf));
+                fs.push(mk(control, LowShelf).with_context(invalid)?);
             }
             module::NodeKind::HighShelf { control } => {
                 if control.freq == 0.0 {
                     preamp = control.gain;
                     continue;
                 }
-                fs.push(mk(control, HighShelf));
+                fs.push(mk(control, HighShelf).with_context(invalid)?);
             }
             module::NodeKind::LowPass { control } => {
-                fs.push(mk(control, LowPass));
+                fs.push(mk(control, LowPass).with_context(invalid)?);
             }
             module::NodeKind::BandPass { control } => {
-                fs.push(mk(control, BandPass));
+                fs.push(mk(control, BandPass).with_context(invalid)?);
             }
             module::NodeKind::Notch { control } => {
-                fs.push(mk(control, Notch));
+                fs.push(mk(control, Notch).with_context(invalid)?);
             }
             module::NodeKind::HighPass { control } => {
-                fs.push(mk(control, HighPass));
+                fs.push(mk(control, HighPass).with_context(invalid)?);
             }
             module::NodeKind::Raw { config: _ } => {
                 anyhow::bail!("cannot load filters from 'raw' node kind in pipewire configuration")
             }
             module::NodeKind::ParamEq { config } => {
-                fs.extend(config.filters.iter().filter_map(|f| match f.ty {
-                    HighShelf if f.control.freq == 0.0 => {
-                        preamp = f.control.gain;
-                        None
+                for (idx, f) in config.filters.iter().enumerate() {
+                    match f.ty {
+                        HighShelf if f.control.freq == 0.0 => preamp = f.control.gain,
+                        ty => fs.push(mk(&f.control, ty).with_context(|| {
+                            format!("invalid filter {} in node `{}`", idx + 1, node.name)
+                        })?),
                     }
-                    ty => Some(mk(&f.control, ty)),
-                }))
+                }
             }
         }
     }
@@ -326,33 +393,54 @@ fn extract_pw_module_filters(conf: &module::Config) -> anyhow::Result<(f64, Vec<
     Ok((preamp, fs))
 }
 
-async fn run_tui(args: TuiArgs) -> anyhow::Result<()> {
-    let (preamp, filters) = match (args.file, args.preset) {
-        (Some(_), Some(_)) => unreachable!("clap should prevent this case"),
-        (Some(path), None) => match path.extension() {
-            Some(ext) if ext.eq_ignore_ascii_case("conf") => {
-                let conf = module::Config::parse_file(&path)?;
-                if conf.context_modules.len() != 1 {
-                    anyhow::bail!(
-                        "cannot load .conf file with {} context modules, expected 1",
-                        conf.context_modules.len()
-                    );
-                }
-
-                extract_pw_module_filters(&conf)?
+/// Load the preamp and filters of a .apo, .txt or pipewire module .conf file
+async fn load_profile(path: PathBuf) -> anyhow::Result<Profile> {
+    match path.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("conf") => {
+            let conf = module::Config::parse_file(&path)?;
+            if conf.context_modules.len() != 1 {
+                anyhow::bail!(
+                    "cannot load .conf file with {} context modules, expected 1",
+                    conf.context_modules.len()
+                );
             }
-            Some(ext) if ext.eq_ignore_ascii_case("apo") || ext.eq_ignore_ascii_case("txt") => {
-                let c = apo::Config::parse_file(path).await?;
-                (c.preamp, c.filters.into_iter().map(Into::into).collect())
+
+            let (preamp, filters) = extract_pw_module_filters(&conf)?;
+            Ok(Profile {
+                preamp,
+                filters,
+                graphic: None,
+            })
+        }
+        Some(ext) if ext.eq_ignore_ascii_case("apo") || ext.eq_ignore_ascii_case("txt") => {
+            let config = apo::Config::parse_file(path).await?;
+            if let Some(approximation) = config
+                .graphic_eq
+                .as_deref()
+                .and_then(graphic::approximation)
+            {
+                eprintln!("warning: {approximation}");
             }
-            _ => anyhow::bail!("file must have an extension of .apo, .txt or .conf"),
-        },
-        (None, Some(preset)) => (0.0, preset.make_filters()),
-        _ => Default::default(),
-    };
+            Profile::from_apo(config)
+        }
+        _ => anyhow::bail!("file must have an extension of .apo, .txt or .conf"),
+    }
+}
 
-    let term = ratatui::init();
+/// Load the profile given by a file or a preset name
+async fn load_profile_arg(
+    file: Option<PathBuf>,
+    preset: Option<String>,
+) -> anyhow::Result<Option<Profile>> {
+    match (file, preset) {
+        (Some(_), Some(_)) => unreachable!("clap should prevent this case"),
+        (Some(path), None) => load_profile(path).await.map(Some),
+        (None, Some(name)) => preset::Library::user()?.load(&name).await.map(Some),
+        (None, None) => Ok(None),
+    }
+}
 
+async fn load_config() -> anyhow::Result<tui::Config> {
     let base_config = tui::Config::default();
     let user_config_path = dirs::config_dir().unwrap().join("pw-eq/pw-eq.conf");
     let config = if user_config_path.exists() {
@@ -368,24 +456,283 @@ async fn run_tui(args: TuiArgs) -> anyhow::Result<()> {
         base_config
     };
 
-    let mut app = tui::App::new(term, config, preamp, filters).await?;
+    Ok(config)
+}
+
+async fn run_tui(args: TuiArgs) -> anyhow::Result<()> {
+    // The daemon only hosts output EQs
+    let mut daemon = if args.no_daemon || args.source {
+        None
+    } else {
+        daemon::Client::connect_running().await?
+    };
+
+    let profile = match load_profile_arg(args.file, args.preset).await? {
+        Some(profile) => Some(profile),
+        // Continue editing the EQ hosted by the daemon
+        None => match &mut daemon {
+            Some(client) => client
+                .call::<daemon::EqState>(&daemon::Request::Get {
+                    name: tui::EQ_NAME.to_string(),
+                })
+                .await
+                .inspect_err(|err| tracing::info!(error = &**err, "daemon has no EQ to edit"))
+                .ok()
+                .map(|state| state.profile),
+            None => None,
+        },
+    };
+    let Profile {
+        preamp,
+        filters,
+        graphic,
+    } = profile.unwrap_or(Profile {
+        preamp: 0.0,
+        filters: vec![],
+        graphic: None,
+    });
+
+    let config = load_config().await?;
+    let term = ratatui::init();
+
+    let source = args.source.then_some(tui::Source {
+        device: args.device,
+    });
+    let mut app = tui::App::new(term, config, preamp, filters, graphic, source, daemon).await?;
     app.enter()?;
 
-    let events = EventStream::new()
-        .filter_map(|event| async { event.ok() })
-        .filter_map(|event| async { event.try_into().ok() });
+    let events = EventStream::new().filter_map(|event| async { event.ok() });
 
     app.run(events).await?;
     ratatui::restore();
     Ok(())
 }
 
-async fn create_eq(CreateArgs { name, file, force }: CreateArgs) -> anyhow::Result<()> {
+async fn run_daemon(args: DaemonArgs) -> anyhow::Result<()> {
+    let initial = load_profile_arg(args.file, args.preset)
+        .await?
+        .map(|profile| (args.name, profile));
+    let config = load_config().await?;
+    daemon::run(config, &daemon::socket_path()?, initial).await
+}
+
+async fn fit_eq(args: FitArgs) -> anyhow::Result<()> {
+    async fn load(path: &Path) -> anyhow::Result<Measurement> {
+        let text = fs::read_to_string(path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Measurement::parse(&text).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    let measurement = load(&args.measurement).await?;
+    let target = load(&args.target).await?;
+    let constraints = optimize::Constraints {
+        bands: args.bands,
+        frequency_range: args.min_freq..=args.max_freq,
+        max_gain: args.max_gain,
+        q_range: args.min_q..=args.max_q,
+        shelves: !args.no_shelves,
+        ..Default::default()
+    };
+
+    let fit =
+        tokio::task::spawn_blocking(move || optimize::fit(&measurement, &target, &constraints))
+            .await??;
+
+    let config = apo::Config {
+        preamp: fit.preamp,
+        filters: fit
+            .filters
+            .iter()
+            .enumerate()
+            .map(|(i, filter)| apo::Filter {
+                number: (i + 1) as u32,
+                enabled: true,
+                filter_type: filter.filter_type,
+                frequency: filter.frequency,
+                gain: filter.gain,
+                q: filter.q,
+            })
+            .collect(),
+        graphic_eq: None,
+    };
+
+    match args.output {
+        Some(path) => {
+            fs::write(&path, config.to_string())
+                .await
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            eprintln!(
+                "Wrote {} bands to `{}`, RMS error {:.2} dB",
+                config.filters.len(),
+                path.display(),
+                fit.error_db
+            );
+        }
+        None => {
+            print!("{config}");
+            eprintln!("RMS error {:.2} dB", fit.error_db);
+        }
+    }
+
+    Ok(())
+}
+
+async fn manage_presets(args: PresetArgs) -> anyhow::Result<()> {
+    #[derive(Tabled)]
+    struct Row {
+        name: String,
+        source: &'static str,
+        description: String,
+    }
+
+    let library = preset::Library::user()?;
+    match args {
+        PresetArgs::List => {
+            let rows = library.list().await?.into_iter().map(|info| Row {
+                name: info.name,
+                source: match info.source {
+                    preset::Source::Builtin => "built-in",
+                    preset::Source::User => "user",
+                },
+                description: info.description,
+            });
+            println!("{}", Table::new(rows));
+        }
+        PresetArgs::Save { name, file, force } => {
+            let profile = load_profile(file).await?;
+            let path = library.save(&name, &profile, force).await?;
+            eprintln!("Saved preset {name} to `{}`", path.display());
+        }
+        PresetArgs::Show { name } => print!("{}", library.load(&name).await?.to_apo()),
+    }
+
+    Ok(())
+}
+
+async fn render_eq(args: RenderArgs) -> anyhow::Result<()> {
+    let Profile {
+        preamp, filters, ..
+    } = load_profile(args.profile).await?;
+    let stats = tokio::task::spawn_blocking(move || {
+        render::render_wav(preamp, &filters, &args.input, &args.output)
+    })
+    .await??;
+
+    eprintln!("Output peak {:.1} dBFS", stats.peak_db);
+    if stats.clipped > 0 {
+        eprintln!(
+            "warning: {} samples clipped, lower the preamp to avoid distortion",
+            stats.clipped
+        );
+    }
+
+    Ok(())
+}
+
+async fn verify_eq(args: VerifyArgs) -> anyhow::Result<()> {
+    let node = find_eq_node(&args.profile).await?;
+    let name = node
+        .info
+        .props
+        .get("media.name")
+        .and_then(|name| name.as_str())
+        .with_context(|| format!("EQ '{}' has no name", args.profile))?
+        .to_string();
+
+    let file = match args.file {
+        Some(file) => file,
+        None => dirs::config_dir()
+            .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?
+            .join(format!("pipewire/pipewire.conf.d/pweq-{name}.conf")),
+    };
+    let Profile {
+        preamp, filters, ..
+    } = load_profile(file).await?;
+
+    println!("Playing a {:.1}s sweep through EQ '{name}'", args.duration);
+    let response = tokio::task::spawn_blocking(move || {
+        verify::measure(
+            &module::sink_node_name(&name),
+            &module::output_node_name(&name),
+            args.duration,
+            args.level,
+            args.rate,
+        )
+    })
+    .await??;
+
+    let report = verify::compare(&response, preamp, &filters);
+    println!("Sample rate: {} Hz", report.sample_rate);
+    println!("Level offset: {:+.2} dB", report.offset_db);
+    println!("Filters:");
+    for (i, band) in report.bands.iter().enumerate() {
+        let filter = &band.filter;
+        let deviation = match band.max_deviation_db {
+            Some(deviation) => format!("{deviation:.2} dB"),
+            None if filter.muted => "muted".to_string(),
+            None => "out of range".to_string(),
+        };
+        println!(
+            "  Filter {:>2}: Freq {:>8.2} Hz  Gain {:+5.2} dB  Q {:.2}  max deviation {deviation}",
+            i + 1,
+            filter.frequency,
+            filter.gain,
+            filter.q,
+        );
+    }
+
+    let max_deviation = report.max_deviation_db();
+    if max_deviation > args.tolerance {
+        anyhow::bail!(
+            "max deviation {max_deviation:.2} dB exceeds the tolerance of {:.2} dB",
+            args.tolerance
+        );
+    }
+
+    println!("Max deviation {max_deviation:.2} dB");
+    Ok(())
+}
+
+async fn create_eq(
+    CreateArgs {
+        name,
+        file,
+        force,
+        source,
+        device,
+    }: CreateArgs,
+) -> anyhow::Result<()> {
     // Parse the .apo file
-    let apo_config = apo::Config::parse_file(file).await?;
+    let mut apo_config = apo::Config::parse_file(file).await?;
+
+    // The bands of a graphic EQ are created as peaking filters
+    if let Some(points) = apo_config.graphic_eq.take() {
+        if let Some(approximation) = graphic::approximation(&points) {
+            eprintln!("warning: {approximation}");
+        }
+        let (_, filters) = Graphic::from_points(&points, graphic::QMode::default());
+        let offset = apo_config.filters.len();
+        apo_config
+            .filters
+            .extend(filters.iter().enumerate().map(|(i, filter)| apo::Filter {
+                number: (offset + i + 1) as u32,
+                enabled: true,
+                filter_type: filter.filter_type,
+                frequency: filter.frequency,
+                gain: filter.gain,
+                q: filter.q,
+            }));
+    }
 
     // Generate the filter-chain config
-    let config_content = pw_util::module::Config::from_apo(&name, &apo_config);
+    let mut module = module::Module::from_apo(&name, &apo_config);
+    if source {
+        module = module.into_source(device.map(module::TargetObject::NodeName));
+    }
+    let config_content = module::Config {
+        context_modules: vec![module],
+    };
     let content = pw_util::to_spa_json(&config_content);
 
     // Get the config directory path
@@ -424,6 +771,24 @@ async fn set_filter(
         anyhow::bail!("Persisting changes is not yet implemented");
     }
 
+    // Let the daemon know about the change if it hosts the EQ, or it would undo it
+    if let Some(mut client) = hosting_daemon(&profile).await? {
+        let state = client
+            .call::<daemon::EqState>(&daemon::Request::SetBand {
+                name: profile.clone(),
+                band: filter,
+                frequency,
+                gain,
+                q,
+            })
+            .await?;
+        println!(
+            "Updated filter {filter} on EQ '{}' in the daemon",
+            state.name
+        );
+        return Ok(());
+    }
+
     let node = find_eq_node(&profile).await?;
 
     pw_eq::update_filter(
@@ -446,6 +811,37 @@ async fn set_filter(
     Ok(())
 }
 
+/// Connection to the daemon if it is running and hosts the given EQ
+async fn hosting_daemon(profile: &str) -> anyhow::Result<Option<daemon::Client>> {
+    let Some(mut client) = daemon::Client::connect_running().await? else {
+        return Ok(None);
+    };
+    Ok(client.hosts(profile).await?.then_some(client))
+}
+
+async fn use_eq(UseArgs { profile }: UseArgs) -> anyhow::Result<()> {
+    if let Some(mut client) = hosting_daemon(&profile).await? {
+        let state = client
+            .call::<daemon::EqState>(&daemon::Request::Use { name: profile })
+            .await?;
+        println!("Routing all streams to EQ '{}'", state.name);
+        return Ok(());
+    }
+
+    let node = find_eq_node(&profile).await?;
+    pw_util::set_default(node.id).await?;
+    // The node of a microphone EQ is the source applications record from
+    let kind = match node.info.props.get("media.class") {
+        Some(class) if class == "Audio/Source" => "source",
+        _ => "sink",
+    };
+    println!(
+        "Set EQ '{profile}' (node {}) as the default {kind}",
+        node.id
+    );
+    Ok(())
+}
+
 async fn describe_eq(DescribeArgs { all, profile }: &DescribeArgs) -> anyhow::Result<()> {
     let node = find_eq_node(profile).await?;
     let info = node.info;
@@ -501,8 +897,17 @@ async fn describe_eq(DescribeArgs { all, profile }: &DescribeArgs) -> anyhow::Re
         }
     }
 
+    let prop = |key| {
+        info.props
+            .get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or("Unknown")
+    };
     println!("EQ Profile: {profile}");
+    println!("Name: {}", prop("media.name"));
     println!("Node ID: {}", node.id);
+    println!("Node Name: {}", prop("node.name"));
+    println!("Description: {}", prop("node.description"));
     println!("Filters:");
     for (id, filter) in filter_infos {
         let freq = filter
@@ -0,0 +1,95 @@
+use std::sync::{Arc, Mutex};
+
+/// Level floor of the meters in dBFS
+pub const FLOOR_DB: f64 = -96.0;
+
+/// Output levels shared between the PipeWire thread and the TUI
+pub type SharedMeter = Arc<Mutex<Meter>>;
+
+/// Peak and RMS levels of the samples since the last read, and a latching clip counter
+#[derive(Debug, Default)]
+pub struct Meter {
+    peak: f32,
+    sum_squares: f64,
+    count: usize,
+    /// Number of samples that exceeded full scale since the last reset
+    clips: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Levels {
+    pub peak_db: f64,
+    pub rms_db: f64,
+    pub clips: u64,
+}
+
+impl Meter {
+    /// Samples of all channels are metered together
+    pub fn push(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            let abs = sample.abs();
+            self.peak = self.peak.max(abs);
+            self.sum_squares += (sample as f64).powi(2);
+            if abs > 1.0 {
+                self.clips += 1;
+            }
+        }
+        self.count += samples.len();
+    }
+
+    /// Levels since the previous call, returns `None` if nothing was captured in between
+    pub fn take_levels(&mut self) -> Option<Levels> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let levels = Levels {
+            peak_db: to_db(self.peak as f64),
+            rms_db: to_db((self.sum_squares / self.count as f64).sqrt()),
+            clips: self.clips,
+        };
+
+        self.peak = 0.0;
+        self.sum_squares = 0.0;
+        self.count = 0;
+        Some(levels)
+    }
+
+    pub fn reset_clips(&mut self) {
+        self.clips = 0;
+    }
+}
+
+fn to_db(amplitude: f64) -> f64 {
+    (20.0 * amplitude.log10()).max(FLOOR_DB)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FLOOR_DB, Meter};
+
+    #[test]
+    fn test_levels_and_clips() {
+        let mut meter = Meter::default();
+        assert_eq!(meter.take_levels(), None);
+
+        // A full scale square wave has equal peak and RMS
+        meter.push(&[1.0, -1.0, 1.0, -1.0]);
+        let levels = meter.take_levels().unwrap();
+        assert_eq!((levels.peak_db, levels.rms_db, levels.clips), (0.0, 0.0, 0));
+
+        meter.push(&[0.5, -2.0, 0.0, 1.5]);
+        let levels = meter.take_levels().unwrap();
+        assert!((levels.peak_db - 6.0206).abs() < 1e-3, "{levels:?}");
+        assert_eq!(levels.clips, 2);
+
+        // Clips latch until reset while the levels only cover the latest samples
+        meter.push(&[0.0; 4]);
+        let levels = meter.take_levels().unwrap();
+        assert_eq!((levels.peak_db, levels.clips), (FLOOR_DB, 2));
+
+        meter.reset_clips();
+        meter.push(&[0.1]);
+        assert_eq!(meter.take_levels().unwrap().clips, 0);
+    }
+}
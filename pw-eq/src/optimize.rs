@@ -0,0 +1,571 @@
+//! Fit parametric EQ bands to a measurement and target locally, without the AutoEQ service.
+
+use std::ops::RangeInclusive;
+
+use autoeq_api::Measurement;
+use pw_util::module::{FREQUENCY_RANGE, FilterType};
+
+use crate::filter::Filter;
+
+/// Resolution of the frequency grid the error is evaluated on
+const POINTS_PER_OCTAVE: f64 = 24.0;
+/// Upper bound on the rounds of coordinate descent
+const MAX_ROUNDS: usize = 200;
+/// Initial and minimum step of each parameter: frequency and Q in octaves, gain in dB
+const INITIAL_STEPS: [f64; 3] = [0.5, 2.0, 0.5];
+const MIN_STEPS: [f64; 3] = [1.0 / 96.0, 0.05, 1.0 / 64.0];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraints {
+    /// Number of bands to fit, including the shelves
+    pub bands: usize,
+    /// Range the error is minimized over, band frequencies are kept within it
+    pub frequency_range: RangeInclusive<f64>,
+    /// Maximum boost or cut of a band in dB
+    pub max_gain: f64,
+    pub q_range: RangeInclusive<f64>,
+    /// Fit the first and last band as low and high shelves instead of peaking filters
+    pub shelves: bool,
+    /// Set the preamp to leave headroom for the largest boost
+    pub preamp: bool,
+    pub sample_rate: f64,
+}
+
+impl Default for Constraints {
+    fn default() -> Self {
+        Self {
+            bands: 10,
+            frequency_range: 20.0..=16000.0,
+            max_gain: 12.0,
+            q_range: 0.2..=6.0,
+            shelves: true,
+            preamp: true,
+            sample_rate: 48000.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Fit {
+    pub preamp: f64,
+    /// Sorted by frequency
+    pub filters: Vec<Filter>,
+    /// RMS error between the equalized measurement and the target in dB
+    pub error_db: f64,
+}
+
+/// Which parameters of a filter the optimizer may adjust
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Parameters {
+    #[default]
+    All,
+    Gain,
+    Q,
+}
+
+impl Parameters {
+    fn adjusts(self, param: usize) -> bool {
+        match self {
+            Parameters::All => true,
+            Parameters::Gain => param == GAIN,
+            Parameters::Q => param == Q,
+        }
+    }
+}
+
+/// Indices of the parameters in the step arrays
+const FREQUENCY: usize = 0;
+const GAIN: usize = 1;
+const Q: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Completed rounds of coordinate descent
+    pub round: usize,
+    pub error_db: f64,
+}
+
+/// Fit bands that bring the measurement as close to the target as the constraints allow.
+/// Both curves are compared relative to their mean level over the frequency range.
+pub fn fit(
+    measurement: &Measurement,
+    target: &Measurement,
+    constraints: &Constraints,
+) -> anyhow::Result<Fit> {
+    anyhow::ensure!(constraints.bands > 0, "at least one band is required");
+    let mut optimizer = Optimizer::new(measurement, target, constraints, Parameters::All)?;
+
+    // Place the bands one at a time on the largest remaining error, then refine them together
+    let shelves = constraints.shelves && constraints.bands >= 2;
+    if shelves {
+        optimizer.add_shelf(FilterType::LowShelf, 105.0);
+        optimizer.add_shelf(FilterType::HighShelf, 10000.0);
+    }
+    while optimizer.filters.len() < constraints.bands {
+        optimizer.add_peak();
+    }
+    optimizer.refine(0..optimizer.filters.len(), &mut |_| {});
+
+    let error_db = optimizer.error();
+    let mut filters = optimizer.filters;
+    filters.sort_by(|a, b| a.frequency.total_cmp(&b.frequency));
+
+    Ok(Fit {
+        preamp: preamp(&filters, constraints),
+        filters,
+        error_db,
+    })
+}
+
+/// Adjust the given parameters of the filters that are neither locked nor muted
+/// to bring the measurement closer to the target.
+/// Filters keep their order and type, `constraints.bands` is ignored.
+pub fn refine(
+    measurement: &Measurement,
+    target: &Measurement,
+    filters: &[Filter],
+    parameters: Parameters,
+    constraints: &Constraints,
+    mut progress: impl FnMut(Progress),
+) -> anyhow::Result<Fit> {
+    anyhow::ensure!(
+        filters.iter().any(|filter| !filter.locked && !filter.muted),
+        "no unlocked bands to optimize"
+    );
+
+    let mut optimizer = Optimizer::new(measurement, target, constraints, parameters)?;
+    for &filter in filters {
+        optimizer.push(filter);
+    }
+    optimizer.refine(0..filters.len(), &mut progress);
+
+    Ok(Fit {
+        preamp: preamp(&optimizer.filters, constraints),
+        error_db: optimizer.error(),
+        filters: optimizer.filters,
+    })
+}
+
+/// Negative of the largest boost of the filters, or 0 if the preamp is not constrained
+fn preamp(filters: &[Filter], constraints: &Constraints) -> f64 {
+    if !constraints.preamp {
+        return 0.0;
+    }
+
+    -log_grid(*FREQUENCY_RANGE.start(), *FREQUENCY_RANGE.end())
+        .into_iter()
+        .map(|freq| {
+            filters
+                .iter()
+                .map(|filter| filter.magnitude_db_at(freq, constraints.sample_rate))
+                .sum::<f64>()
+        })
+        .fold(0.0, f64::max)
+}
+
+fn log_grid(lo: f64, hi: f64) -> Vec<f64> {
+    let n = ((hi / lo).log2() * POINTS_PER_OCTAVE).ceil().max(1.0) as usize;
+    (0..=n)
+        .map(|i| lo * (hi / lo).powf(i as f64 / n as f64))
+        .collect()
+}
+
+struct Optimizer<'a> {
+    constraints: &'a Constraints,
+    parameters: Parameters,
+    frequencies: Vec<f64>,
+    /// Desired response of the EQ at each frequency
+    correction: Vec<f64>,
+    filters: Vec<Filter>,
+    /// Response of each filter at each frequency, cached so trying a change only evaluates one filter
+    responses: Vec<Vec<f64>>,
+    /// Sum of the responses
+    total: Vec<f64>,
+}
+
+impl<'a> Optimizer<'a> {
+    fn new(
+        measurement: &Measurement,
+        target: &Measurement,
+        constraints: &'a Constraints,
+        parameters: Parameters,
+    ) -> anyhow::Result<Self> {
+        let (lo, hi) = (
+            *constraints.frequency_range.start(),
+            *constraints.frequency_range.end(),
+        );
+        anyhow::ensure!(
+            lo > 0.0 && lo < hi && hi < constraints.sample_rate / 2.0,
+            "invalid frequency range {lo}..{hi} Hz"
+        );
+        anyhow::ensure!(constraints.max_gain > 0.0, "maximum gain must be positive");
+        anyhow::ensure!(
+            *constraints.q_range.start() > 0.0
+                && constraints.q_range.start() <= constraints.q_range.end(),
+            "invalid Q range {:?}",
+            constraints.q_range
+        );
+        anyhow::ensure!(!measurement.frequency.is_empty(), "measurement is empty");
+        anyhow::ensure!(!target.frequency.is_empty(), "target is empty");
+
+        let frequencies = log_grid(lo, hi);
+        let mut correction = frequencies
+            .iter()
+            .map(|&freq| target.level_at(freq) - measurement.level_at(freq))
+            .collect::<Vec<_>>();
+        let mean = correction.iter().sum::<f64>() / correction.len() as f64;
+        correction.iter_mut().for_each(|level| *level -= mean);
+
+        Ok(Self {
+            constraints,
+            parameters,
+            total: vec![0.0; frequencies.len()],
+            frequencies,
+            correction,
+            filters: vec![],
+            responses: vec![],
+        })
+    }
+
+    fn response(&self, filter: &Filter) -> Vec<f64> {
+        self.frequencies
+            .iter()
+            .map(|&freq| filter.magnitude_db_at(freq, self.constraints.sample_rate))
+            .collect()
+    }
+
+    /// Clamp a parameter of the filter into the constraints
+    fn clamp(&self, filter: &mut Filter, param: usize) {
+        match param {
+            FREQUENCY => {
+                let range = &self.constraints.frequency_range;
+                filter.frequency = filter.frequency.clamp(*range.start(), *range.end());
+            }
+            GAIN => {
+                let max_gain = self.constraints.max_gain;
+                filter.gain = filter.gain.clamp(-max_gain, max_gain);
+            }
+            _ => {
+                let (q_range, type_q_range) =
+                    (&self.constraints.q_range, filter.filter_type.q_range());
+                let q_min = q_range.start().max(*type_q_range.start());
+                let q_max = q_range.end().min(*type_q_range.end()).max(q_min);
+                filter.q = filter.q.clamp(q_min, q_max);
+            }
+        }
+    }
+
+    /// Remaining error at each frequency
+    fn residual(&self) -> impl Iterator<Item = f64> + '_ {
+        self.correction
+            .iter()
+            .zip(&self.total)
+            .map(|(correction, total)| correction - total)
+    }
+
+    fn error(&self) -> f64 {
+        rms(self.residual())
+    }
+
+    /// Error if the response of filter `idx` were replaced
+    fn error_with(&self, idx: usize, response: &[f64]) -> f64 {
+        rms(self
+            .residual()
+            .zip(&self.responses[idx])
+            .zip(response)
+            .map(|((residual, current), new)| residual + current - new))
+    }
+
+    fn push(&mut self, filter: Filter) {
+        let response = self.response(&filter);
+        self.total
+            .iter_mut()
+            .zip(&response)
+            .for_each(|(total, level)| *total += level);
+        self.filters.push(filter);
+        self.responses.push(response);
+    }
+
+    /// Add a new filter and fit it to the remaining error on its own
+    fn push_and_refine(&mut self, mut filter: Filter) {
+        for param in [FREQUENCY, GAIN, Q] {
+            self.clamp(&mut filter, param);
+        }
+        let idx = self.filters.len();
+        self.push(filter);
+        self.refine(idx..idx + 1, &mut |_| {});
+    }
+
+    fn replace(&mut self, idx: usize, filter: Filter, response: Vec<f64>) {
+        self.total
+            .iter_mut()
+            .zip(&self.responses[idx])
+            .zip(&response)
+            .for_each(|((total, current), new)| *total += new - current);
+        self.filters[idx] = filter;
+        self.responses[idx] = response;
+    }
+
+    /// Add a shelf with the average error beyond its corner frequency as its gain
+    fn add_shelf(&mut self, filter_type: FilterType, frequency: f64) {
+        let beyond = |freq: f64| match filter_type {
+            FilterType::LowShelf => freq <= frequency,
+            _ => freq >= frequency,
+        };
+        let (sum, count) = self
+            .frequencies
+            .iter()
+            .zip(self.residual())
+            .filter(|(freq, _)| beyond(**freq))
+            .fold((0.0, 0), |(sum, count), (_, residual)| {
+                (sum + residual, count + 1)
+            });
+
+        let gain = if count == 0 { 0.0 } else { sum / count as f64 };
+        self.push_and_refine(Filter {
+            frequency,
+            gain,
+            q: 0.7,
+            filter_type,
+            ..Default::default()
+        });
+    }
+
+    /// Add a peaking filter on the largest remaining error
+    fn add_peak(&mut self) {
+        let (frequency, gain) = self
+            .frequencies
+            .iter()
+            .zip(self.residual())
+            .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))
+            .map(|(&freq, residual)| (freq, residual))
+            .unwrap_or((1000.0, 0.0));
+
+        self.push_and_refine(Filter {
+            frequency,
+            gain,
+            q: 1.4,
+            filter_type: FilterType::Peaking,
+            ..Default::default()
+        });
+    }
+
+    /// Coordinate descent over the adjustable parameters of the given filters,
+    /// halving the step of a parameter whenever neither direction improves the error
+    fn refine(&mut self, bands: std::ops::Range<usize>, progress: &mut dyn FnMut(Progress)) {
+        let mut steps = vec![INITIAL_STEPS; bands.len()];
+        let mut error = self.error();
+
+        for round in 1..=MAX_ROUNDS {
+            let mut converged = true;
+
+            for (idx, steps) in bands.clone().zip(&mut steps) {
+                let filter = &self.filters[idx];
+                if filter.locked || filter.muted {
+                    continue;
+                }
+
+                for (param, step) in steps.iter_mut().enumerate() {
+                    if *step < MIN_STEPS[param]
+                        || !self.parameters.adjusts(param)
+                        || (param == GAIN && !self.filters[idx].filter_type.uses_gain())
+                    {
+                        continue;
+                    }
+                    converged = false;
+
+                    let best = [*step, -*step]
+                        .into_iter()
+                        .map(|delta| {
+                            let mut filter = self.filters[idx];
+                            match param {
+                                FREQUENCY => filter.frequency *= delta.exp2(),
+                                GAIN => filter.gain += delta,
+                                _ => filter.q *= delta.exp2(),
+                            }
+                            self.clamp(&mut filter, param);
+                            let response = self.response(&filter);
+                            (self.error_with(idx, &response), filter, response)
+                        })
+                        .min_by(|a, b| a.0.total_cmp(&b.0));
+
+                    match best {
+                        Some((candidate, filter, response)) if candidate < error => {
+                            error = candidate;
+                            self.replace(idx, filter, response);
+                        }
+                        _ => *step /= 2.0,
+                    }
+                }
+            }
+
+            progress(Progress {
+                round,
+                error_db: error,
+            });
+
+            if converged {
+                break;
+            }
+        }
+    }
+}
+
+fn rms(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| {
+        (sum + value * value, count + 1)
+    });
+    if count == 0 {
+        0.0
+    } else {
+        (sum / count as f64).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use autoeq_api::Measurement;
+    use pw_util::module::FilterType;
+
+    use super::{Constraints, Parameters, fit, log_grid, refine};
+    use crate::filter::Filter;
+
+    fn curve(level_at: impl Fn(f64) -> f64) -> Measurement {
+        let frequency = log_grid(20.0, 20000.0);
+        Measurement {
+            raw: frequency
+                .iter()
+                .map(|&freq| level_at(freq) as f32)
+                .collect(),
+            frequency: frequency.into_iter().map(|freq| freq as f32).collect(),
+        }
+    }
+
+    #[test]
+    fn test_fit_recovers_bands() {
+        // A flat target and a measurement that is the inverse of a few bands
+        let bands = [
+            Filter::new(FilterType::LowShelf, 120.0, 4.0, 0.7).unwrap(),
+            Filter::new(FilterType::Peaking, 3000.0, -6.0, 2.0).unwrap(),
+            Filter::new(FilterType::Peaking, 8000.0, 5.0, 3.0).unwrap(),
+        ];
+        let measurement = curve(|freq| {
+            75.0 - bands
+                .iter()
+                .map(|band| band.magnitude_db_at(freq, 48000.0))
+                .sum::<f64>()
+        });
+        let target = curve(|_| 0.0);
+
+        let constraints = Constraints {
+            bands: 4,
+            max_gain: 10.0,
+            ..Default::default()
+        };
+        let fit = fit(&measurement, &target, &constraints).unwrap();
+
+        assert_eq!(fit.filters.len(), 4);
+        assert!(fit.error_db < 0.5, "{fit:?}");
+        assert!(
+            fit.filters
+                .windows(2)
+                .all(|w| w[0].frequency <= w[1].frequency)
+        );
+        for filter in &fit.filters {
+            assert!(filter.gain.abs() <= constraints.max_gain, "{filter:?}");
+            assert!(constraints.frequency_range.contains(&filter.frequency));
+            assert!(constraints.q_range.contains(&filter.q), "{filter:?}");
+        }
+        // The boost of up to 5 dB needs headroom
+        assert!(fit.preamp < -3.0, "{}", fit.preamp);
+    }
+
+    #[test]
+    fn test_fit_rejects_invalid_constraints() {
+        let flat = curve(|_| 0.0);
+        for constraints in [
+            Constraints {
+                bands: 0,
+                ..Default::default()
+            },
+            Constraints {
+                frequency_range: 1000.0..=100.0,
+                ..Default::default()
+            },
+            Constraints {
+                max_gain: 0.0,
+                ..Default::default()
+            },
+        ] {
+            assert!(fit(&flat, &flat, &constraints).is_err(), "{constraints:?}");
+        }
+
+        // A flat measurement needs no correction
+        let fit = fit(&flat, &flat, &Constraints::default()).unwrap();
+        assert!(fit.error_db < 1e-6);
+        assert_eq!(fit.preamp, 0.0);
+    }
+
+    #[test]
+    fn test_refine_leaves_locked_bands() {
+        let actual = Filter::new(FilterType::Peaking, 2000.0, -5.0, 1.5).unwrap();
+        let measurement = curve(|freq| -actual.magnitude_db_at(freq, 48000.0));
+        let target = curve(|_| 0.0);
+        let constraints = Constraints::default();
+
+        let locked = Filter {
+            locked: true,
+            ..Filter::new(FilterType::Peaking, 100.0, 3.0, 1.0).unwrap()
+        };
+        let muted = Filter {
+            muted: true,
+            ..Filter::new(FilterType::Peaking, 500.0, 3.0, 1.0).unwrap()
+        };
+        let start = Filter::new(FilterType::Peaking, 1500.0, 0.0, 1.0).unwrap();
+
+        let mut rounds = 0;
+        let fit = refine(
+            &measurement,
+            &target,
+            &[locked, start, muted],
+            Parameters::All,
+            &constraints,
+            |progress| rounds = progress.round,
+        )
+        .unwrap();
+
+        assert!(rounds > 0);
+        assert_eq!(fit.filters[0], locked);
+        assert_eq!(fit.filters[2], muted);
+        // The unlocked band settles on the peak in the measurement
+        assert!((fit.filters[1].frequency - 2000.0).abs() < 200.0, "{fit:?}");
+        assert!(fit.filters[1].gain < -4.0, "{fit:?}");
+
+        // Only the gain moves when restricted to it
+        let fit = refine(
+            &measurement,
+            &target,
+            &[start],
+            Parameters::Gain,
+            &constraints,
+            |_| {},
+        )
+        .unwrap();
+        let band = fit.filters[0];
+        assert_eq!((band.frequency, band.q), (start.frequency, start.q));
+        assert!(band.gain < 0.0, "{band:?}");
+
+        let all_locked = [locked];
+        assert!(
+            refine(
+                &measurement,
+                &target,
+                &all_locked,
+                Parameters::All,
+                &constraints,
+                |_| {}
+            )
+            .is_err()
+        );
+    }
+}
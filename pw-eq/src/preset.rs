@@ -0,0 +1,443 @@
+//! Named EQ presets, either built into pw-eq or saved by the user as .apo files.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use pw_util::{apo, module::FilterType};
+
+use crate::{
+    filter::Filter,
+    graphic::{self, Graphic},
+};
+
+/// Preamp and filters of an EQ, with the layout of the bands if it is a graphic EQ
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Profile {
+    pub preamp: f64,
+    pub filters: Vec<Filter>,
+    #[serde(default)]
+    pub graphic: Option<Graphic>,
+}
+
+impl Profile {
+    pub fn from_apo(config: apo::Config) -> anyhow::Result<Self> {
+        if let Some(points) = config.graphic_eq {
+            anyhow::ensure!(
+                config.filters.is_empty(),
+                "cannot load a file with both filters and a GraphicEQ"
+            );
+            if let Some(approximation) = graphic::approximation(&points) {
+                tracing::warn!("{approximation}");
+            }
+            let (graphic, filters) = Graphic::from_points(&points, graphic::QMode::default());
+            return Ok(Self {
+                preamp: config.preamp,
+                filters,
+                graphic: Some(graphic),
+            });
+        }
+
+        let filters = config
+            .filters
+            .into_iter()
+            .map(Filter::try_from)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            preamp: config.preamp,
+            filters,
+            graphic: None,
+        })
+    }
+
+    /// Graphic EQs are written as a `GraphicEQ:` line, muted filters are turned off
+    pub fn to_apo(&self) -> apo::Config {
+        if self.graphic.is_some() {
+            return apo::Config {
+                preamp: self.preamp,
+                filters: vec![],
+                graphic_eq: Some(Graphic::points(&self.filters)),
+            };
+        }
+
+        apo::Config {
+            preamp: self.preamp,
+            filters: self
+                .filters
+                .iter()
+                .enumerate()
+                .map(|(i, filter)| apo::Filter {
+                    number: (i + 1) as u32,
+                    enabled: !filter.muted,
+                    filter_type: filter.filter_type,
+                    frequency: filter.frequency,
+                    gain: filter.gain,
+                    q: filter.q,
+                })
+                .collect(),
+            graphic_eq: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Builtin,
+    User,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresetInfo {
+    pub name: String,
+    pub description: String,
+    pub source: Source,
+}
+
+struct Builtin {
+    name: &'static str,
+    description: &'static str,
+    preamp: f64,
+    /// (type, frequency, gain, Q)
+    filters: &'static [(FilterType, f64, f64, f64)],
+}
+
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "bass-boost",
+        description: "Low shelf boosting the bass by 6 dB",
+        preamp: -6.0,
+        filters: &[(FilterType::LowShelf, 105.0, 6.0, 0.7)],
+    },
+    Builtin {
+        name: "harman-tilt",
+        description: "Bass shelf and a gentle downward tilt, loosely after the Harman target",
+        preamp: -4.0,
+        filters: &[
+            (FilterType::LowShelf, 105.0, 4.0, 0.7),
+            (FilterType::HighShelf, 3000.0, -2.0, 0.5),
+        ],
+    },
+    Builtin {
+        name: "loudness",
+        description: "Bass and treble lift for listening at low volume",
+        preamp: -6.0,
+        filters: &[
+            (FilterType::LowShelf, 80.0, 6.0, 0.7),
+            (FilterType::HighShelf, 8000.0, 3.0, 0.7),
+        ],
+    },
+    Builtin {
+        name: "vocal",
+        description: "Less bass and mud, more presence for speech and vocals",
+        preamp: -3.0,
+        filters: &[
+            (FilterType::LowShelf, 100.0, -3.0, 0.7),
+            (FilterType::Peaking, 250.0, -2.0, 1.0),
+            (FilterType::Peaking, 2500.0, 3.0, 1.0),
+        ],
+    },
+    Builtin {
+        name: "treble-cut",
+        description: "High shelf softening the treble by 4 dB",
+        preamp: 0.0,
+        filters: &[(FilterType::HighShelf, 6000.0, -4.0, 0.7)],
+    },
+];
+
+impl Builtin {
+    fn profile(&self) -> Profile {
+        Profile {
+            preamp: self.preamp,
+            filters: self
+                .filters
+                .iter()
+                .map(|&(filter_type, frequency, gain, q)| {
+                    Filter::new(filter_type, frequency, gain, q)
+                        .expect("built-in presets must be valid")
+                })
+                .collect(),
+            graphic: None,
+        }
+    }
+
+    fn info(&self) -> PresetInfo {
+        PresetInfo {
+            name: self.name.to_string(),
+            description: self.description.to_string(),
+            source: Source::Builtin,
+        }
+    }
+}
+
+/// Presets generated from their name, `flat<N>` and `graphic<10|15|31>[-proportional]`
+fn generated(name: &str) -> Option<anyhow::Result<Profile>> {
+    let name = name.to_lowercase();
+    let starts_with_digit = |s: &str| s.starts_with(|c: char| c.is_ascii_digit());
+
+    if let Some(num_str) = name.strip_prefix("flat")
+        && starts_with_digit(num_str.trim_start_matches('-'))
+    {
+        return Some(flat(num_str));
+    }
+
+    // e.g. graphic31 or graphic31-proportional
+    let layout = name
+        .strip_prefix("graphic")
+        .filter(|layout| starts_with_digit(layout))?;
+    Some(graphic_layout(layout))
+}
+
+fn graphic_layout(layout: &str) -> anyhow::Result<Profile> {
+    let (bands, q_mode) = match layout.split_once('-') {
+        Some((bands, q_mode)) => (bands, q_mode.parse()?),
+        None => (layout, graphic::QMode::default()),
+    };
+    let graphic = Graphic::new(bands.parse()?, q_mode);
+    Ok(Profile {
+        preamp: 0.0,
+        filters: graphic.flat(),
+        graphic: Some(graphic),
+    })
+}
+
+/// Peaking bands spaced logarithmically from 50 Hz to 10 kHz
+fn flat(num_str: &str) -> anyhow::Result<Profile> {
+    let bands = num_str
+        .trim_start_matches('-')
+        .parse::<usize>()
+        .with_context(|| format!("invalid flat preset number: {num_str}"))?;
+    anyhow::ensure!(
+        bands > 0 && bands <= 31,
+        "flat preset bands must be between 1 and 31, got {bands}",
+    );
+
+    let n = bands as f64;
+    let f_min = 50.0f64;
+    let f_max = 10000.0;
+
+    let filters = (0..bands)
+        .map(|i| {
+            // Calculate frequency logarithmically
+            let frequency = if bands > 1 {
+                f_min * (f_max / f_min).powf(i as f64 / (n - 1.0))
+            } else {
+                1000.0
+            };
+
+            let q = if bands == 1 {
+                1.0
+            } else {
+                // Calculate the octave distance between each band
+                // log2(f_max / f_min) gives total octaves (~9.96 for 20-20k)
+                let total_octaves = (f_max / f_min).log2();
+                let bandwidth = total_octaves / (n - 1.0);
+
+                2f64.powf(bandwidth).sqrt() / (2f64.powf(bandwidth) - 1.0)
+            };
+
+            Filter {
+                frequency,
+                q,
+                filter_type: FilterType::Peaking,
+                gain: 0.0,
+                muted: false,
+                locked: false,
+            }
+        })
+        .collect();
+
+    Ok(Profile {
+        preamp: 0.0,
+        filters,
+        graphic: None,
+    })
+}
+
+/// User presets are files named `<name>.apo` in a directory and take precedence over
+/// built-in presets of the same name
+pub struct Library {
+    dir: PathBuf,
+}
+
+impl Library {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The user presets in `$XDG_DATA_HOME/pw-eq/presets`
+    pub fn user() -> anyhow::Result<Self> {
+        let dir = dirs::data_dir()
+            .context("could not find the data directory")?
+            .join("pw-eq/presets");
+        Ok(Self::new(dir))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, name: &str) -> anyhow::Result<PathBuf> {
+        anyhow::ensure!(
+            !name.is_empty()
+                && !name.starts_with('.')
+                && !name.contains(['/', '\\'])
+                && generated(name).is_none(),
+            "invalid preset name: {name}"
+        );
+        Ok(self.dir.join(format!("{name}.apo")))
+    }
+
+    /// Built-in and user presets sorted by name, without the generated ones
+    pub async fn list(&self) -> anyhow::Result<Vec<PresetInfo>> {
+        let mut presets = Vec::new();
+
+        match tokio::fs::read_dir(&self.dir).await {
+            Ok(mut entries) => {
+                while let Some(entry) = entries.next_entry().await? {
+                    let path = entry.path();
+                    if path.extension().is_none_or(|ext| ext != "apo") {
+                        continue;
+                    }
+                    if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                        presets.push(PresetInfo {
+                            name: name.to_string(),
+                            description: path.display().to_string(),
+                            source: Source::User,
+                        });
+                    }
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", self.dir.display()));
+            }
+        }
+
+        for builtin in BUILTINS {
+            if !presets.iter().any(|preset| preset.name == builtin.name) {
+                presets.push(builtin.info());
+            }
+        }
+
+        presets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(presets)
+    }
+
+    pub async fn load(&self, name: &str) -> anyhow::Result<Profile> {
+        if let Some(profile) = generated(name) {
+            return profile;
+        }
+
+        let path = self.path(name)?;
+        if path.exists() {
+            let config = apo::Config::parse_file(&path)
+                .await
+                .with_context(|| format!("failed to load preset {}", path.display()))?;
+            return Profile::from_apo(config);
+        }
+
+        BUILTINS
+            .iter()
+            .find(|builtin| builtin.name.eq_ignore_ascii_case(name))
+            .map(Builtin::profile)
+            .with_context(|| format!("unknown preset: {name}"))
+    }
+
+    /// Save a user preset, returning the path it was written to
+    pub async fn save(
+        &self,
+        name: &str,
+        profile: &Profile,
+        force: bool,
+    ) -> anyhow::Result<PathBuf> {
+        let path = self.path(name)?;
+        anyhow::ensure!(
+            force || !path.exists(),
+            "preset {name} already exists at {}",
+            path.display()
+        );
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        tokio::fs::write(&path, profile.to_apo().to_string())
+            .await
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pw_util::module::FilterType;
+
+    use super::{BUILTINS, Library, Profile, Source};
+    use crate::filter::Filter;
+    use crate::graphic::Bands;
+
+    fn library(test: &str) -> Library {
+        Library::new(
+            std::env::temp_dir().join(format!("pw-eq-presets-{test}-{}", std::process::id())),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_builtin_and_generated_presets() {
+        let library = library("builtin");
+        for builtin in BUILTINS {
+            let profile = library.load(builtin.name).await.unwrap();
+            assert!(!profile.filters.is_empty());
+        }
+
+        assert_eq!(library.load("flat10").await.unwrap().filters.len(), 10);
+        assert_eq!(library.load("FLAT-5").await.unwrap().filters.len(), 5);
+        assert_eq!(library.load("Loudness").await.unwrap().filters.len(), 2);
+        let profile = library.load("graphic15-proportional").await.unwrap();
+        assert_eq!(profile.graphic.unwrap().bands, Bands::Fifteen);
+        assert!(library.load("flat40").await.is_err());
+        assert!(library.load("nope").await.is_err());
+
+        // A missing user directory lists the built-in presets
+        let presets = library.list().await.unwrap();
+        assert_eq!(presets.len(), BUILTINS.len());
+        assert!(
+            presets
+                .iter()
+                .all(|preset| preset.source == Source::Builtin)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_user_presets() {
+        let library = library("user");
+        let profile = Profile {
+            preamp: -2.0,
+            filters: vec![
+                Filter::new(FilterType::Peaking, 1000.0, 2.0, 1.5).unwrap(),
+                Filter {
+                    muted: true,
+                    ..Filter::new(FilterType::HighShelf, 8000.0, -1.0, 0.7).unwrap()
+                },
+            ],
+            graphic: None,
+        };
+
+        library.save("mine", &profile, false).await.unwrap();
+        assert_eq!(library.load("mine").await.unwrap(), profile);
+        assert!(library.save("mine", &profile, false).await.is_err());
+
+        // User presets take precedence over built-in ones
+        library.save("loudness", &profile, false).await.unwrap();
+        assert_eq!(library.load("loudness").await.unwrap(), profile);
+        let presets = library.list().await.unwrap();
+        assert_eq!(presets.len(), BUILTINS.len() + 1);
+        let loudness = presets.iter().find(|p| p.name == "loudness").unwrap();
+        assert_eq!(loudness.source, Source::User);
+
+        for name in ["", "../escape", ".hidden", "flat3"] {
+            assert!(library.save(name, &profile, true).await.is_err(), "{name}");
+        }
+
+        std::fs::remove_dir_all(library.dir()).unwrap();
+    }
+}
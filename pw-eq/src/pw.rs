@@ -1,23 +1,112 @@
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
 use std::rc::Rc;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+use anyhow::Context as _;
 use dashmap::DashMap;
-use pipewire::metadata::Metadata;
+use pipewire::core::CoreRc;
+use pipewire::keys;
+use pipewire::metadata::{Metadata, MetadataListener};
+use pipewire::node::{Node, NodeListener};
+use pipewire::properties::properties;
+use pipewire::registry::{GlobalObject, RegistryRc};
+use pipewire::spa::param::ParamType;
+use pipewire::spa::param::audio::{AudioFormat, AudioInfoRaw};
+use pipewire::spa::param::format::{MediaSubtype, MediaType};
+use pipewire::spa::param::format_utils;
+use pipewire::spa::pod::serialize::PodSerializer;
+use pipewire::spa::pod::{self, Pod};
+use pipewire::spa::utils::dict::DictRef;
+use pipewire::spa::utils::{Direction, SpaTypes};
+use pipewire::stream::{StreamFlags, StreamListener, StreamRc};
 use pipewire::types::ObjectType;
 use pipewire::{self, context::ContextRc, main_loop::MainLoopRc};
-use pw_util::module::ModuleArgs;
+use pw_util::module::{self, MANAGED_PROP, ModuleArgs};
 use pw_util::{NodeInfo, api};
 use tokio::sync::mpsc;
 
+use crate::meter::SharedMeter;
+use crate::routing::{self, StreamProps};
+use crate::spectrum::SharedCapture;
 use crate::tui::Notif;
 
 #[derive(Debug, Clone)]
 pub enum Message {
     Terminate,
     SetActiveNode(NodeInfo),
-    LoadModule { name: String, args: Box<ModuleArgs> },
+    /// Route capture streams to the source node of a microphone EQ
+    SetActiveSource(NodeInfo),
+    /// Sink or source node of the EQ with the given name, for streams routed to that EQ
+    SetEqNode {
+        name: String,
+        node: NodeInfo,
+        /// Whether the EQ is a microphone EQ
+        source: bool,
+    },
+    /// Unload the modules of the EQ with the given name, its streams move to the active nodes
+    RemoveEq {
+        name: String,
+    },
+    LoadModule {
+        name: String,
+        args: Box<ModuleArgs>,
+    },
+    /// Capture audio from the node with the given name for the spectrum analyzer,
+    /// replacing any existing capture stream.
+    StartCapture {
+        target: String,
+        /// Capture the monitor of a sink rather than the output of a stream
+        capture_sink: bool,
+    },
+    StopCapture,
+    /// Meter the output of the node with the given name
+    StartMeter {
+        target: String,
+    },
+    /// Route a stream to an EQ or back to where it would play without the EQ, regardless of
+    /// the routing rules
+    SetStreamRouted {
+        node_id: u32,
+        routed: bool,
+        /// EQ to route the stream to, the active node if `None`
+        eq: Option<String>,
+    },
+    /// Play the output of the modules of the EQ with the given name into the sink with the
+    /// given serial
+    SetOutputTarget {
+        name: String,
+        object_serial: i64,
+    },
+}
+
+/// The sink the EQ plays into
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputDevice {
+    pub node_id: u32,
+    pub node_name: String,
+    pub object_serial: i64,
+    /// `device.description` of the device of the sink, or the `node.description` of the sink
+    pub description: Option<String>,
+    pub bluetooth_address: Option<String>,
+}
+
+/// Properties of an `Audio/Device`
+#[derive(Default)]
+struct DeviceProps {
+    description: Option<String>,
+    bluetooth_address: Option<String>,
+}
+
+/// Properties of an `Audio/Sink` node
+struct SinkProps {
+    node_name: String,
+    object_serial: i64,
+    description: Option<String>,
+    bluetooth_address: Option<String>,
+    device_id: Option<u32>,
 }
 
 #[derive(Clone)]
@@ -27,50 +116,329 @@ struct AudioStreamInfo {
     original_target_object: Option<String>,
 }
 
+/// Stream of an application that may be routed to the EQ
+struct AppStream {
+    info: AudioStreamInfo,
+    props: StreamProps,
+    /// Decided by the routing rules until it is toggled
+    routed: bool,
+    /// EQ the stream is routed to, the active node if `None` or the EQ is not loaded
+    eq: Option<String>,
+    direction: routing::Direction,
+}
+
+/// Node of an EQ that streams are routed to
+struct EqTarget {
+    node: NodeInfo,
+    /// Whether the EQ is a microphone EQ, which capture streams are routed to
+    source: bool,
+}
+
+/// Sources of the graph sample rate.
+#[derive(Default)]
+struct SampleRates {
+    /// `clock.rate` from the settings metadata
+    clock_rate: Option<u32>,
+    /// `clock.force-rate` from the settings metadata
+    force_rate: Option<u32>,
+    /// Rates advertised by the managed EQ nodes
+    node_rates: HashMap<u32, NodeRate>,
+    /// The last rate sent to the TUI
+    notified: Option<u32>,
+}
+
+#[derive(Default)]
+struct NodeRate {
+    /// Rate of the negotiated `Format` param
+    format: Option<u32>,
+    /// Preferred rate of the `EnumFormat` param
+    enum_format: Option<u32>,
+}
+
+impl SampleRates {
+    /// The negotiated rate of the active node takes precedence, followed by the settings metadata.
+    /// The node's preferred rate is only used if nothing else is known.
+    fn effective(&self, active_node_id: Option<u32>) -> Option<u32> {
+        let node_rate = active_node_id.and_then(|id| self.node_rates.get(&id));
+        node_rate
+            .and_then(|rate| rate.format)
+            .or(self.force_rate)
+            .or(self.clock_rate)
+            .or_else(|| node_rate.and_then(|rate| rate.enum_format))
+    }
+}
+
 #[derive(Clone)]
 struct State {
+    notifs: mpsc::Sender<Notif>,
     default_audio_sink: Option<NodeInfo>,
     metadata: Rc<Mutex<Option<Metadata>>>,
+    /// Follows the default sink in the default metadata
+    metadata_listener: Rc<Mutex<Option<MetadataListener>>>,
+    settings_metadata: Rc<Mutex<Option<(Metadata, MetadataListener)>>>,
     active_node: Rc<Mutex<Option<NodeInfo>>>,
-    audio_stream_nodes: Rc<DashMap<u32, AudioStreamInfo>>,
+    /// Source node of the microphone EQ capture streams are routed to
+    active_source: Rc<Mutex<Option<NodeInfo>>>,
+    /// Nodes of the EQs by name
+    eq_targets: Rc<DashMap<String, EqTarget>>,
+    audio_stream_nodes: Rc<DashMap<u32, AppStream>>,
+    routing: Rc<routing::Config>,
+    eq_nodes: Rc<DashMap<u32, (Node, NodeListener)>>,
+    /// Stream nodes playing the output of an EQ
+    eq_output_nodes: Rc<DashMap<u32, AudioStreamInfo>>,
+    sample_rates: Rc<Mutex<SampleRates>>,
+    devices: Rc<DashMap<u32, DeviceProps>>,
+    sinks: Rc<DashMap<u32, SinkProps>>,
+    /// `node.name` of the default sink
+    default_sink_name: Rc<Mutex<Option<String>>>,
+    /// The last output device sent to the TUI
+    output_device: Rc<Mutex<Option<OutputDevice>>>,
 }
 
 impl State {
-    fn new(default_audio_sink: Option<NodeInfo>) -> Self {
+    fn new(
+        notifs: mpsc::Sender<Notif>,
+        default_audio_sink: Option<NodeInfo>,
+        routing: routing::Config,
+    ) -> Self {
         Self {
+            notifs,
             default_audio_sink,
             metadata: Rc::new(Mutex::new(None)),
+            metadata_listener: Rc::new(Mutex::new(None)),
+            settings_metadata: Rc::new(Mutex::new(None)),
             active_node: Rc::new(Mutex::new(None)),
+            active_source: Rc::new(Mutex::new(None)),
+            eq_targets: Rc::new(DashMap::new()),
             audio_stream_nodes: Rc::new(DashMap::new()),
+            routing: Rc::new(routing),
+            eq_nodes: Rc::new(DashMap::new()),
+            eq_output_nodes: Rc::new(DashMap::new()),
+            sample_rates: Rc::new(Mutex::new(SampleRates::default())),
+            devices: Rc::new(DashMap::new()),
+            sinks: Rc::new(DashMap::new()),
+            default_sink_name: Rc::new(Mutex::new(None)),
+            output_device: Rc::new(Mutex::new(None)),
         }
     }
 
-    fn route_stream_to_active_node(&self, stream_node: &AudioStreamInfo) {
-        let metadata_opt = self.metadata.lock().unwrap();
-        let active_node_opt = self.active_node.lock().unwrap();
+    /// Notify the TUI if the default sink is now another device. The sink may only appear in the
+    /// registry after it was made the default, in which case this is called again once it does.
+    fn notify_output_device(&self) {
+        let default_sink_name = self.default_sink_name.lock().unwrap();
+        let Some(name) = default_sink_name.as_deref() else {
+            return;
+        };
+
+        let Some(device) = self.sinks.iter().find_map(|entry| {
+            let sink = entry.value();
+            let device = sink.device_id.and_then(|id| self.devices.get(&id));
+            (sink.node_name == name).then(|| OutputDevice {
+                node_id: *entry.key(),
+                node_name: sink.node_name.clone(),
+                object_serial: sink.object_serial,
+                description: device
+                    .as_ref()
+                    .and_then(|device| device.description.clone())
+                    .or_else(|| sink.description.clone()),
+                bluetooth_address: sink.bluetooth_address.clone().or_else(|| {
+                    device
+                        .as_ref()
+                        .and_then(|device| device.bluetooth_address.clone())
+                }),
+            })
+        }) else {
+            return;
+        };
 
-        if let (Some(metadata), Some(node)) = (metadata_opt.as_ref(), active_node_opt.as_ref()) {
-            do_route_stream(metadata, stream_node, &node.object_serial.to_string());
+        let mut output_device = self.output_device.lock().unwrap();
+        if output_device.as_ref() == Some(&device) {
+            return;
         }
+
+        tracing::info!(?device, "Output device changed");
+        *output_device = Some(device.clone());
+        let _ = self
+            .notifs
+            .blocking_send(Notif::OutputDeviceChanged(device));
     }
 
-    fn route_all_streams_to_active_node(&self) {
+    /// Modules are loaded per band count, with the band count appended to the name of the EQ
+    fn route_eq_output(&self, name: &str, object_serial: i64) {
         let metadata_opt = self.metadata.lock().unwrap();
-        let active_node_opt = self.active_node.lock().unwrap();
+        let Some(metadata) = metadata_opt.as_ref() else {
+            return;
+        };
 
-        if let (Some(metadata), Some(node)) = (metadata_opt.as_ref(), active_node_opt.as_ref()) {
-            for entry in self.audio_stream_nodes.iter() {
-                do_route_stream(metadata, entry.value(), &node.object_serial.to_string());
+        let prefix = module::output_node_name(&format!("{name}-"));
+        for entry in self.eq_output_nodes.iter() {
+            if is_band_count_suffixed(&entry.value().node_name, &prefix) {
+                do_route_stream(metadata, entry.value(), &object_serial.to_string());
             }
         }
     }
 
+    /// Notify the TUI if the effective graph sample rate changed
+    fn notify_sample_rate(&self) {
+        let active_node_id = self
+            .active_node
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|node| node.node_id);
+
+        let mut sample_rates = self.sample_rates.lock().unwrap();
+        let Some(rate) = sample_rates.effective(active_node_id) else {
+            return;
+        };
+
+        if sample_rates.notified == Some(rate) {
+            return;
+        }
+
+        sample_rates.notified = Some(rate);
+        tracing::info!(rate, "Graph sample rate changed");
+        let _ = self.notifs.blocking_send(Notif::SampleRateChanged(rate));
+    }
+
+    /// Bind to a managed EQ node and follow the sample rate of its format
+    fn watch_eq_node(&self, registry: &RegistryRc, obj: &GlobalObject<&DictRef>) {
+        let node = match registry.bind::<Node, _>(obj) {
+            Ok(node) => node,
+            Err(err) => {
+                tracing::error!(?err, "Failed to bind to EQ node");
+                return;
+            }
+        };
+
+        let node_id = obj.id;
+        let listener = node
+            .add_listener_local()
+            .param({
+                let st = self.clone();
+                move |_seq, id, index, _next, param| {
+                    let rate = param.and_then(parse_audio_rate);
+                    {
+                        let mut sample_rates = st.sample_rates.lock().unwrap();
+                        let node_rate = sample_rates.node_rates.entry(node_id).or_default();
+                        match id {
+                            ParamType::Format => node_rate.format = rate,
+                            // The first enumerated format is the preferred one
+                            ParamType::EnumFormat if index == 0 => node_rate.enum_format = rate,
+                            _ => return,
+                        }
+                    }
+
+                    st.notify_sample_rate();
+                }
+            })
+            .register();
+
+        node.subscribe_params(&[ParamType::EnumFormat, ParamType::Format]);
+
+        tracing::info!(id = node_id, "Watching EQ node format");
+        self.eq_nodes.insert(node_id, (node, listener));
+    }
+
+    /// Serial of the EQ node the stream should be linked to, `None` if it bypasses the EQs. A
+    /// client of the daemon has no EQ nodes and never routes streams itself.
+    fn stream_target(&self, stream: &AppStream) -> Option<i64> {
+        if !stream.routed {
+            return None;
+        }
+
+        let source = stream.direction == routing::Direction::Capture;
+        let active = if source {
+            &self.active_source
+        } else {
+            &self.active_node
+        };
+        stream
+            .eq
+            .as_ref()
+            .and_then(|name| self.eq_targets.get(name))
+            .filter(|target| target.source == source)
+            .map(|target| target.node.object_serial)
+            .or_else(|| {
+                active
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map(|node| node.object_serial)
+            })
+    }
+
+    fn route_stream(&self, stream: &AppStream) {
+        let metadata_opt = self.metadata.lock().unwrap();
+        if let (Some(metadata), Some(target)) = (metadata_opt.as_ref(), self.stream_target(stream))
+        {
+            do_route_stream(metadata, &stream.info, &target.to_string());
+        }
+    }
+
+    fn route_streams(&self, filter: impl Fn(&AppStream) -> bool) {
+        for entry in self.audio_stream_nodes.iter().filter(|entry| filter(entry)) {
+            self.route_stream(&entry);
+        }
+    }
+
+    fn set_stream_routed(&self, node_id: u32, routed: bool, eq: Option<String>) {
+        let Some(mut stream) = self.audio_stream_nodes.get_mut(&node_id) else {
+            return;
+        };
+
+        if stream.routed == routed && (!routed || stream.eq == eq) {
+            return;
+        }
+
+        let had_target = self.stream_target(&stream).is_some();
+        stream.routed = routed;
+        if routed {
+            stream.eq = eq;
+        }
+        tracing::info!(node_id, stream = %stream.info.node_name, routed, eq = ?stream.eq, "Toggled stream routing");
+        if routed {
+            self.route_stream(&stream);
+        } else if had_target && let Some(metadata) = self.metadata.lock().unwrap().as_ref() {
+            // Without a target in the metadata the stream plays where it would without the EQ
+            metadata.set_property(node_id, "target.object", None, None);
+        }
+        drop(stream);
+        self.notify_streams();
+    }
+
+    fn notify_streams(&self) {
+        let mut streams = self
+            .audio_stream_nodes
+            .iter()
+            .map(|entry| routing::Stream {
+                node_id: *entry.key(),
+                direction: entry.direction,
+                props: entry.props.clone(),
+                routed: entry.routed,
+                eq: entry.eq.clone(),
+            })
+            .collect::<Vec<_>>();
+        streams.sort_by_key(|stream| stream.node_id);
+        let _ = self.notifs.blocking_send(Notif::StreamsChanged(streams));
+    }
+
     fn cleanup(&self) {
         if let Some(metadata) = self.metadata.lock().unwrap().as_ref() {
-            for entry in self.audio_stream_nodes.iter() {
-                let stream_node = entry.value();
+            let routed = self
+                .audio_stream_nodes
+                .iter()
+                .filter(|entry| self.stream_target(entry).is_some());
+            for entry in routed {
+                let stream_node = &entry.info;
                 let target = if stream_node.original_target_object.is_some() {
                     stream_node.original_target_object.clone()
+                } else if entry.direction == routing::Direction::Capture {
+                    // Without a target capture streams follow the default source again
+                    metadata.set_property(stream_node.node_id, "target.object", None, None);
+                    continue;
+                } else if let Some(device) = self.output_device.lock().unwrap().as_ref() {
+                    Some(device.object_serial.to_string())
                 } else {
                     self.default_audio_sink
                         .as_ref()
@@ -85,6 +453,332 @@ impl State {
     }
 }
 
+fn parse_audio_rate(param: &Pod) -> Option<u32> {
+    let (media_type, media_subtype) = format_utils::parse_format(param).ok()?;
+    if media_type != MediaType::Audio || media_subtype != MediaSubtype::Raw {
+        return None;
+    }
+
+    let mut info = AudioInfoRaw::new();
+    info.parse(param).ok()?;
+    Some(info.rate()).filter(|&rate| rate > 0)
+}
+
+/// Stream feeding the spectrum analyzer or the level meter, disconnected on drop
+struct CaptureStream {
+    stream: StreamRc,
+    _listener: StreamListener<u32>,
+}
+
+impl Drop for CaptureStream {
+    fn drop(&mut self) {
+        let _ = self.stream.disconnect();
+    }
+}
+
+/// Consumer of the samples of a capture stream
+trait CaptureSink: Clone + 'static {
+    fn set_rate(&self, _rate: u32) {}
+
+    /// Interleaved samples of all channels
+    fn push(&self, samples: &[f32], channels: usize);
+}
+
+impl CaptureSink for SharedCapture {
+    fn set_rate(&self, rate: u32) {
+        let mut capture = self.lock().unwrap();
+        capture.sample_rate = rate;
+        capture.clear();
+    }
+
+    fn push(&self, samples: &[f32], channels: usize) {
+        self.lock().unwrap().push_interleaved(samples, channels);
+    }
+}
+
+impl CaptureSink for SharedMeter {
+    fn push(&self, samples: &[f32], _channels: usize) {
+        self.lock().unwrap().push(samples);
+    }
+}
+
+fn connect_capture(
+    core: &CoreRc,
+    name: &str,
+    sink: impl CaptureSink,
+    target: &str,
+    capture_sink: bool,
+) -> anyhow::Result<CaptureStream> {
+    let props = properties! {
+        *keys::MEDIA_TYPE => "Audio",
+        *keys::MEDIA_CATEGORY => "Monitor",
+        *keys::NODE_NAME => name,
+        *keys::TARGET_OBJECT => target,
+        *keys::STREAM_CAPTURE_SINK => if capture_sink { "true" } else { "false" },
+        // Don't keep the graph running just for monitoring
+        *keys::NODE_PASSIVE => "true",
+    };
+
+    let stream = StreamRc::new(core.clone(), name, props)?;
+
+    // The user data is the number of channels of the negotiated format
+    let listener = stream
+        .add_local_listener_with_user_data(0u32)
+        .param_changed({
+            let sink = sink.clone();
+            move |_stream, channels, id, param| {
+                let Some(param) = param else {
+                    return;
+                };
+
+                if id != ParamType::Format.as_raw() {
+                    return;
+                }
+
+                let mut info = AudioInfoRaw::new();
+                if info.parse(param).is_err() {
+                    return;
+                }
+
+                *channels = info.channels();
+                sink.set_rate(info.rate());
+                tracing::info!(
+                    rate = info.rate(),
+                    channels = info.channels(),
+                    "Capture format"
+                );
+            }
+        })
+        .process({
+            move |stream, channels| {
+                let Some(mut buffer) = stream.dequeue_buffer() else {
+                    return;
+                };
+
+                let Some(data) = buffer.datas_mut().first_mut() else {
+                    return;
+                };
+
+                let offset = data.chunk().offset() as usize;
+                let size = data.chunk().size() as usize;
+                let Some(bytes) = data.data() else {
+                    return;
+                };
+
+                let Some(bytes) = bytes.get(offset..offset + size) else {
+                    return;
+                };
+
+                let samples = bytes
+                    .chunks_exact(size_of::<f32>())
+                    .map(|sample| f32::from_le_bytes(sample.try_into().unwrap()))
+                    .collect::<Vec<_>>();
+
+                sink.push(&samples, *channels as usize);
+            }
+        })
+        .register()?;
+
+    // Accept any rate and channel count, but always as interleaved floats
+    let mut audio_info = AudioInfoRaw::new();
+    audio_info.set_format(AudioFormat::F32LE);
+    let bytes = f32_format(audio_info)?;
+    let format = Pod::from_bytes(&bytes).context("invalid capture format")?;
+
+    stream.connect(
+        Direction::Input,
+        None,
+        StreamFlags::AUTOCONNECT | StreamFlags::MAP_BUFFERS | StreamFlags::DONT_RECONNECT,
+        &mut [format],
+    )?;
+
+    tracing::info!(name, %target, capture_sink, "Connected capture stream");
+    Ok(CaptureStream {
+        stream,
+        _listener: listener,
+    })
+}
+
+/// Stream playing a signal once, disconnected on drop
+struct PlaybackStream {
+    stream: StreamRc,
+    _listener: StreamListener<usize>,
+}
+
+impl Drop for PlaybackStream {
+    fn drop(&mut self) {
+        let _ = self.stream.disconnect();
+    }
+}
+
+/// Serialize an `EnumFormat` param for interleaved floats
+fn f32_format(audio_info: AudioInfoRaw) -> anyhow::Result<Vec<u8>> {
+    let format = pod::Value::Object(pod::Object {
+        type_: SpaTypes::ObjectParamFormat.as_raw(),
+        id: ParamType::EnumFormat.as_raw(),
+        properties: audio_info.into(),
+    });
+    let (bytes, _) = PodSerializer::serialize(std::io::Cursor::new(Vec::new()), &format)
+        .map_err(|err| anyhow::anyhow!("failed to serialize stream format: {err:?}"))?;
+    Ok(bytes.into_inner())
+}
+
+/// Play a mono signal into the node with the given name, setting `done` once all of it was queued
+fn connect_playback(
+    core: &CoreRc,
+    name: &str,
+    target: &str,
+    signal: Vec<f32>,
+    rate: u32,
+    done: Rc<Cell<bool>>,
+) -> anyhow::Result<PlaybackStream> {
+    let props = properties! {
+        *keys::MEDIA_TYPE => "Audio",
+        *keys::MEDIA_CATEGORY => "Playback",
+        *keys::NODE_NAME => name,
+        *keys::TARGET_OBJECT => target,
+    };
+
+    let stream = StreamRc::new(core.clone(), name, props)?;
+
+    // The user data is the position in the signal
+    let listener = stream
+        .add_local_listener_with_user_data(0usize)
+        .process(move |stream, position| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+
+            let Some(data) = buffer.datas_mut().first_mut() else {
+                return;
+            };
+
+            let Some(bytes) = data.data() else {
+                return;
+            };
+
+            let mut frames = 0;
+            for out in bytes.chunks_exact_mut(size_of::<f32>()) {
+                let sample = signal.get(*position + frames).copied().unwrap_or(0.0);
+                out.copy_from_slice(&sample.to_le_bytes());
+                frames += 1;
+            }
+
+            *position += frames;
+            if *position >= signal.len() {
+                done.set(true);
+            }
+
+            let chunk = data.chunk_mut();
+            *chunk.offset_mut() = 0;
+            *chunk.stride_mut() = size_of::<f32>() as i32;
+            *chunk.size_mut() = (frames * size_of::<f32>()) as u32;
+        })
+        .register()?;
+
+    let mut audio_info = AudioInfoRaw::new();
+    audio_info.set_format(AudioFormat::F32LE);
+    audio_info.set_rate(rate);
+    audio_info.set_channels(1);
+    let bytes = f32_format(audio_info)?;
+    let format = Pod::from_bytes(&bytes).context("invalid playback format")?;
+
+    stream.connect(
+        Direction::Output,
+        None,
+        StreamFlags::AUTOCONNECT | StreamFlags::MAP_BUFFERS | StreamFlags::DONT_RECONNECT,
+        &mut [format],
+    )?;
+
+    tracing::info!(name, %target, rate, "Connected playback stream");
+    Ok(PlaybackStream {
+        stream,
+        _listener: listener,
+    })
+}
+
+/// The first channel of a capture stream and its rate
+#[derive(Clone, Default)]
+struct Recorder(Rc<RefCell<(Vec<f32>, u32)>>);
+
+impl CaptureSink for Recorder {
+    fn set_rate(&self, rate: u32) {
+        *self.0.borrow_mut() = (Vec::new(), rate);
+    }
+
+    fn push(&self, samples: &[f32], channels: usize) {
+        let (recorded, _) = &mut *self.0.borrow_mut();
+        recorded.extend(samples.iter().step_by(channels.max(1)));
+    }
+}
+
+/// Play a signal into `sink` while recording `output`, blocking until the signal was played.
+/// Returns the first channel of the recording and its rate.
+pub fn play_and_record(
+    sink: &str,
+    output: &str,
+    signal: Vec<f32>,
+    rate: u32,
+    timeout: Duration,
+) -> anyhow::Result<(Vec<f32>, u32)> {
+    let mainloop = MainLoopRc::new(None)?;
+    let context = ContextRc::new(&mainloop, None)?;
+    let core = context.connect_rc(None)?;
+
+    let recorder = Recorder::default();
+    let _capture = connect_capture(
+        &core,
+        "pw-eq-verify-capture",
+        recorder.clone(),
+        output,
+        false,
+    )?;
+    let done = Rc::new(Cell::new(false));
+    let _playback = connect_playback(
+        &core,
+        "pw-eq-verify-sweep",
+        sink,
+        signal,
+        rate,
+        done.clone(),
+    )?;
+
+    let timed_out = Rc::new(Cell::new(false));
+    let started = Instant::now();
+    let timer = mainloop.loop_().add_timer({
+        let mainloop = mainloop.clone();
+        let timed_out = timed_out.clone();
+        move |_| {
+            if started.elapsed() > timeout {
+                timed_out.set(true);
+            }
+
+            if done.get() || timed_out.get() {
+                mainloop.quit();
+            }
+        }
+    });
+    let interval = Duration::from_millis(50);
+    timer
+        .update_timer(Some(interval), Some(interval))
+        .into_result()
+        .context("failed to start timer")?;
+
+    mainloop.run();
+
+    anyhow::ensure!(
+        !timed_out.get(),
+        "timed out playing into `{sink}`, is the EQ running?"
+    );
+    Ok(recorder.0.take())
+}
+
+/// Whether `name` is `prefix` followed by a band count
+fn is_band_count_suffixed(name: &str, prefix: &str) -> bool {
+    name.strip_prefix(prefix)
+        .is_some_and(|count| count.parse::<usize>().is_ok())
+}
+
 fn do_route_stream(metadata: &Metadata, stream_node: &AudioStreamInfo, target: &str) {
     metadata.set_property(
         stream_node.node_id,
@@ -105,13 +799,16 @@ pub fn pw_thread(
     notifs: mpsc::Sender<Notif>,
     pw_receiver: pipewire::channel::Receiver<Message>,
     default_audio_sink: Option<NodeInfo>,
+    capture: SharedCapture,
+    meter: SharedMeter,
+    routing: routing::Config,
 ) -> anyhow::Result<()> {
     let mainloop = MainLoopRc::new(None)?;
     let context = ContextRc::new(&mainloop, None)?;
     let core = context.connect_rc(None)?;
     let registry = core.get_registry_rc()?;
 
-    let st = State::new(default_audio_sink);
+    let st = State::new(notifs.clone(), default_audio_sink, routing);
 
     // Listen for any `Stream/Output/Audio` nodes and attach them to our sink by
     // setting `target.object` using the default metadata object.
@@ -123,89 +820,340 @@ pub fn pw_thread(
             let st = st.clone();
             move |obj| match obj.type_ {
                 ObjectType::Metadata => {
-                    // Find the "default" metadata object
+                    match obj.props.and_then(|props| props.get("metadata.name")) {
+                        Some("default") => {
+                            let metadata = match metadata_registry.bind::<Metadata, _>(obj) {
+                                Ok(metadata) => metadata,
+                                Err(err) => {
+                                    tracing::error!(?err, "Failed to bind to metadata object");
+                                    return;
+                                }
+                            };
+
+                            // The default sink changes when the user picks another output or a
+                            // device is plugged in or connected
+                            let listener = metadata
+                                .add_listener_local()
+                                .property({
+                                    let st = st.clone();
+                                    move |subject, key, _type, value| {
+                                        if subject != 0 || key != Some("default.audio.sink") {
+                                            return 0;
+                                        }
+
+                                        // The value is a JSON object such as `{ "name": "..." }`
+                                        let name = value
+                                            .and_then(|value| {
+                                                serde_json::from_str::<serde_json::Value>(value)
+                                                    .ok()
+                                            })
+                                            .and_then(|value| {
+                                                value.get("name")?.as_str().map(str::to_string)
+                                            });
+                                        *st.default_sink_name.lock().unwrap() = name;
+                                        st.notify_output_device();
+                                        0
+                                    }
+                                })
+                                .register();
+
+                            *st.metadata.lock().unwrap() = Some(metadata);
+                            *st.metadata_listener.lock().unwrap() = Some(listener);
+
+                            tracing::info!(id = obj.id, "Bound to default metadata object");
+                        }
+                        // The settings metadata holds the clock rate of the graph
+                        Some("settings") => {
+                            let metadata = match metadata_registry.bind::<Metadata, _>(obj) {
+                                Ok(metadata) => metadata,
+                                Err(err) => {
+                                    tracing::error!(
+                                        ?err,
+                                        "Failed to bind to settings metadata object"
+                                    );
+                                    return;
+                                }
+                            };
+
+                            let listener = metadata
+                                .add_listener_local()
+                                .property({
+                                    let st = st.clone();
+                                    move |subject, key, _type, value| {
+                                        // Settings are set on the core object
+                                        if subject != 0 {
+                                            return 0;
+                                        }
+
+                                        let rate = value
+                                            .and_then(|value| value.parse::<u32>().ok())
+                                            .filter(|&rate| rate > 0);
+
+                                        match key {
+                                            Some("clock.rate") => {
+                                                st.sample_rates.lock().unwrap().clock_rate = rate
+                                            }
+                                            Some("clock.force-rate") => {
+                                                st.sample_rates.lock().unwrap().force_rate = rate
+                                            }
+                                            _ => return 0,
+                                        }
+
+                                        st.notify_sample_rate();
+                                        0
+                                    }
+                                })
+                                .register();
+
+                            *st.settings_metadata.lock().unwrap() = Some((metadata, listener));
+                            tracing::info!(id = obj.id, "Bound to settings metadata object");
+                        }
+                        _ => {}
+                    }
+                }
+                ObjectType::Device => {
+                    let Some(props) = obj.props else {
+                        return;
+                    };
+
+                    st.devices.insert(
+                        obj.id,
+                        DeviceProps {
+                            description: props.get("device.description").map(str::to_string),
+                            bluetooth_address: props.get("api.bluez5.address").map(str::to_string),
+                        },
+                    );
+                }
+                ObjectType::Node => {
                     if obj
                         .props
-                        .is_none_or(|props| props.get("metadata.name") != Some("default"))
+                        .is_some_and(|props| props.get(MANAGED_PROP) == Some("true"))
                     {
+                        st.watch_eq_node(&metadata_registry, obj);
                         return;
                     }
 
-                    match metadata_registry.bind::<Metadata, _>(obj) {
-                        Ok(metadata) => *st.metadata.lock().unwrap() = Some(metadata),
-                        Err(err) => {
-                            tracing::error!(?err, "Failed to bind to metadata object");
+                    if let Some(props) = obj.props {
+                        let node_name = props.get("node.name").unwrap_or_default();
+                        if node_name.starts_with(&module::output_node_name("")) {
+                            st.eq_output_nodes.insert(
+                                obj.id,
+                                AudioStreamInfo {
+                                    node_id: obj.id,
+                                    node_name: node_name.to_string(),
+                                    original_target_object: None,
+                                },
+                            );
                             return;
                         }
-                    };
 
-                    tracing::info!(id = obj.id, "Bound to default metadata object");
-                }
-                ObjectType::Node => {
-                    let Some(stream_info) = obj.props.as_ref().and_then(|props| {
+                        if props.get("media.class") == Some("Audio/Sink") {
+                            let Some(object_serial) = props
+                                .get("object.serial")
+                                .and_then(|serial| serial.parse().ok())
+                            else {
+                                return;
+                            };
+
+                            st.sinks.insert(
+                                obj.id,
+                                SinkProps {
+                                    node_name: node_name.to_string(),
+                                    object_serial,
+                                    description: props.get("node.description").map(str::to_string),
+                                    bluetooth_address: props
+                                        .get("api.bluez5.address")
+                                        .map(str::to_string),
+                                    device_id: props
+                                        .get("device.id")
+                                        .and_then(|id| id.parse().ok()),
+                                },
+                            );
+                            st.notify_output_device();
+                            return;
+                        }
+                    }
+
+                    let Some(stream) = obj.props.as_ref().and_then(|props| {
                         let node_id = obj.id;
                         let node_name = props.get("node.name")?;
                         let media_class = props.get("media.class")?;
                         let original_target_object =
                             props.get("target.object").map(|s| s.to_string());
 
-                        (media_class == "Stream/Output/Audio" && !node_name.contains("pw-eq")).then(
-                            || {
-                                tracing::info!(
-                                    node_id = node_id,
-                                    %node_name,
-                                    ?props,
-                                    "Detected audio stream node"
-                                );
-                                AudioStreamInfo {
+                        let direction = match media_class {
+                            "Stream/Output/Audio" => routing::Direction::Playback,
+                            // Streams recording the monitor of a sink aren't using a microphone
+                            "Stream/Input/Audio"
+                                if props.get("stream.capture.sink") != Some("true") =>
+                            {
+                                routing::Direction::Capture
+                            }
+                            _ => return None,
+                        };
+
+                        (!node_name.contains("pw-eq")).then(|| {
+                            let props = StreamProps {
+                                node_name: node_name.to_string(),
+                                application_name: props.get("application.name").map(str::to_string),
+                                process_binary: props
+                                    .get("application.process.binary")
+                                    .map(str::to_string),
+                                media_role: props.get("media.role").map(str::to_string),
+                            };
+                            let routed = st.routing.routes(&props);
+                            let eq = st.routing.target(&props).map(str::to_string);
+                            tracing::info!(
+                                node_id = node_id,
+                                %node_name,
+                                ?direction,
+                                routed,
+                                ?eq,
+                                ?props,
+                                "Detected audio stream node"
+                            );
+                            AppStream {
+                                info: AudioStreamInfo {
                                     node_id,
                                     node_name: node_name.to_string(),
                                     original_target_object,
-                                }
-                            },
-                        )
+                                },
+                                props,
+                                routed,
+                                eq,
+                                direction,
+                            }
+                        })
                     }) else {
                         return;
                     };
 
-                    st.audio_stream_nodes
-                        .insert(stream_info.node_id, stream_info.clone());
-
-                    st.route_stream_to_active_node(&stream_info);
+                    st.route_stream(&stream);
+                    st.audio_stream_nodes.insert(stream.info.node_id, stream);
+                    st.notify_streams();
                 }
                 _ => {}
             }
         })
+        .global_remove({
+            let st = st.clone();
+            move |id| {
+                if st.eq_nodes.remove(&id).is_some() {
+                    st.sample_rates.lock().unwrap().node_rates.remove(&id);
+                }
+
+                st.eq_output_nodes.remove(&id);
+                st.devices.remove(&id);
+                st.sinks.remove(&id);
+                if st.audio_stream_nodes.remove(&id).is_some() {
+                    st.notify_streams();
+                }
+            }
+        })
         .register();
 
     // Lazy-load modules per filter count as there is no way to dynamically change the number of
-    // filters in an existing module.
-    let modules: Mutex<HashMap<usize, api::ImplModule>> = Mutex::new(HashMap::new());
+    // filters in an existing module. The band count is part of the media name of a module.
+    let modules: Mutex<HashMap<String, api::ImplModule>> = Mutex::new(HashMap::new());
+    let capture_stream: Mutex<Option<CaptureStream>> = Mutex::new(None);
+    let meter_stream: Mutex<Option<CaptureStream>> = Mutex::new(None);
 
     let _receiver = pw_receiver.attach(mainloop.loop_(), {
         let mainloop = mainloop.clone();
         let context = context.clone();
+        let core = core.clone();
         let state = st.clone();
         move |msg| match msg {
             Message::Terminate => {
+                capture_stream.lock().unwrap().take();
+                meter_stream.lock().unwrap().take();
                 state.cleanup();
                 mainloop.quit();
             }
+            Message::StartCapture {
+                target,
+                capture_sink,
+            } => {
+                let mut capture_stream = capture_stream.lock().unwrap();
+                // Disconnect the previous stream before connecting another with the same name
+                capture_stream.take();
+                match connect_capture(
+                    &core,
+                    "pw-eq-spectrum",
+                    capture.clone(),
+                    &target,
+                    capture_sink,
+                ) {
+                    Ok(stream) => *capture_stream = Some(stream),
+                    Err(err) => {
+                        let _ = notifs.blocking_send(Notif::Error(
+                            err.context("failed to start spectrum capture"),
+                        ));
+                    }
+                }
+            }
+            Message::StopCapture => {
+                capture_stream.lock().unwrap().take();
+                capture.lock().unwrap().clear();
+            }
+            Message::StartMeter { target } => {
+                let mut meter_stream = meter_stream.lock().unwrap();
+                meter_stream.take();
+                match connect_capture(&core, "pw-eq-meter", meter.clone(), &target, false) {
+                    Ok(stream) => *meter_stream = Some(stream),
+                    Err(err) => {
+                        let _ = notifs.blocking_send(Notif::Error(
+                            err.context("failed to start level meter"),
+                        ));
+                    }
+                }
+            }
+            Message::SetStreamRouted {
+                node_id,
+                routed,
+                eq,
+            } => state.set_stream_routed(node_id, routed, eq),
+            Message::SetOutputTarget {
+                name,
+                object_serial,
+            } => state.route_eq_output(&name, object_serial),
             Message::SetActiveNode(node_info) => {
                 *state.active_node.lock().unwrap() = Some(node_info.clone());
-                state.route_all_streams_to_active_node();
+                state.route_streams(|stream| stream.direction == routing::Direction::Playback);
+                state.notify_sample_rate();
+            }
+            Message::SetActiveSource(node_info) => {
+                *state.active_source.lock().unwrap() = Some(node_info);
+                state.route_streams(|stream| stream.direction == routing::Direction::Capture);
+            }
+            Message::SetEqNode { name, node, source } => {
+                state
+                    .eq_targets
+                    .insert(name.clone(), EqTarget { node, source });
+                state.route_streams(|stream| stream.eq.as_ref() == Some(&name));
+            }
+            Message::RemoveEq { name } => {
+                let prefix = format!("{name}-");
+                modules.lock().unwrap().retain(|media_name, _| {
+                    let remove = is_band_count_suffixed(media_name, &prefix);
+                    if remove {
+                        tracing::info!(media_name, "Unloading module");
+                    }
+                    !remove
+                });
+
+                state.eq_targets.remove(&name);
+                state.route_streams(|stream| stream.eq.as_ref() == Some(&name));
             }
             Message::LoadModule { name, args } => {
-                // FIXME this count isn't necessary accurate if we use the param_eq config
-                let band_count = args.filter_graph.nodes.len();
                 let spa_json_args = pw_util::to_spa_json(&args);
 
                 let mut modules = modules.lock().unwrap();
 
-                let module = match modules.entry(band_count) {
+                let module = match modules.entry(args.media_name.clone()) {
                     Entry::Occupied(entry) => entry.into_mut(),
                     Entry::Vacant(entry) => {
-                        tracing::info!(band_count, "Loading new module for band count");
+                        tracing::info!(media_name = args.media_name, "Loading new module");
                         let module = match api::load_module(&context, &name, &spa_json_args) {
                             Ok(module) => module,
                             Err(err) => {
@@ -231,3 +1179,80 @@ pub fn pw_thread(
     mainloop.run();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(object_serial: i64) -> NodeInfo {
+        NodeInfo {
+            node_id: object_serial as u32,
+            node_name: format!("node-{object_serial}"),
+            object_serial,
+        }
+    }
+
+    fn stream(direction: routing::Direction, eq: Option<&str>) -> AppStream {
+        AppStream {
+            info: AudioStreamInfo {
+                node_id: 100,
+                node_name: "stream".to_string(),
+                original_target_object: None,
+            },
+            props: routing::StreamProps {
+                node_name: "stream".to_string(),
+                application_name: None,
+                process_binary: None,
+                media_role: None,
+            },
+            routed: true,
+            eq: eq.map(str::to_string),
+            direction,
+        }
+    }
+
+    #[test]
+    fn test_stream_target_matches_direction() {
+        let state = State::new(mpsc::channel(1).0, None, routing::Config::default());
+        *state.active_node.lock().unwrap() = Some(node(1));
+        *state.active_source.lock().unwrap() = Some(node(2));
+        state.eq_targets.insert(
+            "music".to_string(),
+            EqTarget {
+                node: node(3),
+                source: false,
+            },
+        );
+        state.eq_targets.insert(
+            "mic".to_string(),
+            EqTarget {
+                node: node(4),
+                source: true,
+            },
+        );
+
+        let playback = routing::Direction::Playback;
+        let capture = routing::Direction::Capture;
+        assert_eq!(state.stream_target(&stream(playback, None)), Some(1));
+        assert_eq!(state.stream_target(&stream(capture, None)), Some(2));
+        assert_eq!(
+            state.stream_target(&stream(playback, Some("music"))),
+            Some(3)
+        );
+        assert_eq!(state.stream_target(&stream(capture, Some("mic"))), Some(4));
+
+        // A stream is never linked to an EQ of the other direction
+        assert_eq!(state.stream_target(&stream(playback, Some("mic"))), Some(1));
+        assert_eq!(
+            state.stream_target(&stream(capture, Some("music"))),
+            Some(2)
+        );
+
+        *state.active_source.lock().unwrap() = None;
+        assert_eq!(state.stream_target(&stream(capture, Some("music"))), None);
+
+        let mut unrouted = stream(playback, Some("music"));
+        unrouted.routed = false;
+        assert_eq!(state.stream_target(&unrouted), None);
+    }
+}
@@ -0,0 +1,259 @@
+//! Apply an EQ to audio without PipeWire, e.g. to render WAV files for comparison.
+
+use std::path::Path;
+
+use anyhow::Context as _;
+use pw_util::module::BiquadCoefficients;
+
+use crate::filter::Filter;
+
+/// A biquad in transposed direct form II
+#[derive(Debug, Clone, Copy)]
+pub struct Biquad {
+    coeffs: BiquadCoefficients,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    pub fn new(coeffs: BiquadCoefficients) -> Self {
+        Self {
+            coeffs,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let BiquadCoefficients { b0, b1, b2, a1, a2 } = self.coeffs;
+        let y = b0 * x + self.z1;
+        self.z1 = b1 * x - a1 * y + self.z2;
+        self.z2 = b2 * x - a2 * y;
+        y
+    }
+}
+
+/// The preamp and filters of an EQ applied to interleaved samples, with separate state per channel
+#[derive(Debug, Clone)]
+pub struct Processor {
+    preamp: f64,
+    /// The filter chain of each channel
+    channels: Vec<Vec<Biquad>>,
+}
+
+impl Processor {
+    pub fn new(preamp_db: f64, filters: &[Filter], sample_rate: u32, channels: usize) -> Self {
+        let chain = filters
+            .iter()
+            .map(|filter| Biquad::new(filter.biquad_coeffs(sample_rate as f64)))
+            .collect::<Vec<_>>();
+
+        Self {
+            preamp: 10f64.powf(preamp_db / 20.0),
+            channels: vec![chain; channels.max(1)],
+        }
+    }
+
+    /// Process a sample of the given channel
+    pub fn process(&mut self, channel: usize, sample: f64) -> f64 {
+        self.channels[channel]
+            .iter_mut()
+            .fold(sample * self.preamp, |x, biquad| biquad.process(x))
+    }
+
+    /// Process interleaved samples in place, the length must be a multiple of the channel count
+    pub fn process_interleaved(&mut self, samples: &mut [f32]) {
+        let channels = self.channels.len();
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample = self.process(i % channels, *sample as f64) as f32;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderStats {
+    /// Peak of the output in dBFS, before clipping
+    pub peak_db: f64,
+    /// Number of samples that were clipped to full scale
+    pub clipped: u64,
+}
+
+/// Apply the EQ to a WAV file, keeping its sample rate, channels and sample format.
+/// Integer output is clipped to full scale, float output is written as is.
+pub fn render_wav(
+    preamp_db: f64,
+    filters: &[Filter],
+    input: &Path,
+    output: &Path,
+) -> anyhow::Result<RenderStats> {
+    let mut reader = hound::WavReader::open(input)
+        .with_context(|| format!("failed to open {}", input.display()))?;
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+    let mut processor = Processor::new(preamp_db, filters, spec.sample_rate, channels);
+
+    let mut writer = hound::WavWriter::create(output, spec)
+        .with_context(|| format!("failed to create {}", output.display()))?;
+
+    let mut peak = 0f64;
+    let mut clipped = 0;
+    let mut process = |i: usize, sample: f64| {
+        let out = processor.process(i % channels, sample);
+        peak = peak.max(out.abs());
+        if out.abs() > 1.0 {
+            clipped += 1;
+        }
+        out
+    };
+
+    match spec.sample_format {
+        hound::SampleFormat::Float => {
+            for (i, sample) in reader.samples::<f32>().enumerate() {
+                let sample =
+                    sample.with_context(|| format!("failed to read {}", input.display()))?;
+                writer.write_sample(process(i, sample as f64) as f32)?;
+            }
+        }
+        hound::SampleFormat::Int => {
+            let full_scale = (1i64 << (spec.bits_per_sample - 1)) as f64;
+            for (i, sample) in reader.samples::<i32>().enumerate() {
+                let sample =
+                    sample.with_context(|| format!("failed to read {}", input.display()))?;
+                let out = process(i, sample as f64 / full_scale) * full_scale;
+                writer.write_sample(out.round().clamp(-full_scale, full_scale - 1.0) as i32)?;
+            }
+        }
+    }
+
+    writer
+        .finalize()
+        .with_context(|| format!("failed to write {}", output.display()))?;
+
+    Ok(RenderStats {
+        peak_db: 20.0 * peak.log10(),
+        clipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pw_util::module::FilterType;
+
+    use super::{Processor, render_wav};
+    use crate::filter::Filter;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn sine(freq: f64, len: usize) -> impl Iterator<Item = f64> {
+        (0..len).map(move |i| {
+            0.25 * (2.0 * std::f64::consts::PI * freq * i as f64 / SAMPLE_RATE as f64).sin()
+        })
+    }
+
+    /// Gain in dB of the second half of a rendered sine, after the filters settled.
+    /// The half is a whole number of periods of the test frequencies.
+    fn settled_gain_db(samples: &[f32]) -> f64 {
+        let settled = &samples[samples.len() / 2..];
+        let rms = (settled.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / settled.len() as f64)
+            .sqrt();
+        20.0 * (rms * 2f64.sqrt() / 0.25).log10()
+    }
+
+    #[test]
+    fn test_processor_matches_magnitude_response() {
+        let filters = [
+            Filter::new(FilterType::Peaking, 1000.0, 6.0, 1.0).unwrap(),
+            Filter::new(FilterType::HighShelf, 8000.0, -4.0, 0.7).unwrap(),
+            Filter {
+                muted: true,
+                ..Filter::new(FilterType::LowPass, 100.0, 0.0, 0.7).unwrap()
+            },
+        ];
+
+        for freq in [100.0, 1000.0, 3000.0, 12000.0] {
+            // Stereo with the right channel inverted, each channel has its own state
+            let mut samples = sine(freq, SAMPLE_RATE as usize / 2)
+                .flat_map(|sample| [sample as f32, -sample as f32])
+                .collect::<Vec<_>>();
+            let mut processor = Processor::new(-2.0, &filters, SAMPLE_RATE, 2);
+            processor.process_interleaved(&mut samples);
+
+            let expected = -2.0
+                + filters
+                    .iter()
+                    .map(|filter| filter.magnitude_db_at(freq, SAMPLE_RATE as f64))
+                    .sum::<f64>();
+            let left = samples.iter().step_by(2).copied().collect::<Vec<_>>();
+            let right = samples
+                .iter()
+                .skip(1)
+                .step_by(2)
+                .copied()
+                .collect::<Vec<_>>();
+            for channel in [left, right] {
+                let gain = settled_gain_db(&channel);
+                assert!(
+                    (gain - expected).abs() < 0.05,
+                    "{freq} Hz: {gain} != {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_wav_formats() {
+        let dir = std::env::temp_dir().join(format!("pw-eq-render-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let filters = [Filter::new(FilterType::Peaking, 1000.0, 6.0, 1.0).unwrap()];
+
+        for (bits, sample_format) in [
+            (16, hound::SampleFormat::Int),
+            (24, hound::SampleFormat::Int),
+            (32, hound::SampleFormat::Int),
+            (32, hound::SampleFormat::Float),
+        ] {
+            let spec = hound::WavSpec {
+                channels: 1,
+                sample_rate: SAMPLE_RATE,
+                bits_per_sample: bits,
+                sample_format,
+            };
+            let (input, output) = (dir.join("in.wav"), dir.join("out.wav"));
+
+            let mut writer = hound::WavWriter::create(&input, spec).unwrap();
+            let full_scale = (1i64 << (bits - 1)) as f64;
+            for sample in sine(1000.0, SAMPLE_RATE as usize / 2) {
+                match sample_format {
+                    hound::SampleFormat::Float => writer.write_sample(sample as f32).unwrap(),
+                    hound::SampleFormat::Int => writer
+                        .write_sample((sample * full_scale).round() as i32)
+                        .unwrap(),
+                }
+            }
+            writer.finalize().unwrap();
+
+            let stats = render_wav(0.0, &filters, &input, &output).unwrap();
+            assert_eq!(stats.clipped, 0);
+
+            let mut reader = hound::WavReader::open(&output).unwrap();
+            assert_eq!(reader.spec(), spec);
+            let samples = match sample_format {
+                hound::SampleFormat::Float => reader
+                    .samples::<f32>()
+                    .map(Result::unwrap)
+                    .collect::<Vec<_>>(),
+                hound::SampleFormat::Int => reader
+                    .samples::<i32>()
+                    .map(|sample| (sample.unwrap() as f64 / full_scale) as f32)
+                    .collect(),
+            };
+            let gain = settled_gain_db(&samples);
+            assert!(
+                (gain - 6.0).abs() < 0.05,
+                "{bits} bit {sample_format:?}: {gain}"
+            );
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+//! Rules deciding which application streams are routed to the EQ.
+
+/// Whether matching streams are routed to the EQ
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    #[default]
+    Include,
+    Exclude,
+}
+
+/// Stream to include or exclude, matched with [`properties_match`]
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Rule {
+    /// `application.name` of the stream, e.g. `Spotify`
+    pub application_name: Option<String>,
+    /// `application.process.binary` of the stream, e.g. `Discord`
+    pub process_binary: Option<String>,
+    /// `media.role` of the stream, e.g. `Communication`
+    pub media_role: Option<String>,
+    /// `node.name` of the stream
+    pub node_name: Option<String>,
+    pub action: Action,
+    /// Name of the EQ included streams are routed to, the active EQ if unset or not loaded
+    pub eq: Option<String>,
+}
+
+/// Whether an object matches the properties a rule sets, given as pairs of the value in the rule
+/// and the value of the object. Every property that is set must be equal, ignoring ASCII case.
+/// A rule without any properties never matches.
+pub(crate) fn properties_match<'a>(
+    properties: impl IntoIterator<Item = (Option<&'a str>, Option<&'a str>)>,
+) -> bool {
+    let mut any = false;
+    for (rule, prop) in properties {
+        let Some(rule) = rule else {
+            continue;
+        };
+        if !prop.is_some_and(|prop| prop.eq_ignore_ascii_case(rule)) {
+            return false;
+        }
+        any = true;
+    }
+    any
+}
+
+impl Rule {
+    fn matches(&self, stream: &StreamProps) -> bool {
+        properties_match([
+            (
+                self.application_name.as_deref(),
+                stream.application_name.as_deref(),
+            ),
+            (
+                self.process_binary.as_deref(),
+                stream.process_binary.as_deref(),
+            ),
+            (self.media_role.as_deref(), stream.media_role.as_deref()),
+            (self.node_name.as_deref(), Some(stream.node_name.as_str())),
+        ])
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// What happens to streams that no rule matches
+    pub default: Action,
+    /// The first matching rule decides
+    pub rules: Vec<Rule>,
+}
+
+impl Config {
+    fn rule(&self, stream: &StreamProps) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(stream))
+    }
+
+    pub fn routes(&self, stream: &StreamProps) -> bool {
+        let action = self.rule(stream).map_or(self.default, |rule| rule.action);
+        action == Action::Include
+    }
+
+    /// Name of the EQ the stream is routed to, `None` for the active EQ
+    pub fn target(&self, stream: &StreamProps) -> Option<&str> {
+        self.rule(stream).and_then(|rule| rule.eq.as_deref())
+    }
+}
+
+/// Whether a stream plays audio, routed to sink EQs, or records it, routed to microphone EQs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Playback,
+    Capture,
+}
+
+/// Properties of a stream that rules match on
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamProps {
+    pub node_name: String,
+    pub application_name: Option<String>,
+    pub process_binary: Option<String>,
+    pub media_role: Option<String>,
+}
+
+/// An application stream and whether it is routed to the EQ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stream {
+    pub node_id: u32,
+    pub direction: Direction,
+    pub props: StreamProps,
+    pub routed: bool,
+    /// EQ the stream is routed to, `None` for the active EQ
+    pub eq: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Config, Rule, StreamProps};
+
+    fn stream(node_name: &str, application_name: &str, media_role: Option<&str>) -> StreamProps {
+        StreamProps {
+            node_name: node_name.to_string(),
+            application_name: Some(application_name.to_string()),
+            process_binary: None,
+            media_role: media_role.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_routes() {
+        let config = Config {
+            default: Action::Exclude,
+            rules: vec![
+                Rule {
+                    application_name: Some("discord".to_string()),
+                    action: Action::Exclude,
+                    ..Default::default()
+                },
+                Rule {
+                    application_name: Some("Spotify".to_string()),
+                    eq: Some("music".to_string()),
+                    ..Default::default()
+                },
+                Rule {
+                    media_role: Some("Music".to_string()),
+                    ..Default::default()
+                },
+                // Never matches
+                Rule::default(),
+            ],
+        };
+
+        assert!(config.routes(&stream("spotify", "Spotify", None)));
+        assert!(!config.routes(&stream("WEBRTC VoiceEngine", "Discord", Some("Music"))));
+        assert!(config.routes(&stream("Firefox", "Firefox", Some("Music"))));
+        assert!(!config.routes(&stream("Firefox", "Firefox", None)));
+        assert!(Config::default().routes(&stream("Firefox", "Firefox", None)));
+
+        assert_eq!(
+            config.target(&stream("spotify", "Spotify", None)),
+            Some("music")
+        );
+        assert_eq!(
+            config.target(&stream("Firefox", "Firefox", Some("Music"))),
+            None
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use num_complex::Complex32;
+use rustfft::{Fft, FftPlanner};
+
+/// Level floor of the analyzer in dBFS
+pub const FLOOR_DB: f64 = -96.0;
+
+pub const MAX_FFT_SIZE: usize = 65536;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Tap {
+    /// The input of the EQ, i.e. the monitor of the EQ sink
+    Pre,
+    /// The output of the EQ
+    #[default]
+    Post,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// Show the analyzer on startup
+    pub enabled: bool,
+    pub tap: Tap,
+    /// FFT size in samples, rounded up to a power of two
+    pub fft_size: usize,
+    /// Smooth over 1/N octave bands, 0 disables smoothing
+    pub octave_fraction: u32,
+    /// Weight of the previous frame when averaging frames, between 0 and 1
+    pub averaging: f64,
+    /// Maximum redraws per second while the analyzer is shown
+    pub max_fps: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tap: Tap::default(),
+            fft_size: 4096,
+            octave_fraction: 6,
+            averaging: 0.7,
+            max_fps: 30,
+        }
+    }
+}
+
+/// Mono samples captured from PipeWire, shared between the PipeWire thread and the TUI
+pub type SharedCapture = Arc<Mutex<Capture>>;
+
+/// The most recent samples of a capture stream
+#[derive(Debug, Default)]
+pub struct Capture {
+    samples: VecDeque<f32>,
+    capacity: usize,
+    pub sample_rate: u32,
+}
+
+impl Capture {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            sample_rate: 0,
+        }
+    }
+
+    /// Append interleaved samples, mixed down to mono
+    pub fn push_interleaved(&mut self, samples: &[f32], channels: usize) {
+        let channels = channels.max(1);
+        for frame in samples.chunks_exact(channels) {
+            if self.samples.len() == self.capacity {
+                self.samples.pop_front();
+            }
+            self.samples
+                .push_back(frame.iter().sum::<f32>() / channels as f32);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+/// Windowed FFT of the latest captured samples, averaged over time
+pub struct Analyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    buffer: Vec<Complex32>,
+    /// Averaged power of each bin, relative to a full scale sine
+    power: Vec<f64>,
+    sample_rate: f64,
+    octave_fraction: u32,
+    averaging: f64,
+}
+
+impl Analyzer {
+    pub fn new(config: &Config) -> Self {
+        let size = config.fft_size.clamp(256, MAX_FFT_SIZE).next_power_of_two();
+        let fft = FftPlanner::new().plan_fft_forward(size);
+
+        // Hann window
+        let window = (0..size)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * i as f32 / size as f32;
+                0.5 - 0.5 * phase.cos()
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            fft,
+            window,
+            buffer: vec![Complex32::default(); size],
+            power: vec![0.0; size / 2 + 1],
+            sample_rate: 0.0,
+            octave_fraction: config.octave_fraction,
+            averaging: config.averaging.clamp(0.0, 0.99),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.window.len()
+    }
+
+    /// Analyze the most recent samples of the capture, returns false if there are not enough yet
+    pub fn update(&mut self, capture: &Capture) -> bool {
+        let size = self.size();
+        if capture.sample_rate == 0 || capture.samples.len() < size {
+            return false;
+        }
+
+        let start = capture.samples.len() - size;
+        for ((out, &sample), &w) in self
+            .buffer
+            .iter_mut()
+            .zip(capture.samples.range(start..))
+            .zip(&self.window)
+        {
+            *out = Complex32::new(sample * w, 0.0);
+        }
+
+        self.fft.process(&mut self.buffer);
+
+        if capture.sample_rate as f64 != self.sample_rate {
+            self.sample_rate = capture.sample_rate as f64;
+            self.power.fill(0.0);
+        }
+
+        // A full scale sine peaks at half the sum of the window
+        let reference = (self.window.iter().sum::<f32>() as f64 / 2.0).powi(2);
+        for (power, bin) in self.power.iter_mut().zip(&self.buffer) {
+            let current = bin.norm_sqr() as f64 / reference;
+            *power = self.averaging * *power + (1.0 - self.averaging) * current;
+        }
+
+        true
+    }
+
+    /// Level in dBFS at each of the given frequencies
+    pub fn curve(&self, frequencies: impl IntoIterator<Item = f64>) -> Vec<(f64, f64)> {
+        frequencies
+            .into_iter()
+            .map(|freq| (freq, self.level_db_at(freq)))
+            .collect()
+    }
+
+    fn level_db_at(&self, freq: f64) -> f64 {
+        if self.sample_rate <= 0.0 {
+            return FLOOR_DB;
+        }
+
+        let bin_width = self.sample_rate / self.size() as f64;
+        let last_bin = self.power.len() - 1;
+        let bin_at = |freq: f64| ((freq / bin_width).round() as usize).min(last_bin);
+
+        let power = if self.octave_fraction == 0 {
+            self.power[bin_at(freq)]
+        } else {
+            // Average the power over a band centred on the frequency
+            let half_band = 2_f64.powf(0.5 / self.octave_fraction as f64);
+            let (lo, hi) = (bin_at(freq / half_band), bin_at(freq * half_band));
+            self.power[lo..=hi].iter().sum::<f64>() / (hi - lo + 1) as f64
+        };
+
+        (10.0 * power.log10()).max(FLOOR_DB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Analyzer, Capture, Config, FLOOR_DB};
+
+    #[test]
+    fn test_sine_peak() {
+        let config = Config {
+            averaging: 0.0,
+            ..Default::default()
+        };
+        let mut analyzer = Analyzer::new(&config);
+        let mut capture = Capture::new(analyzer.size());
+        capture.sample_rate = 48000;
+
+        assert!(!analyzer.update(&capture));
+
+        // A half scale stereo sine, which is -6 dBFS
+        let samples = (0..analyzer.size())
+            .flat_map(|i| {
+                let t = i as f32 / 48000.0;
+                let sample = 0.5 * (2.0 * std::f32::consts::PI * 1000.0 * t).sin();
+                [sample, sample]
+            })
+            .collect::<Vec<_>>();
+        capture.push_interleaved(&samples, 2);
+        assert!(analyzer.update(&capture));
+
+        let curve = analyzer.curve([100.0, 1000.0, 10000.0]);
+        let level = curve[1].1;
+        // Smoothing spreads the energy over neighbouring bins
+        assert!((-20.0..=-5.0).contains(&level), "{level}");
+        assert!(curve[0].1 < level - 40.0, "{curve:?}");
+        assert!(curve[2].1 < level - 40.0, "{curve:?}");
+
+        capture.clear();
+        capture.push_interleaved(&vec![0.0; analyzer.size()], 1);
+        let mut silent = Analyzer::new(&config);
+        assert!(silent.update(&capture));
+        assert_eq!(silent.curve([1000.0])[0].1, FLOOR_DB);
+    }
+}
@@ -1,13 +1,33 @@
+mod abx;
 mod action;
 mod autoeq;
+pub(crate) mod devices;
 mod draw;
-mod eq;
+pub(crate) mod eq;
+mod history;
+mod measurement;
+mod mouse;
+mod presets;
+mod slots;
+mod streams;
 mod theme;
 
-use crate::{FilterId, UpdateFilter, filter::Filter, update_filters};
-use pw_util::module::{FilterType, TargetObject};
+use crate::{
+    FilterId, UpdateFilter, daemon,
+    filter::{BiquadFormula, Filter},
+    graphic::{self, Graphic},
+    meter::{self, Levels, Meter, SharedMeter},
+    optimize,
+    preset::{self, PresetInfo, Profile},
+    routing,
+    spectrum::{self, Analyzer, Capture, SharedCapture, Tap},
+    update_filters,
+};
+use pw_util::module::{self as pw_module, FilterType, TargetObject};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 use std::{
     collections::BTreeMap,
     io, mem,
@@ -20,19 +40,36 @@ use zi_input::{Event, KeyCode, KeyEvent, KeyModifiers};
 
 use crossterm::{
     cursor,
-    event::DisableMouseCapture,
+    event::{DisableMouseCapture, EnableMouseCapture},
     execute,
     terminal::{self, EnterAlternateScreen},
 };
 use futures_util::{Stream, StreamExt as _, future::BoxFuture, stream::FusedStream};
 use pw_util::{NodeInfo, pipewire};
-use ratatui::{Terminal, prelude::Backend};
+use ratatui::{
+    Terminal,
+    prelude::{Backend, Rect},
+    widgets::TableState,
+};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 
 use crate::pw::{self, pw_thread};
 
-use self::{eq::Eq, theme::Theme};
+use self::{
+    draw::ChartGeometry,
+    eq::Eq,
+    history::{EditKey, History},
+    measurement::{CurveKind, ReferenceCurve},
+    mouse::MouseDrag,
+    slots::Slots,
+    theme::Theme,
+};
+
+/// How often the level meters are refreshed
+const METER_INTERVAL: Duration = Duration::from_millis(100);
+/// Minimum time between optimizer progress updates in the status line
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
 
 pub enum Format {
     PwParamEq,
@@ -50,7 +87,12 @@ pub(super) enum Rotation {
 enum ViewMode {
     #[default]
     Normal,
+    /// Show the biquad coefficients in the band table
     Expert,
+    /// Plot the phase response below the magnitude response
+    Phase,
+    /// Plot the group delay below the magnitude response
+    GroupDelay,
 }
 
 #[derive(
@@ -71,6 +113,9 @@ enum InputMode {
     #[default]
     Eq,
     AutoEq,
+    Presets,
+    Streams,
+    Abx,
     Command,
 }
 
@@ -78,6 +123,9 @@ enum InputMode {
 enum Tab {
     Eq,
     AutoEq,
+    Presets,
+    Streams,
+    Abx,
 }
 
 pub enum Notif {
@@ -91,15 +139,114 @@ pub enum Notif {
         targets: Vec<autoeq_api::Target>,
     },
     AutoEqLoaded {
+        /// EQ the response is applied to
+        eq: String,
         name: String,
         response: autoeq_api::ParametricEq,
     },
+    PresetsListed(Vec<PresetInfo>),
+    PresetLoaded {
+        /// EQ the preset is loaded into
+        eq: String,
+        name: String,
+        profile: Profile,
+    },
+    /// The sample rate of the graph changed
+    SampleRateChanged(u32),
+    CurveLoaded {
+        kind: CurveKind,
+        curve: ReferenceCurve,
+    },
+    /// Bands were fitted to the loaded measurement and target
+    Fitted {
+        /// EQ the bands are fitted for
+        eq: String,
+        fit: optimize::Fit,
+    },
+    OptimizeProgress(optimize::Progress),
+    /// The unlocked bands were optimized, `None` if it failed
+    Optimized(Option<optimize::Fit>),
+    /// The default sink is now another device
+    OutputDeviceChanged(pw::OutputDevice),
+    /// Application streams appeared, went away or were toggled
+    StreamsChanged(Vec<routing::Stream>),
+    /// An EQ hosted by the daemon changed
+    DaemonChanged(daemon::EqState),
     Error(anyhow::Error),
 }
 
+/// Name of the EQ edited in the TUI
+pub const EQ_NAME: &str = "pw-eq";
+/// Name of the microphone EQ edited in the TUI
+pub const MIC_EQ_NAME: &str = "pw-eq-mic";
+
+/// Capture device of a microphone EQ
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Source {
+    /// `node.name` of the device, the default source if `None`
+    pub device: Option<String>,
+}
+
 pub type TaskResult = Result<Option<String>, String>;
 pub type Task = BoxFuture<'static, TaskResult>;
 
+/// Forward requests to the daemon in order, and its changes as notifications
+async fn connect_daemon(
+    mut client: daemon::Client,
+    notifs_tx: mpsc::Sender<Notif>,
+) -> anyhow::Result<mpsc::UnboundedSender<daemon::Request>> {
+    let mut subscription = daemon::Client::connect(&daemon::socket_path()?)
+        .await?
+        .subscribe()
+        .await?;
+    tokio::spawn({
+        let notifs_tx = notifs_tx.clone();
+        async move {
+            loop {
+                let notif = match subscription.next().await {
+                    Ok(daemon::Notification::Changed(state)) => Notif::DaemonChanged(state),
+                    Err(err) => Notif::Error(err.context("lost the connection to the daemon")),
+                };
+                let lost = matches!(notif, Notif::Error(_));
+                if notifs_tx.send(notif).await.is_err() || lost {
+                    return;
+                }
+            }
+        }
+    });
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(request) = rx.recv().await {
+            if let Err(err) = client.call::<serde_json::Value>(&request).await {
+                let _ = notifs_tx.send(Notif::Error(err)).await;
+            }
+        }
+    });
+    Ok(tx)
+}
+
+/// An EQ edited in its own tab
+struct Instance {
+    eq: Eq,
+    history: History,
+    slots: Slots,
+    active_node_id: Option<u32>,
+    module_name: Option<String>,
+}
+
+impl Instance {
+    fn new(eq: Eq) -> Self {
+        Self {
+            eq,
+            history: Default::default(),
+            slots: Default::default(),
+            active_node_id: None,
+            module_name: None,
+        }
+    }
+}
+
 pub struct App<B: Backend + io::Write> {
     term: Terminal<B>,
     notifs: mpsc::Receiver<Notif>,
@@ -107,10 +254,44 @@ pub struct App<B: Backend + io::Write> {
     tasks: Pin<Box<dyn FusedStream<Item = TaskResult> + Send>>,
     task_tx: mpsc::Sender<Task>,
     pw_tx: pipewire::channel::Sender<pw::Message>,
+    /// Requests to the daemon if it hosts the EQ, the PipeWire thread then only monitors it
+    daemon: Option<mpsc::UnboundedSender<daemon::Request>>,
     eq: Eq,
+    history: History,
+    slots: Slots,
+    /// EQs in tab order, `None` for the shown one whose state is in the fields of `App`.
+    /// The first EQ is the one streams are routed to unless a routing rule names another.
+    instances: Vec<Option<Instance>>,
+    /// Index of the shown EQ in `instances`
+    eq_index: usize,
+    abx: Option<abx::AbxTest>,
+    /// Where the band table was last drawn, for mouse selection
+    table_area: Option<Rect>,
+    /// Scroll position of the band table, which keeps the selected band in view
+    table_state: TableState,
+    /// Where the magnitude chart was last drawn, for mouse editing
+    chart_geometry: Option<ChartGeometry>,
+    mouse_drag: Option<MouseDrag>,
+    /// Samples captured for the spectrum analyzer by the PipeWire thread
+    capture: SharedCapture,
+    /// Present while the spectrum analyzer is shown
+    analyzer: Option<Analyzer>,
+    spectrum_tap: Tap,
+    /// Output levels measured by the PipeWire thread
+    meter: SharedMeter,
+    levels: Option<Levels>,
+    /// Measurement of the device being equalized, shown on the chart
+    measurement: Option<ReferenceCurve>,
+    /// Target response, shown on the chart
+    target: Option<ReferenceCurve>,
+    /// Profile the unlocked bands are being optimized from in the background
+    optimizing: Option<Profile>,
     active_node_id: Option<u32>,
+    /// Name of the loaded module, the name of the EQ suffixed with its band count
+    module_name: Option<String>,
     original_default_sink: Option<NodeInfo>,
     pw_handle: Option<thread::JoinHandle<anyhow::Result<()>>>,
+    /// Sample rate of the graph, used for live coefficient updates and the chart
     sample_rate: u32,
     input_mode: InputMode,
     command_history: Vec<String>,
@@ -121,9 +302,13 @@ pub struct App<B: Backend + io::Write> {
     show_help: bool,
     status: Option<Result<String, String>>,
     view_mode: ViewMode,
+    /// Overlay each band's own response on the chart
+    show_band_curves: bool,
     config: Config,
     tab: Tab,
     autoeq_browser: autoeq::AutoEqBrowser,
+    preset_browser: presets::PresetBrowser,
+    stream_list: streams::StreamList,
     http_client: reqwest::Client,
 }
 
@@ -133,6 +318,26 @@ pub struct Config {
     keymap: KeyMap,
     pub(super) theme: Theme,
     autoeq: autoeq::Config,
+    abx: abx::Config,
+    spectrum: spectrum::Config,
+    /// Formula used to compute the biquad coefficients for the live filters and the chart.
+    pub(crate) biquad_formula: BiquadFormula,
+    /// Sample rates to generate `bq_raw` coefficients for when loading the filter-chain module.
+    /// The rate of the graph is always included.
+    pub(crate) sample_rates: Vec<u32>,
+    /// Start with the preamp following the peak of the response
+    auto_preamp: bool,
+    /// Headroom in dB below the peak of the response when auto preamp is enabled
+    auto_preamp_margin: f64,
+    /// Meter the output of the EQ and count samples that clip
+    level_meter: bool,
+    /// Adjust the preamp of the incoming curve to match the average level of the outgoing one
+    /// when switching slots.
+    ab_level_match: bool,
+    /// Presets to load when the default output device changes, the first matching rule is used
+    pub(crate) device_profiles: Vec<devices::Rule>,
+    /// Which application streams are routed to the EQ
+    pub(crate) routing: routing::Config,
 }
 
 #[derive(Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -140,6 +345,9 @@ pub struct Config {
 struct KeyMap {
     eq: BTreeMap<zi_input::KeyEvent, action::EqAction>,
     autoeq: BTreeMap<zi_input::KeyEvent, action::AutoEqAction>,
+    presets: BTreeMap<zi_input::KeyEvent, action::PresetAction>,
+    streams: BTreeMap<zi_input::KeyEvent, action::StreamAction>,
+    abx: BTreeMap<zi_input::KeyEvent, action::AbxAction>,
     command: BTreeMap<zi_input::KeyEvent, action::CommandAction>,
 }
 
@@ -148,6 +356,9 @@ impl Config {
     pub fn merge(mut self, config: Config) -> Self {
         self.keymap.eq.extend(config.keymap.eq);
         self.keymap.autoeq.extend(config.keymap.autoeq);
+        self.keymap.presets.extend(config.keymap.presets);
+        self.keymap.streams.extend(config.keymap.streams);
+        self.keymap.abx.extend(config.keymap.abx);
         self.keymap.command.extend(config.keymap.command);
 
         // Written in this way to make sure we don't forget to merge new fields later
@@ -155,6 +366,16 @@ impl Config {
             keymap: self.keymap,
             theme: config.theme,
             autoeq: config.autoeq,
+            abx: config.abx,
+            spectrum: config.spectrum,
+            biquad_formula: config.biquad_formula,
+            sample_rates: config.sample_rates,
+            auto_preamp: config.auto_preamp,
+            auto_preamp_margin: config.auto_preamp_margin,
+            level_meter: config.level_meter,
+            ab_level_match: config.ab_level_match,
+            device_profiles: config.device_profiles,
+            routing: config.routing,
         }
     }
 }
@@ -171,10 +392,16 @@ impl Default for Config {
                     "k":         "select-previous",
                     "m":         "toggle-mute",
                     "o":         "toggle-solo",
+                    "<C-l>":     "toggle-lock",
+                    "<S-O>":     "optimize-unlocked",
                     "b":         "toggle-bypass",
                     "a":         "add-filter",
                     "x":         "remove-filter",
                     "<S-A>":     "open-auto-eq",
+                    "i":         "open-presets",
+                    "n":         "open-streams",
+                    "]":       { "cycle-eq": "clockwise" },
+                    "[":       { "cycle-eq": "counter-clockwise" },
                     ":":         "enter-command-mode",
                     "1":       { "select-index": 0 },
                     "2":       { "select-index": 1 },
@@ -202,10 +429,17 @@ impl Default for Config {
                     "+":       { "adjust-preamp": { "delta": 0.1 } },
                     "<S-P>":   { "adjust-preamp": { "delta": -0.1 } },
                     "-":       { "adjust-preamp": { "delta": -0.1 } },
+                    "<C-p>":     "toggle-auto-preamp",
                     "<Tab>":   { "cycle-filter-type": "clockwise" },
                     "<S-Tab>": { "cycle-filter-type": "counter-clockwise" },
                     "v":       { "cycle-view-mode": "clockwise" },
+                    "c":         "toggle-band-curves",
+                    "s":         "toggle-spectrum",
+                    "<S-C>":     "reset-clips",
                     "0":       { "adjust-gain": { "set": 0.0 } },
+                    "u":         "undo",
+                    "<C-r>":     "redo",
+                    "t":         "toggle-a-b",
                 },
                 "autoeq": {
                     "<C-c>":    "quit",
@@ -222,6 +456,42 @@ impl Default for Config {
                     "<Esc>":    "enter-eq-mode",
                     ":":        "enter-command-mode",
                 },
+                "presets": {
+                    "<C-c>":    "quit",
+                    "q" :       "quit",
+                    "?":        "toggle-help",
+                    "j":        "select-next",
+                    "k":        "select-previous",
+                    "<Down>":   "select-next",
+                    "<Up>":     "select-previous",
+                    "<CR>":     "apply-preset",
+                    "<Esc>":    "enter-eq-mode",
+                    ":":        "enter-command-mode",
+                },
+                "streams": {
+                    "<C-c>":    "quit",
+                    "q" :       "quit",
+                    "?":        "toggle-help",
+                    "j":        "select-next",
+                    "k":        "select-previous",
+                    "<Down>":   "select-next",
+                    "<Up>":     "select-previous",
+                    "<CR>":     "toggle-stream",
+                    "<Space>":  "toggle-stream",
+                    "<Esc>":    "enter-eq-mode",
+                    ":":        "enter-command-mode",
+                },
+                "abx": {
+                    "?":        "toggle-help",
+                    "a":        "play-a",
+                    "b":        "play-b",
+                    "x":        "play-x",
+                    "1":        "guess-a",
+                    "2":        "guess-b",
+                    "q":        "stop",
+                    "<Esc>":    "stop",
+                    "<C-c>":    "stop",
+                },
                 "command": {
                     "<Esc>":       "exit-command-mode",
                     "<C-c>":       "exit-command-mode",
@@ -239,6 +509,16 @@ impl Default for Config {
             .unwrap(),
             theme: Default::default(),
             autoeq: Default::default(),
+            abx: Default::default(),
+            spectrum: Default::default(),
+            level_meter: true,
+            biquad_formula: Default::default(),
+            sample_rates: vec![44100, 48000, 88200, 96000, 176400, 192000],
+            auto_preamp: false,
+            auto_preamp_margin: 0.5,
+            ab_level_match: true,
+            device_profiles: vec![],
+            routing: Default::default(),
         }
     }
 }
@@ -253,6 +533,9 @@ where
         config: Config,
         preamp: f64,
         filters: impl IntoIterator<Item = Filter>,
+        graphic: Option<Graphic>,
+        source: Option<Source>,
+        daemon: Option<daemon::Client>,
     ) -> io::Result<Self> {
         let default_audio_sink = match pw_util::get_default_audio_sink().await {
             Ok(node) => {
@@ -268,68 +551,95 @@ where
         let (pw_tx, rx) = pipewire::channel::channel();
         let (notifs_tx, notifs) = mpsc::channel(100);
         let pw_notifs_tx = notifs_tx.clone();
-        let pw_handle = thread::spawn(|| pw_thread(pw_notifs_tx, rx, default_audio_sink));
+        let capture = Arc::new(Mutex::new(Capture::new(spectrum::MAX_FFT_SIZE)));
+        let meter = Arc::new(Mutex::new(Meter::default()));
+        let pw_handle = thread::spawn({
+            let capture = capture.clone();
+            let meter = meter.clone();
+            let routing = config.routing.clone();
+            || {
+                pw_thread(
+                    pw_notifs_tx,
+                    rx,
+                    default_audio_sink,
+                    capture,
+                    meter,
+                    routing,
+                )
+            }
+        });
+
+        let daemon = match daemon {
+            Some(client) => Some(
+                connect_daemon(client, notifs_tx.clone())
+                    .await
+                    .map_err(|err| io::Error::other(format!("{err:#}")))?,
+            ),
+            None => None,
+        };
 
         let (task_tx, task_rx) = mpsc::channel::<BoxFuture<'static, TaskResult>>(100);
         let tasks = Box::pin(ReceiverStream::new(task_rx).buffered(8));
 
         let filters = filters.into_iter().collect::<Vec<_>>();
-        let name = "pw-eq";
+        let name = if source.is_some() {
+            MIC_EQ_NAME
+        } else {
+            EQ_NAME
+        };
         let mut eq = if !filters.is_empty() {
             Eq::new(name, filters)
         } else {
-            Eq::new(
-                name,
-                [
-                    Filter {
-                        frequency: 50.0,
-                        filter_type: FilterType::LowShelf,
-                        ..Default::default()
-                    },
-                    Filter {
-                        frequency: 100.0,
-                        ..Default::default()
-                    },
-                    Filter {
-                        frequency: 200.0,
-                        ..Default::default()
-                    },
-                    Filter {
-                        frequency: 500.0,
-                        ..Default::default()
-                    },
-                    Filter {
-                        frequency: 2000.0,
-                        ..Default::default()
-                    },
-                    Filter {
-                        frequency: 5000.0,
-                        ..Default::default()
-                    },
-                    Filter {
-                        frequency: 10000.0,
-                        filter_type: FilterType::HighShelf,
-                        ..Default::default()
-                    },
-                ],
-            )
+            Eq::new(name, default_filters())
         };
 
         eq.adjust_preamp(|_p| preamp);
+        eq.graphic = graphic;
+        eq.source = source;
+        eq.formula = config.biquad_formula;
+        eq.auto_preamp = config.auto_preamp;
+        eq.auto_preamp_margin = config.auto_preamp_margin;
+        // The graph rate is not known yet, it is recomputed once it is
+        eq.update_auto_preamp(48000.0);
+
+        let analyzer = config
+            .spectrum
+            .enabled
+            .then(|| Analyzer::new(&config.spectrum));
+        let spectrum_tap = config.spectrum.tap;
 
         Ok(Self {
             term,
             pw_tx,
+            daemon,
             notifs,
             notifs_tx,
             tasks,
             task_tx,
             eq,
+            history: Default::default(),
+            slots: Default::default(),
+            instances: vec![None],
+            eq_index: 0,
+            abx: None,
+            table_area: None,
+            table_state: Default::default(),
+            chart_geometry: None,
+            mouse_drag: None,
+            capture,
+            analyzer,
+            spectrum_tap,
+            meter,
+            levels: None,
+            measurement: None,
+            target: None,
+            optimizing: None,
             config,
             pw_handle: Some(pw_handle),
-            // TODO query sample rate
+            // Updated once the pipewire thread reports the graph rate
             sample_rate: 48000,
             active_node_id: Default::default(),
+            module_name: Default::default(),
             original_default_sink: Default::default(),
             input_mode: Default::default(),
             command_history: Default::default(),
@@ -339,9 +649,12 @@ where
             command_cursor_pos: Default::default(),
             show_help: Default::default(),
             view_mode: Default::default(),
+            show_band_curves: false,
             status: Default::default(),
             tab: Tab::Eq,
             autoeq_browser: autoeq::AutoEqBrowser::default(),
+            preset_browser: presets::PresetBrowser::default(),
+            stream_list: streams::StreamList::default(),
             http_client: reqwest::Client::new(),
         })
     }
@@ -362,26 +675,39 @@ where
         execute!(
             self.term.backend_mut(),
             EnterAlternateScreen,
-            DisableMouseCapture
+            EnableMouseCapture
         )?;
         terminal::enable_raw_mode()?;
 
         Ok(())
     }
 
-    pub async fn run(mut self, events: impl Stream<Item = zi_input::Event>) -> anyhow::Result<()> {
+    pub async fn run(
+        mut self,
+        events: impl Stream<Item = crossterm::event::Event>,
+    ) -> anyhow::Result<()> {
         execute!(
             self.term.backend_mut(),
             cursor::Show,
             cursor::SetCursorStyle::SteadyBar,
         )?;
 
-        if !self.eq.is_noop() {
+        // The daemon hosts the EQ even if it is flat, so that it can be edited from other clients
+        if self.daemon.is_some() || !self.eq.is_noop() {
             self.load_module();
         }
 
         let mut events = pin!(events.fuse());
 
+        // Caps how often the spectrum analyzer redraws the screen
+        let mut frames = tokio::time::interval(Duration::from_secs_f64(
+            1.0 / self.config.spectrum.max_fps.max(1) as f64,
+        ));
+        frames.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        let mut meter_ticks = tokio::time::interval(METER_INTERVAL);
+        meter_ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
         loop {
             self.draw()?;
 
@@ -391,6 +717,10 @@ where
                         break;
                     }
                 }
+                _ = frames.tick(), if self.analyzer.is_some() => self.update_spectrum(),
+                _ = meter_ticks.tick(), if self.config.level_meter && self.active_node_id.is_some() => {
+                    self.update_levels()
+                }
                 Some(notif) = self.notifs.recv() => self.on_notif(notif).await,
                 result = self.tasks.select_next_some() => match result {
                     Ok(Some(status)) => self.status = Some(Ok(status)),
@@ -431,10 +761,25 @@ where
 
                 let node_id = node.id;
 
-                self.sync_all(node_id, self.sample_rate);
+                // Modules are named after their EQ suffixed with the band count
+                let Some(index) = media_name
+                    .rsplit_once('-')
+                    .and_then(|(name, _)| self.find_instance(name))
+                else {
+                    return;
+                };
 
-                self.active_node_id = Some(node_id);
-                if let Err(err) = self.pw_tx.send(pw::Message::SetActiveNode(NodeInfo {
+                let source = self.with_instance(index, |app| {
+                    app.sync_all(node_id, app.sample_rate);
+                    app.active_node_id = Some(node_id);
+                    app.module_name = Some(media_name.clone());
+                    app.eq.source.is_some()
+                });
+                if index == self.eq_index {
+                    self.start_monitoring();
+                }
+
+                let node = NodeInfo {
                     node_id,
                     node_name: media_name,
                     object_serial: node
@@ -443,7 +788,22 @@ where
                         .get("object.serial")
                         .and_then(|v| v.as_i64())
                         .expect("object.serial missing or malformed"),
-                })) {
+                };
+                let name = self.eq_names().nth(index).unwrap().to_string();
+                if let Err(err) = self.pw_tx.send(pw::Message::SetEqNode {
+                    name,
+                    node: node.clone(),
+                    source,
+                }) {
+                    tracing::error!(error = ?err, "failed to set EQ node");
+                }
+                let active = match source {
+                    true => pw::Message::SetActiveSource(node),
+                    false => pw::Message::SetActiveNode(node),
+                };
+                if index == 0
+                    && let Err(err) = self.pw_tx.send(active)
+                {
                     tracing::error!(
                         error = ?err,
                         "failed to set active node"
@@ -457,22 +817,178 @@ where
                     self.autoeq_browser.filtered_results.len()
                 )));
             }
-            Notif::AutoEqLoaded { name, response } => {
+            Notif::AutoEqLoaded { eq, name, response } => {
+                // The EQ may have been closed since
+                let Some(index) = self.find_instance(&eq) else {
+                    return;
+                };
+
                 tracing::info!(
+                    eq,
                     headphone = name,
                     num_filters = response.filters.len(),
                     "AutoEQ applied"
                 );
-                self.eq.preamp = response.preamp;
-                self.eq.filters = autoeq::param_eq_to_filters(response);
-                self.eq
-                    .filters
-                    .retain(|f| f.frequency < self.config.autoeq.cutoff_frequency);
-                self.status = Some(Ok(format!("Applied EQ for {}", name)));
-                self.enter_eq_mode();
-                self.load_module();
-                if let Some(node_id) = self.active_node_id {
-                    self.sync_all(node_id, self.sample_rate);
+                let cutoff = self.config.autoeq.cutoff_frequency;
+                let preamp = response.preamp;
+                let mut filters = autoeq::param_eq_to_filters(response);
+                filters.retain(|f| f.frequency < cutoff);
+                if filters.is_empty() {
+                    self.status =
+                        Some(Err(format!("no AutoEQ bands for {name} below {cutoff} Hz")));
+                    return;
+                }
+
+                self.with_instance(index, |app| {
+                    let before = app.eq.clone();
+                    app.eq.preamp = preamp;
+                    app.eq.set_graphic(None);
+                    app.eq.filters = filters;
+                    if app.eq.selected_idx >= app.eq.filters.len() {
+                        app.eq.selected_idx = 0;
+                    }
+                    app.eq.update_auto_preamp(app.sample_rate as f64);
+                    app.history.record(&before, &app.eq, None);
+                    app.load_module();
+                    if let Some(node_id) = app.active_node_id {
+                        app.sync_all(node_id, app.sample_rate);
+                    }
+                });
+                self.status = Some(Ok(format!("Applied EQ for {name} to {eq}")));
+                if index == self.eq_index {
+                    self.enter_eq_mode();
+                }
+            }
+            Notif::PresetsListed(presets) => self.preset_browser.on_data_loaded(presets),
+            Notif::StreamsChanged(streams) => self.stream_list.on_streams_changed(streams),
+            Notif::PresetLoaded { eq, name, profile } => {
+                // The EQ may have been closed since
+                let Some(index) = self.find_instance(&eq) else {
+                    return;
+                };
+
+                tracing::info!(
+                    eq,
+                    preset = name,
+                    num_filters = profile.filters.len(),
+                    "preset applied"
+                );
+                if let Err(err) = self.with_instance(index, |app| app.apply_profile(profile)) {
+                    self.status = Some(Err(format!("preset {name}: {err}")));
+                    return;
+                }
+                self.status = Some(Ok(format!("applied preset {name} to {eq}")));
+                if index == self.eq_index {
+                    self.enter_eq_mode();
+                }
+            }
+            Notif::SampleRateChanged(rate) => {
+                if rate == self.sample_rate {
+                    return;
+                }
+
+                tracing::info!(old = self.sample_rate, new = rate, "sample rate changed");
+                self.sample_rate = rate;
+                for index in 0..self.instances.len() {
+                    self.with_instance(index, |app| {
+                        app.eq.update_auto_preamp(app.sample_rate as f64);
+                        // Live coefficients are only valid for the rate they were computed at
+                        if let Some(node_id) = app.active_node_id {
+                            app.sync_all(node_id, app.sample_rate);
+                        }
+                    });
+                }
+            }
+            Notif::CurveLoaded { kind, curve } => {
+                self.status = Some(Ok(format!("loaded {} {}", kind.label(), curve.name)));
+                match kind {
+                    CurveKind::Measurement => self.measurement = Some(curve),
+                    CurveKind::Target => self.target = Some(curve),
+                }
+            }
+            Notif::Fitted { eq, fit } => {
+                let Some(index) = self.find_instance(&eq) else {
+                    return;
+                };
+
+                tracing::info!(
+                    eq,
+                    num_filters = fit.filters.len(),
+                    error_db = fit.error_db,
+                    "fitted filters"
+                );
+                self.status = Some(Ok(format!(
+                    "fitted {} bands for {eq}, RMS error {:.2} dB",
+                    fit.filters.len(),
+                    fit.error_db
+                )));
+                self.with_instance(index, |app| app.apply_fit(fit));
+            }
+            Notif::OptimizeProgress(progress) => {
+                self.status = Some(Ok(format!(
+                    "optimizing... round {}, RMS error {:.2} dB",
+                    progress.round, progress.error_db
+                )));
+            }
+            Notif::Optimized(fit) => {
+                let Some(base) = self.optimizing.take() else {
+                    return;
+                };
+                let Some(mut fit) = fit else { return };
+                tracing::info!(error_db = fit.error_db, "optimized unlocked filters");
+                // The fit is of the bands as they were when the optimizer started
+                if base != self.eq.profile() {
+                    self.status = Some(Err(
+                        "the EQ was edited while optimizing, discarded the result".to_string(),
+                    ));
+                    return;
+                }
+                for (fitted, band) in fit.filters.iter_mut().zip(&self.eq.filters) {
+                    if band.locked {
+                        *fitted = *band;
+                    }
+                }
+                self.status = Some(Ok(format!(
+                    "optimized unlocked bands, RMS error {:.2} dB",
+                    fit.error_db
+                )));
+                self.apply_fit(fit);
+            }
+            Notif::OutputDeviceChanged(device) => self.on_output_device_changed(device),
+            Notif::DaemonChanged(state) => {
+                let Some(index) = self.find_instance(&state.name) else {
+                    return;
+                };
+
+                let changed = self.with_instance(index, |app| {
+                    // Edits of other clients, those of the TUI come back as they are
+                    if state.profile != app.eq.profile() || state.bypassed != app.eq.bypassed {
+                        let before = app.eq.clone();
+                        app.eq.preamp = state.profile.preamp;
+                        app.eq.filters = state.profile.filters.clone();
+                        app.eq.graphic = state.profile.graphic;
+                        app.eq.bypassed = state.bypassed;
+                        if app.eq.selected_idx >= app.eq.filters.len() {
+                            app.eq.selected_idx = 0;
+                        }
+                        app.history.record(&before, &app.eq, None);
+                    }
+
+                    if state.module == app.module_name {
+                        return false;
+                    }
+
+                    tracing::info!(
+                        eq = state.name,
+                        module = state.module,
+                        "daemon loaded module"
+                    );
+                    app.active_node_id = state.node_id;
+                    app.module_name = state.module;
+                    true
+                });
+                if changed && index == self.eq_index {
+                    self.start_monitoring();
                 }
             }
             Notif::Error(err) => {
@@ -482,11 +998,71 @@ where
         }
     }
 
+    /// Play the EQ into the new device and load the preset of the first rule matching it
+    fn on_output_device_changed(&mut self, device: pw::OutputDevice) {
+        // The daemon follows the output device itself
+        if self.daemon.is_some() {
+            return;
+        }
+
+        // Don't replace the profile the TUI was started with by the rule for the current device
+        let (noop, source) =
+            self.with_instance(0, |app| (app.eq.is_noop(), app.eq.source.is_some()));
+        let keep_profile = self.original_default_sink.is_none() && !noop;
+        self.original_default_sink = Some(NodeInfo {
+            node_id: device.node_id,
+            node_name: device.node_name.clone(),
+            object_serial: device.object_serial,
+        });
+
+        for name in self.eq_names() {
+            if let Err(err) = self.pw_tx.send(pw::Message::SetOutputTarget {
+                name: name.to_string(),
+                object_serial: device.object_serial,
+            }) {
+                tracing::error!(error = ?err, "failed to set output target");
+            }
+        }
+
+        // Device presets are for what is played, not the microphone
+        if source {
+            return;
+        }
+
+        let Some(rule) = devices::matching(&self.config.device_profiles, &device) else {
+            return;
+        };
+
+        let name = device.description.as_deref().unwrap_or(&device.node_name);
+        if keep_profile {
+            tracing::info!(preset = rule.preset, "keeping the initial profile");
+        } else if self.abx.is_some() {
+            self.status = Some(Err(format!(
+                "not loading preset {} for {name} during an ABX test",
+                rule.preset
+            )));
+        } else {
+            tracing::info!(
+                preset = rule.preset,
+                device = name,
+                "loading preset for device"
+            );
+            // Device presets are for the EQ streams are routed to by default
+            let eq = self.eq_names().next().unwrap().to_string();
+            self.load_preset(eq, rule.preset.clone());
+        }
+    }
+
     fn apply_updates(
         &self,
         node_id: u32,
         updates: impl IntoIterator<Item = (FilterId, UpdateFilter), IntoIter: Send> + Send + 'static,
     ) {
+        if let Some(daemon) = &self.daemon {
+            self.sync_daemon(daemon);
+            return;
+        }
+
         self.schedule(async move {
             match update_filters(node_id, updates).await {
                 Ok(()) => Ok(None),
@@ -495,6 +1071,87 @@ where
         });
     }
 
+    /// The daemon owns the module, send it the whole EQ
+    fn sync_daemon(&self, daemon: &mpsc::UnboundedSender<daemon::Request>) {
+        let name = self.eq.name.clone();
+        let _ = daemon.send(daemon::Request::LoadProfile {
+            name: name.clone(),
+            profile: self.eq.profile(),
+        });
+        let _ = daemon.send(daemon::Request::Bypass {
+            name: name.clone(),
+            bypassed: self.eq.bypassed,
+        });
+        let _ = daemon.send(daemon::Request::Solo {
+            name,
+            band: self
+                .eq
+                .soloed_index()
+                .map(|idx| NonZero::new(idx + 1).unwrap()),
+        });
+    }
+
+    /// Send the daemon what an edit changed, the whole EQ only if more than the parameters of
+    /// the bands did. Adding or removing bands reloads the module instead.
+    fn sync_daemon_edit(&self, daemon: &mpsc::UnboundedSender<daemon::Request>, before: &Eq) {
+        let name = &self.eq.name;
+        if before.filters.len() != self.eq.filters.len() {
+            return;
+        }
+        if before.filters.iter().zip(&self.eq.filters).any(|(a, b)| {
+            a.filter_type != b.filter_type || a.muted != b.muted || a.locked != b.locked
+        }) {
+            self.sync_daemon(daemon);
+            return;
+        }
+
+        if before.bypassed != self.eq.bypassed {
+            let _ = daemon.send(daemon::Request::Bypass {
+                name: name.clone(),
+                bypassed: self.eq.bypassed,
+            });
+        }
+        if before.soloed_index() != self.eq.soloed_index() {
+            let _ = daemon.send(daemon::Request::Solo {
+                name: name.clone(),
+                band: self
+                    .eq
+                    .soloed_index()
+                    .map(|idx| NonZero::new(idx + 1).unwrap()),
+            });
+        }
+        if before.preamp != self.eq.preamp {
+            let _ = daemon.send(daemon::Request::SetBand {
+                name: name.clone(),
+                band: FilterId::Preamp,
+                frequency: None,
+                gain: Some(self.eq.preamp),
+                q: None,
+            });
+        }
+        for (idx, (a, b)) in before.filters.iter().zip(&self.eq.filters).enumerate() {
+            let changed = |a: f64, b: f64| (a != b).then_some(b);
+            let (frequency, gain, q) = match self.eq.graphic {
+                // The daemon derives the rest from the gain, as the layout is the same
+                Some(_) => (None, changed(a.gain, b.gain), None),
+                None => (
+                    changed(a.frequency, b.frequency),
+                    changed(a.gain, b.gain),
+                    changed(a.q, b.q),
+                ),
+            };
+            if frequency.is_some() || gain.is_some() || q.is_some() {
+                let _ = daemon.send(daemon::Request::SetBand {
+                    name: name.clone(),
+                    band: FilterId::Index(NonZero::new(idx + 1).unwrap()),
+                    frequency,
+                    gain,
+                    q,
+                });
+            }
+        }
+    }
+
     /// Sync preamp gain to PipeWire
     fn sync_preamp(&self, node_id: u32) {
         let update = self.eq.build_preamp_update();
@@ -510,21 +1167,17 @@ where
 
     // Sync preamp and all filters to PipeWire
     fn sync_all(&self, node_id: u32, sample_rate: u32) {
-        let mut updates = Vec::with_capacity(self.eq.filters.len() + 1);
-
-        updates.push((FilterId::Preamp, self.eq.build_preamp_update()));
+        self.apply_updates(node_id, self.eq.build_updates(sample_rate));
+    }
 
-        for idx in 0..self.eq.filters.len() {
-            let id = FilterId::Index(NonZero::new(idx + 1).unwrap());
-            updates.push((id, self.eq.build_filter_update(idx, sample_rate)));
+    fn handle_event(&mut self, event: crossterm::event::Event) -> io::Result<ControlFlow<()>> {
+        // Mouse events are handled directly as they are not part of the keymap
+        if let crossterm::event::Event::Mouse(mouse) = event {
+            return self.handle_mouse(mouse);
         }
 
-        self.apply_updates(node_id, updates);
-    }
-
-    fn handle_event(&mut self, event: Event) -> io::Result<ControlFlow<()>> {
-        match event {
-            Event::Key(key) => self.handle_key(key),
+        match Event::try_from(event) {
+            Ok(Event::Key(key)) => self.handle_key(key),
             _ => Ok(ControlFlow::Continue(())),
         }
     }
@@ -547,6 +1200,26 @@ where
                     Ok(ControlFlow::Continue(()))
                 }
             }
+            InputMode::Presets => {
+                if let Some(action) = self.config.keymap.presets.get(&key) {
+                    self.perform_preset_action(*action)
+                } else {
+                    Ok(ControlFlow::Continue(()))
+                }
+            }
+            InputMode::Streams => {
+                if let Some(action) = self.config.keymap.streams.get(&key) {
+                    self.perform_stream_action(*action)
+                } else {
+                    Ok(ControlFlow::Continue(()))
+                }
+            }
+            InputMode::Abx => {
+                if let Some(action) = self.config.keymap.abx.get(&key) {
+                    self.perform_abx_action(*action);
+                }
+                Ok(ControlFlow::Continue(()))
+            }
             InputMode::Command => {
                 if let Some(action) = self.config.keymap.command.get(&key) {
                     self.perform_command_action(*action)
@@ -573,10 +1246,22 @@ where
         }
     }
 
-    fn cycle_view_mode(&mut self, _rotation: Rotation) {
-        self.view_mode = match self.view_mode {
-            ViewMode::Normal => ViewMode::Expert,
-            ViewMode::Expert => ViewMode::Normal,
+    fn cycle_view_mode(&mut self, rotation: Rotation) {
+        const MODES: [ViewMode; 4] = [
+            ViewMode::Normal,
+            ViewMode::Expert,
+            ViewMode::Phase,
+            ViewMode::GroupDelay,
+        ];
+
+        let idx = MODES
+            .iter()
+            .position(|&mode| mode == self.view_mode)
+            .expect("view mode must exist in MODES");
+
+        self.view_mode = match rotation {
+            Rotation::Clockwise => MODES[(idx + 1) % MODES.len()],
+            Rotation::CounterClockwise => MODES[(idx + MODES.len() - 1) % MODES.len()],
         };
     }
 
@@ -589,6 +1274,24 @@ where
         let before_bypass = self.eq.bypassed;
         let before_solo = self.eq.soloed_index();
         let before_filter_count = self.eq.filters.len();
+        let before_eq = self.eq.clone();
+
+        if self.eq.graphic.is_some()
+            && self.mouse_drag.is_none()
+            && matches!(
+                action,
+                EqAction::AddFilter
+                    | EqAction::RemoveFilter
+                    | EqAction::AdjustFrequency(_)
+                    | EqAction::AdjustQ(_)
+                    | EqAction::CycleFilterType(_)
+            )
+        {
+            self.status = Some(Err(
+                "only the gains can be changed in graphic mode".to_string()
+            ));
+            return Ok(ControlFlow::Continue(()));
+        }
 
         match action {
             EqAction::Quit => return Ok(ControlFlow::Break(())),
@@ -600,6 +1303,7 @@ where
             EqAction::ToggleBypass => self.eq.toggle_bypass(),
             EqAction::ToggleMute => self.eq.toggle_mute(),
             EqAction::ToggleSolo => self.eq.toggle_solo(),
+            EqAction::ToggleLock => self.eq.toggle_lock(),
             EqAction::SelectIndex(idx) => {
                 if idx < self.eq.filters.len() {
                     self.eq.selected_idx = idx;
@@ -608,14 +1312,57 @@ where
             EqAction::AdjustFrequency(adj) => self.eq.adjust_freq(|f| adj.apply(f)),
             EqAction::AdjustGain(adj) => self.eq.adjust_gain(|g| adj.apply(g)),
             EqAction::AdjustQ(adj) => self.eq.adjust_q(|q| adj.apply(q)),
-            EqAction::AdjustPreamp(adj) => self.eq.adjust_preamp(|p| adj.apply(p)),
+            EqAction::AdjustPreamp(adj) => {
+                // A manual adjustment takes over from the automatic preamp
+                self.eq.auto_preamp = false;
+                self.eq.adjust_preamp(|p| adj.apply(p))
+            }
+            EqAction::ToggleAutoPreamp => self.eq.toggle_auto_preamp(),
             EqAction::CycleFilterType(rotation) => self.eq.cycle_filter_type(rotation),
             EqAction::CycleViewMode(rotation) => self.cycle_view_mode(rotation),
+            EqAction::ToggleBandCurves => self.show_band_curves = !self.show_band_curves,
+            EqAction::ToggleSpectrum => self.toggle_spectrum(),
+            EqAction::ResetClips => self.reset_clips(),
+            EqAction::OptimizeUnlocked => self.optimize(optimize::Parameters::All),
             EqAction::OpenAutoEq => self.open_autoeq(),
+            EqAction::OpenPresets => self.open_presets(),
+            EqAction::OpenStreams => self.open_streams(),
+            EqAction::CycleEq(rotation) => {
+                let len = self.instances.len();
+                let index = match rotation {
+                    Rotation::Clockwise => (self.eq_index + 1) % len,
+                    Rotation::CounterClockwise => (self.eq_index + len - 1) % len,
+                };
+                self.select_instance(index);
+            }
             EqAction::EnterCommandMode => self.enter_command_mode(':'),
+            EqAction::Undo => {
+                self.undo(1);
+                return Ok(ControlFlow::Continue(()));
+            }
+            EqAction::Redo => {
+                self.redo(1);
+                return Ok(ControlFlow::Continue(()));
+            }
+            EqAction::ToggleAB => {
+                let alternate = self.slots.alternate().to_string();
+                self.swap_slot(&alternate);
+                return Ok(ControlFlow::Continue(()));
+            }
         }
 
-        if let Some(node_id) = self.active_node_id {
+        self.eq.update_auto_preamp(self.sample_rate as f64);
+        let key = match self.mouse_drag {
+            Some(drag) => Some(EditKey::Drag(drag.band_idx)),
+            None => EditKey::for_action(action, before_idx),
+        };
+        self.history.record(&before_eq, &self.eq, key);
+
+        if let Some(daemon) = &self.daemon {
+            if self.active_node_id.is_some() {
+                self.sync_daemon_edit(daemon, &before_eq);
+            }
+        } else if let Some(node_id) = self.active_node_id {
             let bypass_changed = before_bypass != self.eq.bypassed;
             let solo_changed = before_solo != self.eq.soloed_index();
 
@@ -634,7 +1381,8 @@ where
             }
         }
 
-        if !self.eq.is_noop()
+        // The daemon hosts the EQ even if it is flat
+        if (self.daemon.is_some() || !self.eq.is_noop())
             && (before_filter_count != self.eq.filters.len() || self.active_node_id.is_none())
         {
             // Filter count changed or no active node - (re)load module
@@ -649,51 +1397,719 @@ where
         Ok(ControlFlow::Continue(()))
     }
 
-    fn perform_autoeq_action(
-        &mut self,
-        action: action::AutoEqAction,
-    ) -> io::Result<ControlFlow<()>> {
-        use action::AutoEqAction;
+    fn undo(&mut self, count: usize) {
+        let before_filter_count = self.eq.filters.len();
+        let n = (0..count)
+            .take_while(|_| self.history.undo(&mut self.eq))
+            .count();
 
-        match action {
-            AutoEqAction::Quit => return Ok(ControlFlow::Break(())),
-            AutoEqAction::ToggleHelp => self.show_help = !self.show_help,
-            AutoEqAction::SelectNext => {
-                if self.autoeq_browser.selected_index + 1
-                    < self.autoeq_browser.filtered_results.len()
-                {
-                    self.autoeq_browser.selected_index += 1;
-                }
-            }
-            AutoEqAction::SelectPrevious => {
-                self.autoeq_browser.selected_index =
-                    self.autoeq_browser.selected_index.saturating_sub(1);
-            }
-            AutoEqAction::ApplyAutoEq => self.apply_selected_autoeq(),
-            AutoEqAction::CycleTarget(rotation) => {
-                if let Some(targets) = &self.autoeq_browser.targets {
-                    let len = targets.len();
-                    match rotation {
-                        Rotation::Clockwise => {
-                            self.autoeq_browser.selected_target_index =
-                                (self.autoeq_browser.selected_target_index + 1) % len;
-                        }
-                        Rotation::CounterClockwise => {
-                            self.autoeq_browser.selected_target_index = self
-                                .autoeq_browser
-                                .selected_target_index
-                                .checked_sub(1)
-                                .unwrap_or(len - 1);
-                        }
-                    }
-                }
-            }
-            AutoEqAction::EnterEqMode => self.enter_eq_mode(),
-            AutoEqAction::EnterFilterMode => self.enter_command_mode('/'),
-            AutoEqAction::EnterCommandMode => self.enter_command_mode(':'),
+        if n == 0 {
+            self.status = Some(Err("already at oldest change".to_string()));
+            return;
         }
 
-        Ok(ControlFlow::Continue(()))
+        self.status = Some(Ok(format!("undid {n} change(s)")));
+        self.sync_restored(before_filter_count);
+    }
+
+    fn redo(&mut self, count: usize) {
+        let before_filter_count = self.eq.filters.len();
+        let n = (0..count)
+            .take_while(|_| self.history.redo(&mut self.eq))
+            .count();
+
+        if n == 0 {
+            self.status = Some(Err("already at newest change".to_string()));
+            return;
+        }
+
+        self.status = Some(Ok(format!("redid {n} change(s)")));
+        self.sync_restored(before_filter_count);
+    }
+
+    /// Show or hide the spectrum analyzer, capturing the tapped node while it is shown
+    fn toggle_spectrum(&mut self) {
+        if self.analyzer.take().is_some() {
+            let _ = self.pw_tx.send(pw::Message::StopCapture);
+            return;
+        }
+
+        self.analyzer = Some(Analyzer::new(&self.config.spectrum));
+        self.start_capture();
+    }
+
+    /// The module may have been reloaded, which removes the nodes being captured and metered
+    fn start_monitoring(&mut self) {
+        if self.analyzer.is_some() {
+            self.start_capture();
+        }
+
+        if let Some(module_name) = &self.module_name
+            && self.config.level_meter
+        {
+            let target = match self.eq.source {
+                Some(_) => pw_module::source_node_name(module_name),
+                None => pw_module::output_node_name(module_name),
+            };
+            if let Err(err) = self.pw_tx.send(pw::Message::StartMeter { target }) {
+                tracing::error!(error = ?err, "failed to start level meter");
+            }
+        }
+    }
+
+    /// Capture the tapped node for the analyzer, once the module is loaded
+    fn start_capture(&mut self) {
+        let Some(module_name) = &self.module_name else {
+            return;
+        };
+
+        let (target, capture_sink) = match (self.spectrum_tap, &self.eq.source) {
+            (Tap::Pre, None) => (pw_module::sink_node_name(module_name), true),
+            (Tap::Post, None) => (pw_module::output_node_name(module_name), false),
+            (
+                Tap::Pre,
+                Some(Source {
+                    device: Some(device),
+                }),
+            ) => (device.clone(), false),
+            // The default source isn't known by name here, so the pre tap falls back to the EQ
+            (_, Some(_)) => (pw_module::source_node_name(module_name), false),
+        };
+
+        if let Err(err) = self.pw_tx.send(pw::Message::StartCapture {
+            target,
+            capture_sink,
+        }) {
+            tracing::error!(error = ?err, "failed to start capture");
+        }
+    }
+
+    fn update_spectrum(&mut self) {
+        let Some(analyzer) = &mut self.analyzer else {
+            return;
+        };
+
+        analyzer.update(&self.capture.lock().unwrap());
+    }
+
+    fn update_levels(&mut self) {
+        // Keep showing the last levels while nothing is playing
+        if let Some(levels) = self.meter.lock().unwrap().take_levels() {
+            self.levels = Some(levels);
+        } else if let Some(levels) = &mut self.levels {
+            levels.peak_db = meter::FLOOR_DB;
+            levels.rms_db = meter::FLOOR_DB;
+        }
+    }
+
+    fn reset_clips(&mut self) {
+        self.meter.lock().unwrap().reset_clips();
+        if let Some(levels) = &mut self.levels {
+            levels.clips = 0;
+        }
+    }
+
+    fn load_curve(&mut self, kind: CurveKind, path: PathBuf) {
+        let notifs_tx = self.notifs_tx.clone();
+        self.schedule(async move {
+            let curve = ReferenceCurve::load(&path)
+                .await
+                .map_err(|err| format!("{err:#}"))?;
+            let _ = notifs_tx.send(Notif::CurveLoaded { kind, curve }).await;
+            Ok(None)
+        });
+    }
+
+    /// Replace the filters with a fit, keeping the selection if it is still valid
+    fn apply_fit(&mut self, fit: optimize::Fit) {
+        let before = self.eq.clone();
+        self.eq.preamp = fit.preamp;
+        self.eq.filters = fit.filters;
+        if let Some(graphic) = self.eq.graphic {
+            for band in &mut self.eq.filters {
+                graphic.conform(band);
+            }
+        }
+        if self.eq.selected_idx >= self.eq.filters.len() {
+            self.eq.selected_idx = 0;
+        }
+        self.eq.update_auto_preamp(self.sample_rate as f64);
+        self.history.record(&before, &self.eq, None);
+        self.load_module();
+        if let Some(node_id) = self.active_node_id {
+            self.sync_all(node_id, self.sample_rate);
+        }
+    }
+
+    fn reference_curves(&mut self) -> Option<(autoeq_api::Measurement, autoeq_api::Measurement)> {
+        match (&self.measurement, &self.target) {
+            (Some(measurement), Some(target)) => {
+                Some((measurement.data.clone(), target.data.clone()))
+            }
+            _ => {
+                self.status = Some(Err("load a measurement and a target first".to_string()));
+                None
+            }
+        }
+    }
+
+    /// Fit bands to the loaded measurement and target in the background
+    fn fit(&mut self, bands: usize) {
+        if self.eq.graphic.is_some() {
+            self.status = Some(Err(
+                "cannot fit bands in graphic mode, use :optimize instead".to_string(),
+            ));
+            return;
+        }
+
+        let Some((measurement, target)) = self.reference_curves() else {
+            return;
+        };
+
+        let constraints = optimize::Constraints {
+            bands,
+            sample_rate: self.sample_rate as f64,
+            ..Default::default()
+        };
+        let notifs_tx = self.notifs_tx.clone();
+        let eq = self.eq.name.clone();
+        self.status = Some(Ok(format!("fitting {bands} bands...")));
+        self.schedule(async move {
+            let fit = tokio::task::spawn_blocking(move || {
+                optimize::fit(&measurement, &target, &constraints)
+            })
+            .await
+            .map_err(|err| err.to_string())?
+            .map_err(|err| format!("{err:#}"))?;
+            let _ = notifs_tx.send(Notif::Fitted { eq, fit }).await;
+            Ok(None)
+        });
+    }
+
+    /// Optimize the unlocked bands against the loaded measurement and target in the background,
+    /// reporting the progress in the status line
+    fn optimize(&mut self, parameters: optimize::Parameters) {
+        if self.optimizing.is_some() {
+            self.status = Some(Err("already optimizing".to_string()));
+            return;
+        }
+
+        let Some((measurement, target)) = self.reference_curves() else {
+            return;
+        };
+
+        // Only the gains of a graphic EQ can change
+        let parameters = match self.eq.graphic {
+            Some(_) => optimize::Parameters::Gain,
+            None => parameters,
+        };
+        let filters = self.eq.filters.clone();
+        let constraints = optimize::Constraints {
+            max_gain: graphic::MAX_GAIN,
+            sample_rate: self.sample_rate as f64,
+            ..Default::default()
+        };
+        let notifs_tx = self.notifs_tx.clone();
+        self.optimizing = Some(self.eq.profile());
+        self.status = Some(Ok("optimizing...".to_string()));
+        self.schedule(async move {
+            let progress_tx = notifs_tx.clone();
+            let mut last_report = Instant::now();
+            let result = tokio::task::spawn_blocking(move || {
+                optimize::refine(
+                    &measurement,
+                    &target,
+                    &filters,
+                    parameters,
+                    &constraints,
+                    |progress| {
+                        if last_report.elapsed() >= PROGRESS_INTERVAL {
+                            last_report = Instant::now();
+                            let _ = progress_tx.try_send(Notif::OptimizeProgress(progress));
+                        }
+                    },
+                )
+            })
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result);
+
+            match result {
+                Ok(fit) => {
+                    let _ = notifs_tx.send(Notif::Optimized(Some(fit))).await;
+                    Ok(None)
+                }
+                Err(err) => {
+                    let _ = notifs_tx.send(Notif::Optimized(None)).await;
+                    Err(format!("{err:#}"))
+                }
+            }
+        });
+    }
+
+    /// Switch between a graphic and a parametric EQ
+    fn set_graphic(&mut self, graphic: Option<Graphic>) {
+        let before = self.eq.clone();
+        self.eq.set_graphic(graphic);
+        self.eq.update_auto_preamp(self.sample_rate as f64);
+        self.history.record(&before, &self.eq, None);
+        self.status = Some(Ok(match graphic {
+            Some(graphic) => format!(
+                "graphic EQ with {} bands ({})",
+                graphic.bands.centres().len(),
+                graphic.q_mode
+            ),
+            None => "parametric EQ".to_string(),
+        }));
+        self.sync_restored(before.filters.len());
+    }
+
+    /// Make the given slot live, keeping the current EQ in the active slot
+    fn swap_slot(&mut self, name: &str) {
+        if name == self.slots.active() {
+            self.status = Some(Err(format!("slot {name} is already active")));
+            return;
+        }
+
+        let sample_rate = self.sample_rate as f64;
+        let before_filter_count = self.eq.filters.len();
+        let outgoing_level = self.eq.mean_gain_db(sample_rate);
+
+        self.slots.swap(name, &mut self.eq, &mut self.history);
+
+        // Level matching takes over the preamp like a manual adjustment would, and can be undone.
+        // It never boosts past the headroom the automatic preamp would leave.
+        let headroom = -self.eq.peak_gain_db(sample_rate).max(0.0) - self.eq.auto_preamp_margin;
+        let target = self.eq.preamp + outgoing_level - self.eq.mean_gain_db(sample_rate);
+        let preamp = target.min(headroom.max(self.eq.preamp));
+        let offset = preamp - self.eq.preamp;
+        if self.config.ab_level_match && offset.abs() >= 0.05 {
+            let before = self.eq.clone();
+            self.eq.auto_preamp = false;
+            self.eq.preamp = preamp;
+            self.history.record(&before, &self.eq, None);
+            self.status = Some(Ok(format!(
+                "switched to slot {name} (level matched {offset:+.1} dB)"
+            )));
+        } else {
+            self.status = Some(Ok(format!("switched to slot {name}")));
+        }
+
+        self.sync_restored(before_filter_count);
+    }
+
+    /// Bring the pipewire node in line with a restored snapshot, which may differ in any band
+    fn sync_restored(&mut self, before_filter_count: usize) {
+        if before_filter_count != self.eq.filters.len() || self.active_node_id.is_none() {
+            // A loaded module must be replaced even if the EQ is now a no-op,
+            // its bands no longer match
+            if !self.eq.is_noop() || self.active_node_id.is_some() {
+                self.load_module();
+            }
+        } else if let Some(node_id) = self.active_node_id {
+            self.sync_all(node_id, self.sample_rate);
+        }
+    }
+
+    fn perform_autoeq_action(
+        &mut self,
+        action: action::AutoEqAction,
+    ) -> io::Result<ControlFlow<()>> {
+        use action::AutoEqAction;
+
+        match action {
+            AutoEqAction::Quit => return Ok(ControlFlow::Break(())),
+            AutoEqAction::ToggleHelp => self.show_help = !self.show_help,
+            AutoEqAction::SelectNext => {
+                if self.autoeq_browser.selected_index + 1
+                    < self.autoeq_browser.filtered_results.len()
+                {
+                    self.autoeq_browser.selected_index += 1;
+                }
+            }
+            AutoEqAction::SelectPrevious => {
+                self.autoeq_browser.selected_index =
+                    self.autoeq_browser.selected_index.saturating_sub(1);
+            }
+            AutoEqAction::ApplyAutoEq => self.apply_selected_autoeq(),
+            AutoEqAction::CycleTarget(rotation) => {
+                if let Some(targets) = &self.autoeq_browser.targets {
+                    let len = targets.len();
+                    match rotation {
+                        Rotation::Clockwise => {
+                            self.autoeq_browser.selected_target_index =
+                                (self.autoeq_browser.selected_target_index + 1) % len;
+                        }
+                        Rotation::CounterClockwise => {
+                            self.autoeq_browser.selected_target_index = self
+                                .autoeq_browser
+                                .selected_target_index
+                                .checked_sub(1)
+                                .unwrap_or(len - 1);
+                        }
+                    }
+                }
+            }
+            AutoEqAction::EnterEqMode => self.enter_eq_mode(),
+            AutoEqAction::EnterFilterMode => self.enter_command_mode('/'),
+            AutoEqAction::EnterCommandMode => self.enter_command_mode(':'),
+        }
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn perform_preset_action(
+        &mut self,
+        action: action::PresetAction,
+    ) -> io::Result<ControlFlow<()>> {
+        use action::PresetAction;
+
+        match action {
+            PresetAction::Quit => return Ok(ControlFlow::Break(())),
+            PresetAction::ToggleHelp => self.show_help = !self.show_help,
+            PresetAction::SelectNext => self.preset_browser.select_next(),
+            PresetAction::SelectPrevious => self.preset_browser.select_previous(),
+            PresetAction::ApplyPreset => match self.preset_browser.selected() {
+                Some(preset) => self.load_preset(self.eq.name.clone(), preset.name.clone()),
+                None => self.status = Some(Err("No preset selected".to_string())),
+            },
+            PresetAction::EnterEqMode => self.enter_eq_mode(),
+            PresetAction::EnterCommandMode => self.enter_command_mode(':'),
+        }
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn open_presets(&mut self) {
+        self.tab = Tab::Presets;
+        self.input_mode = InputMode::Presets;
+        self.preset_browser.load_data(self.notifs_tx.clone());
+    }
+
+    fn perform_stream_action(
+        &mut self,
+        action: action::StreamAction,
+    ) -> io::Result<ControlFlow<()>> {
+        use action::StreamAction;
+
+        match action {
+            StreamAction::Quit => return Ok(ControlFlow::Break(())),
+            StreamAction::ToggleHelp => self.show_help = !self.show_help,
+            StreamAction::SelectNext => self.stream_list.select_next(),
+            StreamAction::SelectPrevious => self.stream_list.select_previous(),
+            StreamAction::ToggleStream => self.toggle_stream(),
+            StreamAction::EnterEqMode => self.enter_eq_mode(),
+            StreamAction::EnterCommandMode => self.enter_command_mode(':'),
+        }
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Names of the EQs in tab order
+    fn eq_names(&self) -> impl Iterator<Item = &str> {
+        self.instances.iter().map(|instance| match instance {
+            Some(instance) => instance.eq.name.as_str(),
+            None => self.eq.name.as_str(),
+        })
+    }
+
+    fn find_instance(&self, name: &str) -> Option<usize> {
+        self.eq_names().position(|eq_name| eq_name == name)
+    }
+
+    /// Show another EQ, returning the state of the one that was shown
+    fn replace_instance(&mut self, instance: Instance) -> Instance {
+        Instance {
+            eq: mem::replace(&mut self.eq, instance.eq),
+            history: mem::replace(&mut self.history, instance.history),
+            slots: mem::replace(&mut self.slots, instance.slots),
+            active_node_id: mem::replace(&mut self.active_node_id, instance.active_node_id),
+            module_name: mem::replace(&mut self.module_name, instance.module_name),
+        }
+    }
+
+    /// Run `f` with the EQ at `index` in the fields of `App`, whether it is shown or not
+    fn with_instance<R>(&mut self, index: usize, f: impl FnOnce(&mut Self) -> R) -> R {
+        let Some(instance) = self.instances[index].take() else {
+            return f(self);
+        };
+
+        let shown = self.replace_instance(instance);
+        let result = f(self);
+        self.instances[index] = Some(self.replace_instance(shown));
+        result
+    }
+
+    /// The ABX test and the optimizer work on the shown EQ
+    fn can_switch_eq(&mut self) -> bool {
+        if self.abx.is_some() || self.optimizing.is_some() {
+            self.status = Some(Err(
+                "cannot switch EQs during an ABX test or while optimizing".to_string(),
+            ));
+            return false;
+        }
+        true
+    }
+
+    fn select_instance(&mut self, index: usize) {
+        if index == self.eq_index || !self.can_switch_eq() {
+            return;
+        }
+
+        let instance = self.instances[index].take().expect("EQ is not shown");
+        self.instances[self.eq_index] = Some(self.replace_instance(instance));
+        self.eq_index = index;
+        self.levels = None;
+        self.start_monitoring();
+        self.status = Some(Ok(format!("editing {}", self.eq.name)));
+    }
+
+    /// Show the EQ with the given name, creating a flat one if there is none
+    fn open_eq(&mut self, name: &str) {
+        if let Some(index) = self.find_instance(name) {
+            self.select_instance(index);
+            return;
+        }
+
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+        {
+            self.status = Some(Err(format!(
+                "invalid EQ name {name}, use letters, digits, - and _"
+            )));
+            return;
+        }
+
+        if !self.can_switch_eq() {
+            return;
+        }
+
+        let mut eq = Eq::new(name, default_filters());
+        eq.formula = self.config.biquad_formula;
+        eq.auto_preamp = self.config.auto_preamp;
+        eq.auto_preamp_margin = self.config.auto_preamp_margin;
+        eq.update_auto_preamp(self.sample_rate as f64);
+        self.instances.push(Some(Instance::new(eq)));
+        self.select_instance(self.instances.len() - 1);
+        // Load it right away so that streams can be routed to its sink
+        self.load_module();
+    }
+
+    /// Close the shown EQ and unload it, the first EQ can't be closed
+    fn close_eq(&mut self) {
+        if self.eq_index == 0 {
+            self.status = Some(Err(format!("{} can't be closed", self.eq.name)));
+            return;
+        }
+
+        if !self.can_switch_eq() {
+            return;
+        }
+
+        let index = self.eq_index - 1;
+        let instance = self.instances[index].take().expect("EQ is not shown");
+        let closed = self.replace_instance(instance);
+        self.instances.remove(self.eq_index);
+        self.eq_index = index;
+        self.levels = None;
+        self.start_monitoring();
+
+        let name = closed.eq.name;
+        if self.daemon.is_some() {
+            self.status = Some(Ok(format!("closed {name}, the daemon still hosts it")));
+            return;
+        }
+
+        if let Err(err) = self
+            .pw_tx
+            .send(pw::Message::RemoveEq { name: name.clone() })
+        {
+            tracing::error!(error = ?err, "failed to remove EQ");
+        }
+        self.status = Some(Ok(format!("closed {name}")));
+    }
+
+    fn open_streams(&mut self) {
+        self.tab = Tab::Streams;
+        self.input_mode = InputMode::Streams;
+    }
+
+    /// Route the selected stream to the shown EQ or around it until the stream goes away
+    fn toggle_stream(&mut self) {
+        let Some(stream) = self.stream_list.selected() else {
+            self.status = Some(Err("No stream selected".to_string()));
+            return;
+        };
+
+        let node_id = stream.node_id;
+        let routed = !stream.routed;
+        // Streams can only be routed to an EQ going the same way, or else to the active one
+        let same_direction =
+            (stream.direction == routing::Direction::Capture) == self.eq.source.is_some();
+        let eq = (routed && same_direction).then(|| self.eq.name.clone());
+        // The daemon routes the streams, the PipeWire thread only keeps track of the toggle
+        if let Some(daemon) = &self.daemon {
+            let _ = daemon.send(daemon::Request::RouteStream {
+                node_id,
+                routed,
+                eq: eq.clone(),
+            });
+        }
+
+        if let Err(err) = self.pw_tx.send(pw::Message::SetStreamRouted {
+            node_id,
+            routed,
+            eq,
+        }) {
+            tracing::error!(error = ?err, "failed to toggle stream routing");
+        }
+    }
+
+    fn load_preset(&mut self, eq: String, name: String) {
+        let notifs_tx = self.notifs_tx.clone();
+        self.schedule(async move {
+            let profile = async { preset::Library::user()?.load(&name).await }
+                .await
+                .map_err(|err| format!("{err:#}"))?;
+            let _ = notifs_tx
+                .send(Notif::PresetLoaded { eq, name, profile })
+                .await;
+            Ok(None)
+        });
+    }
+
+    fn save_preset(&mut self, name: String, force: bool) {
+        let profile = self.eq.profile();
+        self.schedule(async move {
+            let path = async { preset::Library::user()?.save(&name, &profile, force).await }
+                .await
+                .map_err(|err| format!("{err:#}"))?;
+            Ok(Some(format!("saved preset {name} to {}", path.display())))
+        });
+    }
+
+    /// Replace the EQ with a preset or a profile loaded from a file
+    fn apply_profile(&mut self, profile: Profile) -> Result<(), String> {
+        if profile.filters.is_empty() {
+            return Err("no bands to apply".to_string());
+        }
+        if profile.filters.len() > self.eq.max_filters {
+            return Err(format!(
+                "{} bands, at most {} are supported",
+                profile.filters.len(),
+                self.eq.max_filters
+            ));
+        }
+
+        let before = self.eq.clone();
+        self.eq.preamp = profile.preamp;
+        self.eq.graphic = profile.graphic;
+        self.eq.filters = profile.filters;
+        self.eq.selected_idx = 0;
+        self.eq.solo_idx = None;
+        self.eq.update_auto_preamp(self.sample_rate as f64);
+        self.history.record(&before, &self.eq, None);
+        self.sync_restored(before.filters.len());
+        Ok(())
+    }
+
+    fn start_abx(&mut self, b: (String, Eq), num_trials: usize) {
+        if num_trials == 0 {
+            self.status = Some(Err("abx: number of trials must be positive".to_string()));
+            return;
+        }
+
+        let before_filter_count = self.eq.filters.len();
+        let test = abx::AbxTest::new(
+            ("current".to_string(), self.eq.clone()),
+            b,
+            self.eq.clone(),
+            num_trials,
+        );
+
+        self.eq = test.playing_eq().clone();
+        self.abx = Some(test);
+        self.tab = Tab::Abx;
+        self.input_mode = InputMode::Abx;
+        self.status = None;
+        self.sync_restored(before_filter_count);
+    }
+
+    fn perform_abx_action(&mut self, action: action::AbxAction) {
+        use action::AbxAction;
+
+        let Some(test) = &mut self.abx else {
+            return;
+        };
+
+        match action {
+            AbxAction::ToggleHelp => self.show_help = !self.show_help,
+            AbxAction::PlayA => test.playing = abx::Choice::A,
+            AbxAction::PlayB => test.playing = abx::Choice::B,
+            AbxAction::PlayX => test.playing = abx::Choice::X,
+            AbxAction::GuessA | AbxAction::GuessB => {
+                test.guess(matches!(action, AbxAction::GuessA));
+                if test.is_finished() {
+                    self.stop_abx();
+                    return;
+                }
+                self.status = Some(Ok(format!("trial {} recorded", test.completed())));
+            }
+            AbxAction::Stop => {
+                self.stop_abx();
+                return;
+            }
+        }
+
+        // X may have been reassigned even if the selection is unchanged
+        self.eq = test.playing_eq().clone();
+        if let Some(node_id) = self.active_node_id {
+            self.sync_all(node_id, self.sample_rate);
+        }
+    }
+
+    /// Restore the EQ from before the test and report the result
+    fn stop_abx(&mut self) {
+        let Some(test) = self.abx.take() else {
+            return;
+        };
+
+        let before_filter_count = self.eq.filters.len();
+        self.eq = test.original.clone();
+        self.enter_eq_mode();
+        self.sync_restored(before_filter_count);
+
+        if test.completed() == 0 {
+            self.status = Some(Ok("ABX test stopped".to_string()));
+            return;
+        }
+
+        let summary = test.summary();
+        tracing::info!(summary, "ABX test finished");
+        self.status = Some(Ok(summary.clone()));
+
+        if let Some(path) = self.config.abx.log_file.clone() {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            let line = format!("{timestamp}\t{summary}\n");
+            self.schedule(async move {
+                use tokio::io::AsyncWriteExt as _;
+
+                let mut file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .await
+                    .map_err(|err| format!("failed to open ABX log {}: {err}", path.display()))?;
+                file.write_all(line.as_bytes())
+                    .await
+                    .map_err(|err| format!("failed to write ABX log {}: {err}", path.display()))?;
+
+                Ok(None)
+            });
+        }
     }
 
     fn enter_eq_mode(&mut self) {
@@ -787,9 +2203,21 @@ where
     }
 
     fn load_module(&mut self) {
+        if let Some(daemon) = &self.daemon {
+            self.sync_daemon(daemon);
+            return;
+        }
+
         let pw_tx = self.pw_tx.clone();
-        let mut args = self.eq.to_module_args(self.sample_rate);
-        if let Some(sink) = &self.original_default_sink {
+        let mut rates = self.config.sample_rates.clone();
+        rates.push(self.sample_rate);
+        rates.sort_unstable();
+        rates.dedup();
+
+        let mut args = self.eq.to_module_args(rates);
+        if self.eq.source.is_none()
+            && let Some(sink) = &self.original_default_sink
+        {
             args.playback_props.target_object = Some(TargetObject::Serial(sink.object_serial));
         }
 
@@ -806,6 +2234,7 @@ where
 
     fn apply_selected_autoeq(&mut self) {
         if let Some(result) = self.autoeq_browser.apply_selected(
+            self.eq.name.clone(),
             self.http_client.clone(),
             self.notifs_tx.clone(),
             self.sample_rate,
@@ -892,6 +2321,36 @@ where
                     }
                 }
             }
+            InputMode::Presets => {
+                for (key, action) in &self.config.keymap.presets {
+                    if let Some(desc) = action.description() {
+                        action_keys
+                            .entry(desc.to_string())
+                            .or_default()
+                            .push(format!("{key}"));
+                    }
+                }
+            }
+            InputMode::Streams => {
+                for (key, action) in &self.config.keymap.streams {
+                    if let Some(desc) = action.description() {
+                        action_keys
+                            .entry(desc.to_string())
+                            .or_default()
+                            .push(format!("{key}"));
+                    }
+                }
+            }
+            InputMode::Abx => {
+                for (key, action) in &self.config.keymap.abx {
+                    if let Some(desc) = action.description() {
+                        action_keys
+                            .entry(desc.to_string())
+                            .or_default()
+                            .push(format!("{key}"));
+                    }
+                }
+            }
             InputMode::Command => {
                 // Command mode doesn't show help text in the same way
                 return String::new();
@@ -950,17 +2409,178 @@ where
         match &words[..] {
             ["q" | "quit"] => return Ok(ControlFlow::Break(())),
             ["autoeq"] => self.open_autoeq(),
+            ["store"] => {
+                self.slots.store(None, &self.eq);
+                self.status = Some(Ok(format!("stored slot {}", self.slots.active())));
+            }
+            ["store", name] => {
+                self.slots.store(Some(name), &self.eq);
+                self.status = Some(Ok(format!("stored slot {name}")));
+            }
+            ["recall", name] => {
+                let before_filter_count = self.eq.filters.len();
+                if self.slots.recall(name, &mut self.eq, &mut self.history) {
+                    self.status = Some(Ok(format!("recalled slot {name}")));
+                    self.sync_restored(before_filter_count);
+                } else {
+                    self.status = Some(Err(format!("slot {name} is empty")));
+                }
+            }
+            ["swap", name] => self.swap_slot(name),
+            ["abx", against, args @ ..] => {
+                let num_trials = match args {
+                    [] => self.config.abx.trials,
+                    [n] => match n.parse::<usize>() {
+                        Ok(n) => n,
+                        Err(_) => {
+                            self.status =
+                                Some(Err("usage: abx <slot|bypass> [trials]".to_string()));
+                            return Ok(ControlFlow::Continue(()));
+                        }
+                    },
+                    _ => {
+                        self.status = Some(Err("usage: abx <slot|bypass> [trials]".to_string()));
+                        return Ok(ControlFlow::Continue(()));
+                    }
+                };
+
+                let b = if *against == "bypass" {
+                    let mut eq = self.eq.clone();
+                    eq.bypassed = true;
+                    eq
+                } else {
+                    match self.slots.get(against) {
+                        Some(eq) => eq.clone(),
+                        None => {
+                            self.status = Some(Err(format!("slot {against} is empty")));
+                            return Ok(ControlFlow::Continue(()));
+                        }
+                    }
+                };
+
+                self.start_abx((against.to_string(), b), num_trials);
+            }
+            ["abx", ..] => {
+                self.status = Some(Err("usage: abx <slot|bypass> [trials]".to_string()));
+            }
+            ["reset-clips"] => self.reset_clips(),
+            [name @ ("load-measurement" | "load-target"), args @ ..] if !args.is_empty() => {
+                let kind = if *name == "load-measurement" {
+                    CurveKind::Measurement
+                } else {
+                    CurveKind::Target
+                };
+                // Take the rest of the line so that unquoted paths may contain spaces
+                let path = match args {
+                    [path] => path.to_string(),
+                    _ => cmd
+                        .trim_start()
+                        .split_once(' ')
+                        .map_or("", |(_, path)| path)
+                        .trim()
+                        .to_string(),
+                };
+                self.load_curve(kind, path.into())
+            }
+            [cmd @ ("load-measurement" | "load-target"), ..] => {
+                self.status = Some(Err(format!("usage: {cmd} <path>")));
+            }
+            ["fit"] => self.fit(optimize::Constraints::default().bands),
+            ["fit", n] => match n.parse::<usize>() {
+                Ok(bands) if bands > 0 => self.fit(bands),
+                _ => self.status = Some(Err("usage: fit [bands]".to_string())),
+            },
+            ["fit", ..] => self.status = Some(Err("usage: fit [bands]".to_string())),
+            ["optimize"] => self.optimize(optimize::Parameters::All),
+            ["optimize", "gain"] => self.optimize(optimize::Parameters::Gain),
+            ["optimize", "q"] => self.optimize(optimize::Parameters::Q),
+            ["optimize", ..] => {
+                self.status = Some(Err("usage: optimize [gain|q]".to_string()));
+            }
+            ["graphic", "off"] => self.set_graphic(None),
+            ["graphic", bands, args @ ..] if args.len() <= 1 => {
+                let graphic = bands.parse::<graphic::Bands>().and_then(|bands| {
+                    let q_mode = args.first().map_or(Ok(graphic::QMode::default()), |q| {
+                        q.parse::<graphic::QMode>()
+                    })?;
+                    Ok(Graphic::new(bands, q_mode))
+                });
+                match graphic {
+                    Ok(graphic) => self.set_graphic(Some(graphic)),
+                    Err(err) => self.status = Some(Err(format!("{err:#}"))),
+                }
+            }
+            ["graphic", ..] => {
+                self.status = Some(Err(
+                    "usage: graphic <10|15|31> [constant|proportional] | graphic off".to_string(),
+                ));
+            }
+            ["presets"] => self.open_presets(),
+            ["streams"] => self.open_streams(),
+            ["eq", name] => self.open_eq(name),
+            ["eq", ..] => self.status = Some(Err("usage: eq <name>".to_string())),
+            ["close-eq"] => self.close_eq(),
+            ["preset", name] => self.load_preset(self.eq.name.clone(), name.to_string()),
+            ["preset", ..] => self.status = Some(Err("usage: preset <name>".to_string())),
+            [cmd @ ("save-preset" | "save-preset!"), name] => {
+                self.save_preset(name.to_string(), cmd.ends_with('!'))
+            }
+            [cmd @ ("save-preset" | "save-preset!"), ..] => {
+                self.status = Some(Err(format!("usage: {cmd} <name>")));
+            }
+            ["clear-measurement"] => self.measurement = None,
+            ["clear-target"] => self.target = None,
+            ["spectrum"] => self.toggle_spectrum(),
+            ["spectrum", tap @ ("pre" | "post")] => {
+                self.spectrum_tap = if *tap == "pre" { Tap::Pre } else { Tap::Post };
+                match &mut self.analyzer {
+                    // Start over as the levels of the previous tap no longer apply
+                    Some(analyzer) => *analyzer = Analyzer::new(&self.config.spectrum),
+                    None => self.analyzer = Some(Analyzer::new(&self.config.spectrum)),
+                }
+                self.start_capture();
+            }
+            ["spectrum", ..] => {
+                self.status = Some(Err("usage: spectrum [pre|post]".to_string()));
+            }
+            [cmd @ ("store" | "recall" | "swap"), ..] => {
+                self.status = Some(Err(format!("usage: {cmd} <slot>")));
+            }
+            [cmd @ ("u" | "undo" | "redo"), args @ ..] => {
+                let count = match args {
+                    [] => 1,
+                    [n] => match n.parse::<usize>() {
+                        Ok(n) => n,
+                        Err(_) => {
+                            self.status = Some(Err(format!("usage: {cmd} [count]")));
+                            return Ok(ControlFlow::Continue(()));
+                        }
+                    },
+                    _ => {
+                        self.status = Some(Err(format!("usage: {cmd} [count]")));
+                        return Ok(ControlFlow::Continue(()));
+                    }
+                };
+
+                if *cmd == "redo" {
+                    self.redo(count);
+                } else {
+                    self.undo(count);
+                }
+            }
             ["sort"] => match self.tab {
                 Tab::Eq => {
+                    let before = self.eq.clone();
                     self.eq
                         .filters
-                        .sort_by(|a, b| a.frequency.partial_cmp(&b.frequency).unwrap());
+                        .sort_by(|a, b| a.frequency.total_cmp(&b.frequency));
+                    self.history.record(&before, &self.eq, None);
                     if let Some(node_id) = self.active_node_id {
                         self.sync_all(node_id, self.sample_rate);
                     }
                 }
                 // noop for now
-                Tab::AutoEq => {}
+                Tab::AutoEq | Tab::Presets | Tab::Streams | Tab::Abx => {}
             },
             [cmd @ ("w" | "write" | "w!" | "write!"), args @ ..] => {
                 let force = cmd.ends_with('!');
@@ -1022,9 +2642,49 @@ where
     }
 }
 
+/// Bands of a new EQ, flat so that nothing changes until they are adjusted
+fn default_filters() -> Vec<Filter> {
+    vec![
+        Filter {
+            frequency: 50.0,
+            filter_type: FilterType::LowShelf,
+            ..Default::default()
+        },
+        Filter {
+            frequency: 100.0,
+            ..Default::default()
+        },
+        Filter {
+            frequency: 200.0,
+            ..Default::default()
+        },
+        Filter {
+            frequency: 500.0,
+            ..Default::default()
+        },
+        Filter {
+            frequency: 2000.0,
+            ..Default::default()
+        },
+        Filter {
+            frequency: 5000.0,
+            ..Default::default()
+        },
+        Filter {
+            frequency: 10000.0,
+            filter_type: FilterType::HighShelf,
+            ..Default::default()
+        },
+    ]
+}
+
 impl<B: Backend + io::Write> Drop for App<B> {
     fn drop(&mut self) {
-        let _ = execute!(io::stdout(), cursor::SetCursorStyle::DefaultUserShape);
+        let _ = execute!(
+            io::stdout(),
+            cursor::SetCursorStyle::DefaultUserShape,
+            DisableMouseCapture
+        );
     }
 }
 
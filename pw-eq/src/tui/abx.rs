@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+
+use crate::filter::Filter;
+
+use super::eq::Eq;
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// Number of trials in a test
+    pub trials: usize,
+    /// Append a line with the result of each test to this file
+    pub log_file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            trials: 16,
+            log_file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Choice {
+    A,
+    B,
+    X,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Trial {
+    x_is_a: bool,
+    guessed_a: bool,
+}
+
+/// Blind ABX test between two EQ states.
+/// X is randomly assigned to A or B for each trial and the listener guesses which one it is.
+pub(super) struct AbxTest {
+    pub a_label: String,
+    pub b_label: String,
+    a: Eq,
+    b: Eq,
+    /// The live EQ before the test, restored when it ends
+    pub original: Eq,
+    pub playing: Choice,
+    x_is_a: bool,
+    trials: Vec<Trial>,
+    pub num_trials: usize,
+}
+
+impl AbxTest {
+    pub fn new(
+        (a_label, mut a): (String, Eq),
+        (b_label, mut b): (String, Eq),
+        original: Eq,
+        num_trials: usize,
+    ) -> Self {
+        // Pad both sides to the same number of bands so that switching never reloads the module,
+        // which would be audible and give away the identity of X.
+        let len = a.filters.len().max(b.filters.len());
+        pad(&mut a, len);
+        pad(&mut b, len);
+
+        Self {
+            a_label,
+            b_label,
+            a,
+            b,
+            original,
+            playing: Choice::X,
+            x_is_a: fastrand::bool(),
+            trials: Vec::with_capacity(num_trials),
+            num_trials,
+        }
+    }
+
+    pub fn playing_eq(&self) -> &Eq {
+        let is_a = match self.playing {
+            Choice::A => true,
+            Choice::B => false,
+            Choice::X => self.x_is_a,
+        };
+
+        if is_a { &self.a } else { &self.b }
+    }
+
+    /// Record a guess for X and start the next trial with a fresh X
+    pub fn guess(&mut self, guessed_a: bool) {
+        self.trials.push(Trial {
+            x_is_a: self.x_is_a,
+            guessed_a,
+        });
+        self.x_is_a = fastrand::bool();
+        self.playing = Choice::X;
+    }
+
+    /// Number of completed trials
+    pub fn completed(&self) -> usize {
+        self.trials.len()
+    }
+
+    pub fn correct(&self) -> usize {
+        self.trials
+            .iter()
+            .filter(|trial| trial.x_is_a == trial.guessed_a)
+            .count()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.trials.len() >= self.num_trials
+    }
+
+    /// Probability of guessing at least this many trials correctly by chance
+    pub fn p_value(&self) -> f64 {
+        binomial_p_value(self.correct(), self.completed())
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "ABX {} vs {}: {}/{} correct, p = {:.4}",
+            self.a_label,
+            self.b_label,
+            self.correct(),
+            self.completed(),
+            self.p_value()
+        )
+    }
+}
+
+fn pad(eq: &mut Eq, len: usize) {
+    while eq.filters.len() < len {
+        eq.filters.push(Filter {
+            muted: true,
+            ..Default::default()
+        });
+    }
+}
+
+/// One-sided binomial test against guessing, i.e. P(X >= correct) for X ~ B(trials, 0.5)
+pub(super) fn binomial_p_value(correct: usize, trials: usize) -> f64 {
+    if trials == 0 {
+        return 1.0;
+    }
+
+    // Accumulate C(n, k) / 2^n in floating point to avoid overflow for long tests
+    let mut term = 0.5f64.powi(trials as i32);
+    let mut p = 0.0;
+    for k in 0..=trials {
+        if k >= correct {
+            p += term;
+        }
+        term *= (trials - k) as f64 / (k + 1) as f64;
+    }
+
+    p.min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AbxTest, Choice, binomial_p_value};
+    use crate::filter::Filter;
+    use crate::tui::eq::Eq;
+
+    #[test]
+    fn test_binomial_p_value() {
+        assert_eq!(binomial_p_value(0, 0), 1.0);
+        assert_eq!(binomial_p_value(0, 16), 1.0);
+        assert!((binomial_p_value(16, 16) - 1.0 / 65536.0).abs() < 1e-12);
+        // The usual threshold, 12 of 16 is significant at 5%
+        assert!((binomial_p_value(12, 16) - 0.0384063720703125).abs() < 1e-12);
+        assert!(binomial_p_value(11, 16) > 0.05);
+    }
+
+    #[test]
+    fn test_trials() {
+        let a = Eq::new("a", [Filter::default()]);
+        let b = Eq::new("b", [Filter::default(), Filter::default()]);
+        let mut test = AbxTest::new(("A".into(), a.clone()), ("B".into(), b), a, 4);
+
+        test.playing = Choice::A;
+        assert_eq!(test.playing_eq().filters.len(), 2);
+        assert!(test.playing_eq().filters[1].muted);
+
+        while !test.is_finished() {
+            // Cheat by comparing against the known sides
+            let x_is_a = {
+                test.playing = Choice::X;
+                let x = test.playing_eq().filters.clone();
+                test.playing = Choice::A;
+                x == test.playing_eq().filters
+            };
+            test.guess(x_is_a);
+        }
+
+        assert_eq!(test.correct(), 4);
+        assert_eq!(test.summary(), "ABX A vs B: 4/4 correct, p = 0.0625");
+    }
+}
@@ -12,15 +12,31 @@ pub enum EqAction {
     ToggleBypass,
     ToggleMute,
     ToggleSolo,
+    /// Keep the selected band fixed when optimizing
+    ToggleLock,
     SelectIndex(usize),
     AdjustFrequency(Adjustment),
     AdjustGain(Adjustment),
     AdjustQ(Adjustment),
     AdjustPreamp(Adjustment),
+    ToggleAutoPreamp,
     CycleFilterType(Rotation),
     CycleViewMode(Rotation),
+    ToggleBandCurves,
+    ToggleSpectrum,
+    /// Clear the latched clip counter
+    ResetClips,
+    /// Fit the unlocked bands to the loaded measurement and target
+    OptimizeUnlocked,
     OpenAutoEq,
+    OpenPresets,
+    OpenStreams,
+    /// Show the EQ of the next or previous tab
+    CycleEq(Rotation),
     EnterCommandMode,
+    Undo,
+    Redo,
+    ToggleAB,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -37,6 +53,44 @@ pub enum AutoEqAction {
     EnterCommandMode,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PresetAction {
+    Quit,
+    ToggleHelp,
+    SelectNext,
+    SelectPrevious,
+    ApplyPreset,
+    EnterEqMode,
+    EnterCommandMode,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StreamAction {
+    Quit,
+    ToggleHelp,
+    SelectNext,
+    SelectPrevious,
+    /// Route the selected stream to the EQ or around it, overriding the routing rules
+    ToggleStream,
+    EnterEqMode,
+    EnterCommandMode,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AbxAction {
+    ToggleHelp,
+    PlayA,
+    PlayB,
+    PlayX,
+    GuessA,
+    GuessB,
+    /// End the test early, reporting the trials so far
+    Stop,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum CommandAction {
@@ -83,16 +137,28 @@ impl EqAction {
             EqAction::ToggleBypass => Some("bypass"),
             EqAction::ToggleMute => Some("mute"),
             EqAction::ToggleSolo => Some("solo"),
+            EqAction::ToggleLock => Some("lock"),
             EqAction::SelectIndex(_) => Some("select"),
             EqAction::AdjustFrequency(_) => Some("freq"),
             EqAction::AdjustGain(Adjustment::Set(0.0)) => Some("zero gain"),
             EqAction::AdjustGain(_) => Some("gain"),
             EqAction::AdjustQ(_) => Some("Q"),
             EqAction::AdjustPreamp(_) => Some("preamp"),
+            EqAction::ToggleAutoPreamp => Some("auto preamp"),
             EqAction::CycleFilterType(..) => Some("cycle type"),
             EqAction::CycleViewMode(..) => Some("cycle view"),
+            EqAction::ToggleBandCurves => Some("band curves"),
+            EqAction::ToggleSpectrum => Some("spectrum"),
+            EqAction::ResetClips => Some("reset clips"),
+            EqAction::OptimizeUnlocked => Some("optimize"),
             EqAction::OpenAutoEq => Some("autoeq"),
+            EqAction::OpenPresets => Some("presets"),
+            EqAction::OpenStreams => Some("streams"),
+            EqAction::CycleEq(..) => Some("switch EQ"),
             EqAction::EnterCommandMode => None,
+            EqAction::Undo => Some("undo"),
+            EqAction::Redo => Some("redo"),
+            EqAction::ToggleAB => Some("A/B"),
         }
     }
 }
@@ -113,3 +179,48 @@ impl AutoEqAction {
         }
     }
 }
+
+impl PresetAction {
+    /// Returns a short description of the action for help text
+    pub fn description(&self) -> Option<&'static str> {
+        match self {
+            PresetAction::Quit => Some("quit"),
+            PresetAction::ToggleHelp => Some("help"),
+            PresetAction::SelectNext => Some("next"),
+            PresetAction::SelectPrevious => Some("prev"),
+            PresetAction::ApplyPreset => Some("apply"),
+            PresetAction::EnterEqMode => Some("close"),
+            PresetAction::EnterCommandMode => None,
+        }
+    }
+}
+
+impl StreamAction {
+    /// Returns a short description of the action for help text
+    pub fn description(&self) -> Option<&'static str> {
+        match self {
+            StreamAction::Quit => Some("quit"),
+            StreamAction::ToggleHelp => Some("help"),
+            StreamAction::SelectNext => Some("next"),
+            StreamAction::SelectPrevious => Some("prev"),
+            StreamAction::ToggleStream => Some("toggle EQ"),
+            StreamAction::EnterEqMode => Some("close"),
+            StreamAction::EnterCommandMode => None,
+        }
+    }
+}
+
+impl AbxAction {
+    /// Returns a short description of the action for help text
+    pub fn description(&self) -> Option<&'static str> {
+        match self {
+            AbxAction::ToggleHelp => Some("help"),
+            AbxAction::PlayA => Some("play A"),
+            AbxAction::PlayB => Some("play B"),
+            AbxAction::PlayX => Some("play X"),
+            AbxAction::GuessA => Some("X is A"),
+            AbxAction::GuessB => Some("X is B"),
+            AbxAction::Stop => Some("stop"),
+        }
+    }
+}
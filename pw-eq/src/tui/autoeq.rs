@@ -135,8 +135,10 @@ impl AutoEqBrowser {
         });
     }
 
+    /// Fetch the AutoEQ response for the selected headphone and target, to be applied to `eq`
     pub fn apply_selected(
         &self,
+        eq: String,
         http_client: reqwest::Client,
         notifs_tx: mpsc::Sender<Notif>,
         sample_rate: u32,
@@ -162,7 +164,9 @@ impl AutoEqBrowser {
 
             match autoeq_api::equalize(&http_client, &request).await {
                 Ok(response) => {
-                    let _ = notifs_tx.send(Notif::AutoEqLoaded { name, response }).await;
+                    let _ = notifs_tx
+                        .send(Notif::AutoEqLoaded { eq, name, response })
+                        .await;
                 }
                 Err(err) => {
                     let _ = notifs_tx.send(Notif::Error(err.into())).await;
@@ -281,6 +285,7 @@ pub fn param_eq_to_filters(response: autoeq_api::ParametricEq) -> Vec<Filter> {
                 q: f.q,
                 filter_type,
                 muted: false,
+                locked: false,
             }
         })
         .collect()
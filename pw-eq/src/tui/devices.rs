@@ -0,0 +1,97 @@
+use crate::pw::OutputDevice;
+use crate::routing::properties_match;
+
+/// Preset to load when the default output device matches, like routing rules match streams
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Rule {
+    /// `node.name` of the sink, e.g. `alsa_output.usb-FiiO_K5_Pro-00.analog-stereo`
+    pub node_name: Option<String>,
+    /// `device.description` of the device, e.g. `Built-in Audio`
+    pub description: Option<String>,
+    /// Address of a bluetooth device, e.g. `AC:80:0A:12:34:56`
+    pub bluetooth_address: Option<String>,
+    /// Name of the preset to load, see `pw-eq preset list`
+    pub preset: String,
+}
+
+impl Rule {
+    fn matches(&self, device: &OutputDevice) -> bool {
+        properties_match([
+            (self.node_name.as_deref(), Some(device.node_name.as_str())),
+            (self.description.as_deref(), device.description.as_deref()),
+            (
+                self.bluetooth_address.as_deref(),
+                device.bluetooth_address.as_deref(),
+            ),
+        ])
+    }
+}
+
+/// The first rule matching the device
+pub fn matching<'a>(rules: &'a [Rule], device: &OutputDevice) -> Option<&'a Rule> {
+    rules.iter().find(|rule| rule.matches(device))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rule, matching};
+    use crate::pw::OutputDevice;
+
+    #[test]
+    fn test_matching() {
+        let rules = [
+            Rule {
+                bluetooth_address: Some("ac:80:0a:12:34:56".to_string()),
+                preset: "headphones".to_string(),
+                ..Default::default()
+            },
+            Rule {
+                node_name: Some("alsa_output.usb-dac".to_string()),
+                description: Some("USB DAC".to_string()),
+                preset: "dac".to_string(),
+                ..Default::default()
+            },
+            Rule {
+                description: Some("Built-in Audio".to_string()),
+                preset: "speakers".to_string(),
+                ..Default::default()
+            },
+            Rule {
+                preset: "everything".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let device = |node_name: &str, description: &str, address: Option<&str>| OutputDevice {
+            node_id: 42,
+            node_name: node_name.to_string(),
+            object_serial: 100,
+            description: Some(description.to_string()),
+            bluetooth_address: address.map(str::to_string),
+        };
+        let preset = |device| matching(&rules, &device).map(|rule| rule.preset.as_str());
+
+        assert_eq!(
+            preset(device(
+                "bluez_output.AC_80_0A_12_34_56.1",
+                "WH-1000XM4",
+                Some("AC:80:0A:12:34:56")
+            )),
+            Some("headphones")
+        );
+        assert_eq!(
+            preset(device("alsa_output.usb-dac", "USB DAC", None)),
+            Some("dac")
+        );
+        // All properties of a rule must match
+        assert_eq!(
+            preset(device("alsa_output.usb-dac", "Other DAC", None)),
+            None
+        );
+        assert_eq!(
+            preset(device("alsa_output.pci", "built-in audio", None)),
+            Some("speakers")
+        );
+    }
+}
@@ -1,13 +1,18 @@
-use super::{App, Eq, InputMode, Tab, ViewMode, theme::Theme};
+use super::measurement::ReferenceCurve;
+use super::{App, Eq, InputMode, Tab, ViewMode, abx::Choice, theme::Theme};
+use crate::graphic::{self, Graphic};
+use crate::spectrum::{self, Analyzer, Tap};
+use crate::{preset, routing};
 use pw_util::module::FilterType;
 use ratatui::{
     layout::Direction,
     prelude::{Backend, Constraint, Layout, Rect},
-    style::{Modifier, Style},
+    style::{Color, Modifier, Style},
     symbols::Marker,
     text::{Line, Span},
     widgets::{
-        Axis, Block, Borders, Cell, Chart, Dataset, GraphType, Padding, Paragraph, Row, Table, Wrap,
+        Axis, Block, Borders, Cell, Chart, Dataset, GraphType, Padding, Paragraph, Row, Table,
+        TableState, Wrap,
     },
 };
 use std::io;
@@ -46,7 +51,13 @@ where
                 // Buffer always contains the prefix (: or /)
                 Paragraph::new(self.command_buffer.clone()).style(Style::default().fg(theme.footer))
             }
-            InputMode::Eq | InputMode::AutoEq if self.status.is_some() && self.show_help => {
+            InputMode::Eq
+            | InputMode::AutoEq
+            | InputMode::Presets
+            | InputMode::Streams
+            | InputMode::Abx
+                if self.status.is_some() && self.show_help =>
+            {
                 // Show both help text and status
                 let (msg, color) = match self.status.as_ref().unwrap() {
                     Ok(msg) => (msg.to_owned(), theme.status_ok),
@@ -58,17 +69,35 @@ where
                 ];
                 Paragraph::new(lines).wrap(Wrap { trim: true })
             }
-            InputMode::Eq | InputMode::AutoEq if self.status.is_some() => {
+            InputMode::Eq
+            | InputMode::AutoEq
+            | InputMode::Presets
+            | InputMode::Streams
+            | InputMode::Abx
+                if self.status.is_some() =>
+            {
                 let (msg, color) = match self.status.as_ref().unwrap() {
                     Ok(msg) => (msg.to_owned(), theme.status_ok),
                     Err(msg) => (msg.to_owned(), theme.status_error),
                 };
                 Paragraph::new(msg).style(Style::default().fg(color))
             }
-            InputMode::Eq | InputMode::AutoEq if self.show_help => Paragraph::new(help_text)
-                .style(Style::default().fg(theme.help))
-                .wrap(Wrap { trim: true }),
-            InputMode::Eq | InputMode::AutoEq => {
+            InputMode::Eq
+            | InputMode::AutoEq
+            | InputMode::Presets
+            | InputMode::Streams
+            | InputMode::Abx
+                if self.show_help =>
+            {
+                Paragraph::new(help_text)
+                    .style(Style::default().fg(theme.help))
+                    .wrap(Wrap { trim: true })
+            }
+            InputMode::Eq
+            | InputMode::AutoEq
+            | InputMode::Presets
+            | InputMode::Streams
+            | InputMode::Abx => {
                 Paragraph::new("Press ? for help").style(Style::default().fg(theme.footer))
             }
         }
@@ -78,6 +107,9 @@ where
         match self.tab {
             Tab::Eq => self.draw_eq_tab(),
             Tab::AutoEq => self.draw_autoeq_tab(),
+            Tab::Presets => self.draw_presets_tab(),
+            Tab::Streams => self.draw_streams_tab(),
+            Tab::Abx => self.draw_abx_tab(),
         }
     }
 
@@ -95,7 +127,13 @@ where
 
         let help_len = help_text.len();
         let footer = self.render_footer(help_text);
+        let eq_names = self.eq_names().map(str::to_string).collect::<Vec<_>>();
+        let eq_index = self.eq_index;
+        let table_state = &mut self.table_state;
+        table_state.select(Some(eq.selected_idx));
 
+        let mut table = None;
+        let mut chart = None;
         self.term.draw(|f| {
             // Set background color for the entire frame
             f.render_widget(
@@ -123,11 +161,27 @@ where
                 theme.gain_neutral
             };
 
-            let mut header_spans = vec![
+            let mut header_spans = vec![Span::styled(
+                "PipeWire EQ:",
+                Style::default().fg(theme.header),
+            )];
+            // Every EQ is listed like a tab, the shown one highlighted
+            for (index, name) in eq_names.into_iter().enumerate() {
+                let style = if index == eq_index {
+                    Style::default()
+                        .fg(theme.header)
+                        .bg(theme.selected_row)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(theme.dimmed)
+                };
+                header_spans.push(Span::raw(" "));
+                header_spans.push(Span::styled(name, style));
+            }
+            header_spans.extend([
                 Span::styled(
                     format!(
-                        "PipeWire EQ: {} | Bands: {}/{} | Sample Rate: {:.0} Hz | Preamp: ",
-                        eq.name,
+                        " | Bands: {}/{} | Sample Rate: {:.0} Hz | Preamp: ",
                         eq.filters.len(),
                         eq.max_filters,
                         sample_rate
@@ -138,7 +192,44 @@ where
                     format!("{} dB", Gain(eq.preamp)),
                     Style::default().fg(preamp_color),
                 ),
-            ];
+            ]);
+
+            if eq.auto_preamp {
+                header_spans.push(Span::styled(" (auto)", Style::default().fg(theme.header)));
+            }
+
+            if let Some(levels) = &self.levels {
+                let level_color = if levels.peak_db >= 0.0 {
+                    theme.status_error
+                } else if levels.peak_db >= -6.0 {
+                    theme.q_value
+                } else {
+                    theme.gain_positive
+                };
+
+                header_spans.push(Span::styled(" | Out ", Style::default().fg(theme.header)));
+                header_spans.push(Span::styled(
+                    level_bar(levels.peak_db, levels.rms_db, 10),
+                    Style::default().fg(level_color),
+                ));
+                header_spans.push(Span::styled(
+                    format!(
+                        " {:.1}/{:.1} dB",
+                        levels.peak_db.max(-60.0),
+                        levels.rms_db.max(-60.0)
+                    ),
+                    Style::default().fg(theme.header),
+                ));
+
+                if levels.clips > 0 {
+                    header_spans.push(Span::styled(
+                        format!(" | CLIP {}", levels.clips),
+                        Style::default()
+                            .fg(theme.status_error)
+                            .add_modifier(Modifier::BOLD),
+                    ));
+                }
+            }
 
             if eq.bypassed {
                 header_spans.push(Span::styled(
@@ -149,6 +240,26 @@ where
                 ));
             }
 
+            if !self.slots.is_empty() {
+                header_spans.push(Span::styled(
+                    format!(" | Slot {}", self.slots.active()),
+                    Style::default()
+                        .fg(theme.header)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
+
+            if self.analyzer.is_some() {
+                let tap = match self.spectrum_tap {
+                    Tap::Pre => "pre",
+                    Tap::Post => "post",
+                };
+                header_spans.push(Span::styled(
+                    format!(" | Spectrum ({tap})"),
+                    Style::default().fg(theme.header),
+                ));
+            }
+
             if let Some(solo_idx) = eq.soloed_index() {
                 header_spans.push(Span::styled(
                     format!(" | SOLO {}", solo_idx + 1),
@@ -166,9 +277,37 @@ where
             );
             f.render_widget(header, chunks[0]);
 
-            draw_filters_table(f, chunks[1], eq, view_mode, sample_rate, theme);
+            match eq.graphic {
+                Some(graphic) => draw_graphic_sliders(f, chunks[1], eq, graphic, theme),
+                None => {
+                    table = Some(chunks[1]);
+                    draw_filters_table(
+                        f,
+                        chunks[1],
+                        eq,
+                        view_mode,
+                        sample_rate,
+                        theme,
+                        table_state,
+                    );
+                }
+            }
 
-            draw_frequency_response(f, chunks[2], eq, sample_rate, theme);
+            let options = ChartOptions {
+                view_mode,
+                band_curves: self.show_band_curves,
+                spectrum: self.analyzer.as_ref(),
+                measurement: self.measurement.as_ref(),
+                target: self.target.as_ref(),
+            };
+            chart = Some(draw_frequency_response(
+                f,
+                chunks[2],
+                eq,
+                &options,
+                sample_rate,
+                theme,
+            ));
 
             f.render_widget(footer.clone(), chunks[3]);
 
@@ -176,6 +315,100 @@ where
                 f.set_cursor_position((chunks[3].x + self.command_cursor_pos as u16, chunks[3].y));
             }
         })?;
+
+        self.table_area = table;
+        self.chart_geometry = chart;
+        Ok(())
+    }
+
+    fn draw_abx_tab(&mut self) -> anyhow::Result<()> {
+        let theme = &self.config.theme;
+        let Some(test) = &self.abx else {
+            return Ok(());
+        };
+
+        let help_text = if self.show_help {
+            self.generate_help_text()
+        } else {
+            String::new()
+        };
+
+        let help_len = help_text.len();
+        let footer = self.render_footer(help_text);
+
+        self.term.draw(|f| {
+            f.render_widget(
+                Block::default().style(Style::default().bg(theme.background)),
+                f.area(),
+            );
+            let footer_height =
+                Self::footer_height(help_len, self.show_help, self.status.is_some(), f.area().width);
+
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Length(3),             // Header
+                    Constraint::Min(5),                // Current selection
+                    Constraint::Length(footer_height), // Footer
+                ])
+                .split(f.area());
+
+            // Deliberately shows nothing that could reveal the identity of X
+            let header = Paragraph::new(Line::from(Span::styled(
+                format!(
+                    "ABX Test: A = {} | B = {} | Trial {}/{}",
+                    test.a_label,
+                    test.b_label,
+                    (test.completed() + 1).min(test.num_trials),
+                    test.num_trials
+                ),
+                Style::default().fg(theme.header),
+            )))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(theme.border))
+                    .padding(Padding::horizontal(1)),
+            );
+            f.render_widget(header, chunks[0]);
+
+            let choice = |choice: Choice, label: &'static str| {
+                let style = if test.playing == choice {
+                    Style::default()
+                        .fg(theme.text)
+                        .bg(theme.selected_row)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(theme.dimmed)
+                };
+                Span::styled(format!("  {label}  "), style)
+            };
+
+            let body = Paragraph::new(vec![
+                Line::from(vec![
+                    choice(Choice::A, "A"),
+                    Span::raw(" "),
+                    choice(Choice::B, "B"),
+                    Span::raw(" "),
+                    choice(Choice::X, "X"),
+                ]),
+                Line::default(),
+                Line::from(Span::styled(
+                    "Is X the same as A or B?",
+                    Style::default().fg(theme.text),
+                )),
+            ])
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(theme.border))
+                    .padding(Padding::uniform(1)),
+            );
+            f.render_widget(body, chunks[1]);
+
+            f.render_widget(footer.clone(), chunks[2]);
+        })?;
+
         Ok(())
     }
 
@@ -298,6 +531,220 @@ where
 
         Ok(())
     }
+
+    fn draw_presets_tab(&mut self) -> anyhow::Result<()> {
+        let theme = &self.config.theme;
+        let browser = &self.preset_browser;
+        let help_text = self.generate_help_text();
+
+        let help_len = help_text.len();
+        let footer = self.render_footer(help_text);
+
+        self.term.draw(|f| {
+            f.render_widget(
+                Block::default().style(Style::default().bg(theme.background)),
+                f.area(),
+            );
+
+            let footer_height =
+                Self::footer_height(help_len, self.show_help, self.status.is_some(), f.area().width);
+
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Length(3),             // Header
+                    Constraint::Min(10),               // Presets table
+                    Constraint::Length(footer_height), // Footer
+                ])
+                .split(f.area());
+
+            let header = Paragraph::new(Line::from(vec![Span::styled(
+                "Presets",
+                Style::default()
+                    .fg(theme.header)
+                    .add_modifier(Modifier::BOLD),
+            )]))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(theme.border))
+                    .padding(Padding::horizontal(1)),
+            );
+            f.render_widget(header, chunks[0]);
+
+            if browser.loading && browser.presets.is_empty() {
+                let loading = Paragraph::new("Loading presets...").block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(theme.border))
+                        .padding(Padding::horizontal(1)),
+                );
+                f.render_widget(loading, chunks[1]);
+            } else {
+                let rows: Vec<Row> = browser
+                    .presets
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, preset)| {
+                        let style = if idx == browser.selected_index {
+                            Style::default().bg(theme.selected_row)
+                        } else {
+                            Style::default()
+                        }
+                        .fg(theme.text);
+
+                        Row::new(vec![
+                            Cell::from(preset.name.as_str()),
+                            Cell::from(match preset.source {
+                                preset::Source::Builtin => "built-in",
+                                preset::Source::User => "user",
+                            }),
+                            Cell::from(preset.description.as_str()),
+                        ])
+                        .style(style)
+                    })
+                    .collect();
+
+                let table = Table::new(
+                    rows,
+                    [
+                        Constraint::Percentage(25),
+                        Constraint::Length(10),
+                        Constraint::Fill(1),
+                    ],
+                )
+                .header(
+                    Row::new(vec!["Name", "Source", "Description"])
+                        .style(Style::default().add_modifier(Modifier::BOLD)),
+                )
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(theme.border))
+                        .title(format!(" {} presets ", browser.presets.len()))
+                        .padding(Padding::horizontal(1)),
+                );
+                f.render_widget(table, chunks[1]);
+            }
+
+            f.render_widget(footer.clone(), chunks[2]);
+        })?;
+
+        Ok(())
+    }
+
+    fn draw_streams_tab(&mut self) -> anyhow::Result<()> {
+        let theme = &self.config.theme;
+        let list = &self.stream_list;
+        let help_text = self.generate_help_text();
+
+        let help_len = help_text.len();
+        let footer = self.render_footer(help_text);
+
+        self.term.draw(|f| {
+            f.render_widget(
+                Block::default().style(Style::default().bg(theme.background)),
+                f.area(),
+            );
+
+            let footer_height =
+                Self::footer_height(help_len, self.show_help, self.status.is_some(), f.area().width);
+
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Length(3),             // Header
+                    Constraint::Min(10),               // Streams table
+                    Constraint::Length(footer_height), // Footer
+                ])
+                .split(f.area());
+
+            let header = Paragraph::new(Line::from(vec![Span::styled(
+                "Streams",
+                Style::default()
+                    .fg(theme.header)
+                    .add_modifier(Modifier::BOLD),
+            )]))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(theme.border))
+                    .padding(Padding::horizontal(1)),
+            );
+            f.render_widget(header, chunks[0]);
+
+            let rows: Vec<Row> = list
+                .streams
+                .iter()
+                .enumerate()
+                .map(|(idx, stream)| {
+                    let style = if idx == list.selected_index {
+                        Style::default().bg(theme.selected_row)
+                    } else {
+                        Style::default()
+                    }
+                    .fg(if stream.routed {
+                        theme.text
+                    } else {
+                        theme.dimmed
+                    });
+
+                    let props = &stream.props;
+                    Row::new(vec![
+                        Cell::from(match stream.direction {
+                            routing::Direction::Playback => "playback",
+                            routing::Direction::Capture => "capture",
+                        }),
+                        Cell::from(props.application_name.as_deref().unwrap_or("-")),
+                        Cell::from(props.process_binary.as_deref().unwrap_or("-")),
+                        Cell::from(props.media_role.as_deref().unwrap_or("-")),
+                        Cell::from(props.node_name.as_str()),
+                        Cell::from(match (&stream.eq, stream.routed) {
+                            (_, false) => "bypass",
+                            (Some(eq), true) => eq.as_str(),
+                            (None, true) => "active EQ",
+                        }),
+                    ])
+                    .style(style)
+                })
+                .collect();
+
+            let table = Table::new(
+                rows,
+                [
+                    Constraint::Length(9),
+                    Constraint::Percentage(20),
+                    Constraint::Percentage(15),
+                    Constraint::Length(14),
+                    Constraint::Fill(1),
+                    Constraint::Length(12),
+                ],
+            )
+            .header(
+                Row::new(vec![
+                    "Type",
+                    "Application",
+                    "Binary",
+                    "Role",
+                    "Node",
+                    "Route",
+                ])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(theme.border))
+                    .title(format!(" {} streams ", list.streams.len()))
+                    .padding(Padding::horizontal(1)),
+            );
+            f.render_widget(table, chunks[1]);
+
+            f.render_widget(footer.clone(), chunks[2]);
+        })?;
+
+        Ok(())
+    }
 }
 
 fn draw_filters_table(
@@ -307,6 +754,7 @@ fn draw_filters_table(
     view_mode: ViewMode,
     sample_rate: u32,
     theme: &Theme,
+    state: &mut TableState,
 ) {
     let rows: Vec<Row> = eq_state
         .filters
@@ -361,7 +809,8 @@ fn draw_filters_table(
 
             // Create base cells
             let mut cells = vec![
-                Cell::from(format!("{}", idx + 1)).style(
+                // Locked bands are marked for the optimizer
+                Cell::from(format!("{}{}", idx + 1, if band.locked { "*" } else { "" })).style(
                     Style::default()
                         .fg(num_color)
                         .add_modifier(if is_selected && !is_dimmed {
@@ -407,7 +856,7 @@ fn draw_filters_table(
             // Add expert mode columns
             if matches!(view_mode, ViewMode::Expert) {
                 // Calculate biquad coefficients
-                let coeff = band.biquad_coeffs(sample_rate as f64);
+                let coeff = band.biquad_coeffs_with(eq_state.formula, sample_rate as f64);
 
                 cells.push(
                     Cell::from(format!("{:.6}", coeff.b0)).style(Style::default().fg(coeff_color)),
@@ -488,79 +937,577 @@ fn draw_filters_table(
             .padding(Padding::horizontal(1)),
     );
 
-    f.render_widget(table, area);
+    f.render_stateful_widget(table, area, state);
+}
+
+/// Vertical sliders of a graphic EQ, one column per band with the centre frequencies below
+fn draw_graphic_sliders(
+    f: &mut ratatui::Frame,
+    area: Rect,
+    eq_state: &Eq,
+    graphic: Graphic,
+    theme: &Theme,
+) {
+    let selected = &eq_state.filters[eq_state.selected_idx];
+    let title = format!(
+        " {} bands, {} | {} Hz {} dB ",
+        eq_state.filters.len(),
+        graphic.q_mode,
+        FrequencyLabel(selected.frequency),
+        Gain(selected.gain),
+    );
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.border))
+        .title(Span::styled(title, Style::default().fg(theme.header)))
+        .padding(Padding::horizontal(1));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    // The bottom row holds the labels
+    let rows = inner.height.saturating_sub(1) as usize;
+    if rows == 0 || eq_state.filters.is_empty() {
+        return;
+    }
+
+    let column_width = (inner.width as usize / eq_state.filters.len()).max(1);
+    let db_per_row = 2.0 * graphic::MAX_GAIN / rows as f64;
+
+    let mut lines = (0..rows)
+        .map(|row| {
+            // Rows grow downwards
+            let top = graphic::MAX_GAIN - row as f64 * db_per_row;
+            let bottom = top - db_per_row;
+            let spans = eq_state
+                .filters
+                .iter()
+                .enumerate()
+                .map(|(idx, band)| {
+                    let (lo, hi) = (band.gain.min(0.0), band.gain.max(0.0));
+                    let is_dimmed = eq_state.is_band_dimmed(idx);
+                    let (symbol, color) = if hi > lo && top > lo && bottom < hi {
+                        let color = if is_dimmed {
+                            theme.dimmed
+                        } else if band.gain > 0.0 {
+                            theme.gain_positive
+                        } else {
+                            theme.gain_negative
+                        };
+                        ('█', color)
+                    } else if top > 0.0 && bottom <= 0.0 {
+                        ('─', theme.gain_neutral)
+                    } else {
+                        ('│', theme.border)
+                    };
+
+                    let style = Style::default().fg(color);
+                    let style = if idx == eq_state.selected_idx {
+                        style.bg(theme.selected_row)
+                    } else {
+                        style
+                    };
+                    Span::styled(format!("{symbol:^column_width$}"), style)
+                })
+                .collect::<Vec<_>>();
+            Line::from(spans)
+        })
+        .collect::<Vec<_>>();
+
+    // Label every band if there is room, otherwise every few bands
+    let labels = eq_state
+        .filters
+        .iter()
+        .map(|band| FrequencyLabel(band.frequency).to_string())
+        .collect::<Vec<_>>();
+    let label_width = labels.iter().map(String::len).max().unwrap_or(0) + 1;
+    let stride = label_width.div_ceil(column_width);
+    let mut label_row = vec![' '; eq_state.filters.len() * column_width + label_width];
+    for (idx, label) in labels.iter().enumerate().step_by(stride) {
+        let start = idx * column_width;
+        label_row[start..start + label.len()].copy_from_slice(&label.chars().collect::<Vec<_>>());
+    }
+    lines.push(Line::styled(
+        label_row.into_iter().collect::<String>(),
+        Style::default().fg(theme.frequency),
+    ));
+
+    f.render_widget(Paragraph::new(lines), inner);
+}
+
+/// Compact frequency for narrow columns, e.g. 31.5, 800 or 12.5k
+struct FrequencyLabel(f64);
+
+impl std::fmt::Display for FrequencyLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.0 >= 1000.0 {
+            write!(f, "{}k", self.0 / 1000.0)
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+/// What to draw on the chart besides the summed response
+struct ChartOptions<'a> {
+    view_mode: ViewMode,
+    /// Overlay each band's own response
+    band_curves: bool,
+    spectrum: Option<&'a Analyzer>,
+    measurement: Option<&'a ReferenceCurve>,
+    target: Option<&'a ReferenceCurve>,
 }
 
 fn draw_frequency_response(
     f: &mut ratatui::Frame,
     area: Rect,
     eq: &Eq,
+    options: &ChartOptions<'_>,
     sample_rate: u32,
     theme: &Theme,
-) {
+) -> ChartGeometry {
     const NUM_POINTS: usize = 200;
 
     // Generate frequency response curve data
-    let curve_data = eq.frequency_response_curve(NUM_POINTS, sample_rate as f64);
+    let magnitude = eq.frequency_response_curve(NUM_POINTS, sample_rate as f64);
+
+    // Mark each band so it can be picked with the mouse
+    let markers = (0..eq.filters.len())
+        .map(|idx| BandMarker {
+            point: eq.band_marker(idx, sample_rate as f64),
+            dimmed: eq.is_band_dimmed(idx),
+        })
+        .collect::<Vec<_>>();
+
+    // Bands that are not audible have no effect on the summed curve, so their own curves are left out
+    let band_curves = if options.band_curves && !eq.bypassed {
+        (0..eq.filters.len())
+            .filter(|&idx| !eq.is_band_effectively_muted(idx))
+            .map(|idx| eq.band_response_curve(idx, NUM_POINTS, sample_rate as f64))
+            .collect()
+    } else {
+        vec![]
+    };
+
+    let spectrum = options
+        .spectrum
+        .map(|analyzer| analyzer.curve(Eq::curve_frequencies(NUM_POINTS)));
+
+    // Measurement and target are aligned at 1 kHz so they can be compared with the EQ curve
+    let frequencies = || Eq::curve_frequencies(NUM_POINTS);
+    let mut references = vec![];
+    if let Some(target) = options.target {
+        references.push(NamedCurve {
+            name: format!("target: {}", target.name),
+            points: target.normalized_curve(frequencies()),
+            color: theme.target,
+        });
+    }
+
+    if let Some(measurement) = options.measurement {
+        let points = measurement.normalized_curve(frequencies());
+        // What the EQ is expected to turn the measurement into
+        if !eq.bypassed {
+            references.push(NamedCurve {
+                name: "measurement + EQ".to_string(),
+                points: points
+                    .iter()
+                    .zip(&magnitude)
+                    .map(|(&(freq, level), &(_, gain))| (freq, level + gain))
+                    .collect(),
+                color: theme.predicted,
+            });
+        }
+
+        references.push(NamedCurve {
+            name: format!("measurement: {}", measurement.name),
+            points,
+            color: theme.measurement,
+        });
+    }
+
+    let overlay = Overlay {
+        markers: &markers,
+        selected_idx: eq.selected_idx,
+        curves: &band_curves,
+        spectrum: spectrum.as_deref(),
+        references: &references,
+    };
+
+    // The summed curve is not what is heard while bypassed
+    let magnitude_color = if eq.bypassed {
+        theme.bypassed
+    } else {
+        theme.chart
+    };
+
+    let secondary = match options.view_mode {
+        ViewMode::Normal | ViewMode::Expert => None,
+        ViewMode::Phase => Some((
+            "Phase (°)",
+            eq.phase_response_curve(NUM_POINTS, sample_rate as f64),
+        )),
+        ViewMode::GroupDelay => Some((
+            "Group Delay (ms)",
+            eq.group_delay_curve(NUM_POINTS, sample_rate as f64),
+        )),
+    };
+
+    let Some((title, secondary)) = secondary else {
+        return draw_curve(
+            f,
+            area,
+            "Gain (dB)",
+            &magnitude,
+            Some(overlay),
+            magnitude_color,
+            theme,
+        );
+    };
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+
+    draw_curve(
+        f,
+        chunks[1],
+        title,
+        &secondary,
+        None,
+        theme.chart_secondary,
+        theme,
+    );
+    draw_curve(
+        f,
+        chunks[0],
+        "Gain (dB)",
+        &magnitude,
+        Some(overlay),
+        magnitude_color,
+        theme,
+    )
+}
+
+struct BandMarker {
+    /// (frequency, value)
+    point: (f64, f64),
+    /// Muted, not soloed or bypassed
+    dimmed: bool,
+}
+
+/// Information drawn along with the summed curve
+struct Overlay<'a> {
+    markers: &'a [BandMarker],
+    selected_idx: usize,
+    /// Each band's own response, drawn beneath the summed curve
+    curves: &'a [Vec<(f64, f64)>],
+    /// Spectrum of the captured audio in dBFS, drawn behind everything else
+    spectrum: Option<&'a [(f64, f64)]>,
+    /// Measurement and target related curves, shown in the legend
+    references: &'a [NamedCurve],
+}
+
+struct NamedCurve {
+    name: String,
+    points: Vec<(f64, f64)>,
+    color: Color,
+}
+
+/// Where a chart's data was plotted, to map mouse positions back to data coordinates
+#[derive(Debug, Clone)]
+pub(super) struct ChartGeometry {
+    /// Area inside the axes
+    pub graph: Rect,
+    /// Bounds of the x-axis in log10(Hz)
+    pub x_bounds: [f64; 2],
+    pub y_bounds: [f64; 2],
+    /// Screen position of each band marker
+    pub markers: Vec<Option<(u16, u16)>>,
+}
+
+impl ChartGeometry {
+    /// Screen position of a data point, if it is inside the graph
+    fn to_screen(&self, freq: f64, value: f64) -> Option<(u16, u16)> {
+        let [x_min, x_max] = self.x_bounds;
+        let [y_min, y_max] = self.y_bounds;
+        let tx = (freq.log10() - x_min) / (x_max - x_min);
+        let ty = (y_max - value) / (y_max - y_min);
+        if !(0.0..=1.0).contains(&tx) || !(0.0..=1.0).contains(&ty) {
+            return None;
+        }
+
+        let col = self.graph.x as f64 + tx * self.graph.width.saturating_sub(1) as f64;
+        let row = self.graph.y as f64 + ty * self.graph.height.saturating_sub(1) as f64;
+        Some((col.round() as u16, row.round() as u16))
+    }
+
+    /// Frequency in Hz under a screen column
+    pub fn frequency_at(&self, col: u16) -> f64 {
+        let [x_min, x_max] = self.x_bounds;
+        let t = (col.saturating_sub(self.graph.x) as f64
+            / self.graph.width.saturating_sub(1).max(1) as f64)
+            .clamp(0.0, 1.0);
+        10_f64.powf(x_min + t * (x_max - x_min))
+    }
+
+    /// Change in value per screen row
+    pub fn value_per_row(&self) -> f64 {
+        let [y_min, y_max] = self.y_bounds;
+        (y_max - y_min) / self.graph.height.saturating_sub(1).max(1) as f64
+    }
+}
+
+/// Plot (frequency, value) pairs on a log frequency axis
+fn draw_curve(
+    f: &mut ratatui::Frame,
+    area: Rect,
+    title: &str,
+    curve_data: &[(f64, f64)],
+    overlay: Option<Overlay<'_>>,
+    color: Color,
+    theme: &Theme,
+) -> ChartGeometry {
+    let to_chart = |points: &[(f64, f64)]| {
+        points
+            .iter()
+            .map(|(freq, db)| (freq.log10(), *db))
+            .collect::<Vec<_>>()
+    };
 
     // Convert to chart data format (log x-axis manually handled via data)
-    let data: Vec<(f64, f64)> = curve_data
-        .iter()
-        .map(|(freq, db)| (freq.log10(), *db))
-        .collect();
+    let data = to_chart(curve_data);
 
-    // Find min/max for y-axis bounds
-    let max_db = curve_data
-        .iter()
-        .map(|(_, db)| db)
-        .fold(f64::NEG_INFINITY, |a, &b| a.max(b))
-        .max(1.0);
+    let band_curves = overlay
+        .as_ref()
+        .map(|overlay| {
+            overlay
+                .curves
+                .iter()
+                .map(|curve| to_chart(curve))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let marker_points = |filter: &dyn Fn(usize, &BandMarker) -> bool| {
+        overlay
+            .as_ref()
+            .map(|overlay| {
+                overlay
+                    .markers
+                    .iter()
+                    .enumerate()
+                    .filter(|(idx, marker)| filter(*idx, marker))
+                    .map(|(_, marker)| (marker.point.0.log10(), marker.point.1))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+    };
+    let selected_idx = overlay.as_ref().map(|overlay| overlay.selected_idx);
+    let active = marker_points(&|idx, marker| Some(idx) != selected_idx && !marker.dimmed);
+    let dimmed = marker_points(&|idx, marker| Some(idx) != selected_idx && marker.dimmed);
+    let selected = marker_points(&|idx, _| Some(idx) == selected_idx);
 
-    let min_db = curve_data
+    // Find min/max for y-axis bounds, keeping every marker in view
+    let references = overlay
+        .as_ref()
+        .map_or(&[][..], |overlay| overlay.references);
+
+    let values = || {
+        curve_data
+            .iter()
+            .map(|(_, db)| *db)
+            .chain(
+                overlay
+                    .iter()
+                    .flat_map(|overlay| overlay.markers.iter().map(|marker| marker.point.1)),
+            )
+            .chain(
+                references
+                    .iter()
+                    .flat_map(|curve| curve.points.iter().map(|(_, db)| *db)),
+            )
+    };
+
+    let max_db = values().fold(f64::NEG_INFINITY, f64::max).max(1.0);
+    let min_db = values().fold(f64::INFINITY, f64::min).min(-1.0);
+
+    // The spectrum has its own scale, stretch the range above the floor over the height of the chart
+    let spectrum = overlay
+        .as_ref()
+        .and_then(|overlay| overlay.spectrum)
+        .map(|spectrum| {
+            let (y_min, y_max) = (min_db - 1.0, max_db + 1.0);
+            spectrum
+                .iter()
+                .map(|(freq, level)| {
+                    let t = (level - spectrum::FLOOR_DB) / -spectrum::FLOOR_DB;
+                    (freq.log10(), y_min + t * (y_max - y_min))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    // Datasets are drawn in order, so the spectrum and band curves go beneath the summed curve
+    let mut datasets = vec![
+        Dataset::default()
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(theme.spectrum))
+            .data(&spectrum),
+    ];
+
+    let reference_data = references
         .iter()
-        .map(|(_, db)| db)
-        .fold(f64::INFINITY, |a, &b| a.min(b))
-        .min(-1.0);
+        .map(|curve| to_chart(&curve.points))
+        .collect::<Vec<_>>();
+    datasets.extend(references.iter().zip(&reference_data).map(|(curve, data)| {
+        Dataset::default()
+            .name(curve.name.clone())
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(curve.color))
+            .data(data)
+    }));
 
-    let dataset = Dataset::default()
+    datasets.extend(band_curves.iter().map(|curve| {
+        Dataset::default()
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(theme.band_curve))
+            .data(curve)
+    }));
+
+    let mut dataset = Dataset::default()
         .marker(Marker::Braille)
         .graph_type(GraphType::Line)
-        .style(Style::default().fg(theme.chart))
+        .style(Style::default().fg(color))
         .data(&data);
+    // Only name it when there is a legend for the other curves
+    if !references.is_empty() {
+        dataset = dataset.name("EQ");
+    }
+    datasets.push(dataset);
+
+    if overlay.is_some() {
+        datasets.push(
+            Dataset::default()
+                .marker(Marker::Dot)
+                .graph_type(GraphType::Scatter)
+                .style(Style::default().fg(theme.dimmed))
+                .data(&dimmed),
+        );
+        datasets.push(
+            Dataset::default()
+                .marker(Marker::Dot)
+                .graph_type(GraphType::Scatter)
+                .style(Style::default().fg(theme.band_marker))
+                .data(&active),
+        );
+        datasets.push(
+            Dataset::default()
+                .marker(Marker::Dot)
+                .graph_type(GraphType::Scatter)
+                .style(
+                    Style::default()
+                        .fg(theme.band_marker_selected)
+                        .add_modifier(Modifier::BOLD),
+                )
+                .data(&selected),
+        );
+    }
 
     // X-axis: log scale from 20 Hz to 20 kHz
-    let log_min = 20_f64.log10();
-    let log_max = 20000_f64.log10();
+    let x_bounds = [20_f64.log10(), 20000_f64.log10()];
+    let y_bounds = [min_db - 1.0, max_db + 1.0];
+
+    let x_labels = vec!["20Hz".to_string(), "20kHz".to_string()];
+    let y_labels = vec![
+        format!("{:.1}", min_db),
+        "0".into(),
+        format!("{:.1}", max_db),
+    ];
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.border))
+        .padding(Padding::horizontal(1));
+
+    let mut geometry = ChartGeometry {
+        graph: graph_area(block.inner(area), &x_labels, &y_labels),
+        x_bounds,
+        y_bounds,
+        markers: vec![],
+    };
+
+    if let Some(overlay) = &overlay {
+        geometry.markers = overlay
+            .markers
+            .iter()
+            .map(|marker| geometry.to_screen(marker.point.0, marker.point.1))
+            .collect();
+    }
 
     let x_axis = Axis::default()
         .title("Frequency")
         .style(Style::default().fg(theme.border))
-        .bounds([log_min, log_max])
-        .labels(vec!["20Hz".to_string(), "20kHz".to_string()]);
+        .bounds(x_bounds)
+        .labels(x_labels);
 
-    // Y-axis: dB scale
     let y_axis = Axis::default()
-        .title("Gain (dB)")
+        .title(title)
         .style(Style::default().fg(theme.border))
-        .bounds([min_db - 1.0, max_db + 1.0])
-        .labels(vec![
-            format!("{:.1}", min_db),
-            "0".into(),
-            format!("{:.1}", max_db),
-        ]);
-
-    let chart = Chart::new(vec![dataset])
+        .bounds(y_bounds)
+        .labels(y_labels);
+
+    let chart = Chart::new(datasets)
         .style(Style::default().bg(theme.background))
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(theme.border))
-                .padding(Padding::horizontal(1)),
-        )
+        .block(block)
         .x_axis(x_axis)
         .y_axis(y_axis);
 
     f.render_widget(chart, area);
+
+    geometry
+}
+
+/// Area the chart plots its data in, mirroring the layout of ratatui's `Chart`
+/// with labels on both axes and the x-axis labels aligned left.
+fn graph_area(inner: Rect, x_labels: &[String], y_labels: &[String]) -> Rect {
+    let y_label_width = y_labels.iter().map(|label| label.len()).max().unwrap_or(0);
+    // The last character of the first x label sits below the y-axis
+    let x_label_width = x_labels
+        .first()
+        .map_or(0, |label| label.len().saturating_sub(1));
+    let label_width = (y_label_width.max(x_label_width) as u16).min(inner.width / 3);
+
+    // One column for the y-axis, one row each for the x labels and the x-axis
+    let x = inner.x + label_width + 1;
+    Rect::new(
+        x,
+        inner.y,
+        inner.right().saturating_sub(x),
+        inner.height.saturating_sub(2),
+    )
+}
+
+/// Horizontal meter from -60 to 0 dBFS, solid up to the RMS level and shaded up to the peak
+fn level_bar(peak_db: f64, rms_db: f64, width: usize) -> String {
+    const RANGE_DB: f64 = 60.0;
+    let cells =
+        |db: f64| (((db + RANGE_DB) / RANGE_DB).clamp(0.0, 1.0) * width as f64).round() as usize;
+    let (rms, peak) = (cells(rms_db), cells(peak_db));
+    (0..width)
+        .map(|i| {
+            if i < rms {
+                '█'
+            } else if i < peak {
+                '▒'
+            } else {
+                '·'
+            }
+        })
+        .collect()
 }
 
 struct Gain(f64);
@@ -1,18 +1,26 @@
+use std::num::NonZero;
+
+use num_complex::Complex64;
 use pw_util::{
-    apo::{self, FilterType},
+    apo::FilterType,
     module::{
-        self, Control, Module, ModuleArgs, NodeKind, ParamEqConfig, ParamEqFilter,
-        RateAndBiquadCoefficients, RawNodeConfig,
+        self, Control, FREQUENCY_RANGE, Module, ModuleArgs, NodeKind, ParamEqConfig, ParamEqFilter,
+        RateAndBiquadCoefficients, RawNodeConfig, TargetObject,
     },
 };
 use strum::IntoEnumIterator;
 
-use crate::{UpdateFilter, filter::Filter};
+use crate::{
+    FilterId, UpdateFilter,
+    filter::{BiquadFormula, Filter},
+    graphic::Graphic,
+    preset::Profile,
+};
 
-use super::{Format, Rotation};
+use super::{Format, Rotation, Source};
 
 #[derive(Clone)]
-pub(super) struct Eq {
+pub(crate) struct Eq {
     pub name: String,
     pub filters: Vec<Filter>,
     pub selected_idx: usize,
@@ -20,6 +28,15 @@ pub(super) struct Eq {
     pub preamp: f64, // dB
     pub bypassed: bool,
     pub solo_idx: Option<usize>,
+    pub formula: BiquadFormula,
+    /// Keep the preamp at the negated peak of the response, see [`Eq::update_auto_preamp`]
+    pub auto_preamp: bool,
+    /// Extra headroom in dB when the preamp is computed automatically
+    pub auto_preamp_margin: f64,
+    /// Fixed ISO bands where only the gains can be changed, `None` for a parametric EQ
+    pub graphic: Option<Graphic>,
+    /// Set for a microphone EQ, which is a virtual source rather than a sink
+    pub source: Option<Source>,
 }
 
 impl Eq {
@@ -52,11 +69,35 @@ impl Eq {
             max_filters: 31,
             bypassed: false,
             solo_idx: None,
+            formula: BiquadFormula::default(),
+            auto_preamp: false,
+            auto_preamp_margin: 0.0,
+            graphic: None,
+            source: None,
+        }
+    }
+
+    /// Switch to a graphic EQ with flat bands, or back to a parametric EQ keeping the bands.
+    /// Changing the Q mode of the same layout keeps the gains.
+    pub fn set_graphic(&mut self, graphic: Option<Graphic>) {
+        match (self.graphic, graphic) {
+            (Some(current), Some(new)) if current.bands == new.bands => {
+                for band in &mut self.filters {
+                    new.conform(band);
+                }
+            }
+            (_, Some(new)) => {
+                self.filters = new.flat();
+                self.selected_idx = 0;
+                self.solo_idx = None;
+            }
+            (_, None) => {}
         }
+        self.graphic = graphic;
     }
 
     pub fn add_filter(&mut self) {
-        if self.filters.len() >= self.max_filters {
+        if self.graphic.is_some() || self.filters.len() >= self.max_filters {
             return;
         }
 
@@ -79,6 +120,7 @@ impl Eq {
             q: 1.0,
             filter_type: FilterType::Peaking,
             muted: false,
+            locked: false,
         };
 
         if let Some(solo_idx) = self.solo_idx
@@ -92,7 +134,7 @@ impl Eq {
     }
 
     pub fn delete_selected_filter(&mut self) {
-        if self.filters.len() > 1 {
+        if self.graphic.is_none() && self.filters.len() > 1 {
             let removed_idx = self.selected_idx;
             self.filters.remove(self.selected_idx);
             if let Some(solo_idx) = self.solo_idx {
@@ -119,24 +161,46 @@ impl Eq {
     }
 
     pub fn adjust_freq(&mut self, f: impl FnOnce(f64) -> f64) {
+        if self.graphic.is_some() {
+            return;
+        }
+
         if let Some(band) = self.filters.get_mut(self.selected_idx) {
-            band.frequency = f(band.frequency).clamp(20.0, 20000.0);
+            band.frequency =
+                f(band.frequency).clamp(*FREQUENCY_RANGE.start(), *FREQUENCY_RANGE.end());
         }
     }
 
     pub fn adjust_gain(&mut self, f: impl FnOnce(f64) -> f64) {
         if let Some(band) = self.filters.get_mut(self.selected_idx) {
-            band.gain = f(band.gain).clamp(-12.0, 12.0);
+            // Passes and notches ignore the gain, so there is nothing to edit
+            let Some(gain_range) = band.filter_type.gain_range() else {
+                return;
+            };
+
+            band.gain = f(band.gain).clamp(*gain_range.start(), *gain_range.end());
+            if let Some(graphic) = self.graphic {
+                graphic.conform(band);
+            }
         }
     }
 
     pub fn adjust_q(&mut self, f: impl FnOnce(f64) -> f64) {
+        if self.graphic.is_some() {
+            return;
+        }
+
         if let Some(band) = self.filters.get_mut(self.selected_idx) {
-            band.q = f(band.q).clamp(0.001, 10.0);
+            let q_range = band.filter_type.q_range();
+            band.q = f(band.q).clamp(*q_range.start(), *q_range.end());
         }
     }
 
     pub fn cycle_filter_type(&mut self, rotation: Rotation) {
+        if self.graphic.is_some() {
+            return;
+        }
+
         let types = FilterType::iter().collect::<Vec<_>>();
         if let Some(band) = self.filters.get_mut(self.selected_idx) {
             let idx = types
@@ -148,6 +212,8 @@ impl Eq {
                 Rotation::Clockwise => types[(idx + 1) % types.len()],
                 Rotation::CounterClockwise => types[(idx + types.len() - 1) % types.len()],
             };
+            // The Q of the previous type may not be usable for the new one
+            band.clamp();
         }
     }
 
@@ -157,6 +223,12 @@ impl Eq {
         }
     }
 
+    pub fn toggle_lock(&mut self) {
+        if let Some(band) = self.filters.get_mut(self.selected_idx) {
+            band.locked = !band.locked;
+        }
+    }
+
     pub fn toggle_solo(&mut self) {
         let selected = self.selected_idx;
         self.solo_idx = match self.soloed_index() {
@@ -169,6 +241,48 @@ impl Eq {
         self.preamp = f(self.preamp).clamp(-12.0, 12.0);
     }
 
+    pub fn toggle_auto_preamp(&mut self) {
+        self.auto_preamp = !self.auto_preamp;
+    }
+
+    /// Peak of the combined magnitude response in dB, ignoring solo so that auditioning a band
+    /// doesn't move the automatic preamp.
+    /// Sampled densely as overlapping bands and shelves can peak between the band frequencies.
+    pub fn peak_gain_db(&self, sample_rate: f64) -> f64 {
+        const NUM_POINTS: usize = 4096;
+
+        Self::curve_frequencies(NUM_POINTS)
+            .map(|freq| {
+                self.filters
+                    .iter()
+                    .map(|band| band.magnitude_db_at_with(self.formula, freq, sample_rate))
+                    .sum::<f64>()
+            })
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    /// Average level of the response including the preamp, in dB over a log frequency scale.
+    /// Used to level match curves so that the louder one doesn't sound better by default.
+    pub fn mean_gain_db(&self, sample_rate: f64) -> f64 {
+        const NUM_POINTS: usize = 512;
+
+        // Only the preamp is heard while the bands are bypassed
+        if self.bypassed {
+            return self.preamp;
+        }
+
+        let curve = self.frequency_response_curve(NUM_POINTS, sample_rate);
+        self.preamp + curve.iter().map(|(_, db)| db).sum::<f64>() / curve.len() as f64
+    }
+
+    /// Set the preamp to `-max(0, peak) - margin` if auto preamp is enabled.
+    /// Unlike manual adjustments this is not clamped, the headroom is needed regardless.
+    pub fn update_auto_preamp(&mut self, sample_rate: f64) {
+        if self.auto_preamp {
+            self.preamp = -self.peak_gain_db(sample_rate).max(0.0) - self.auto_preamp_margin;
+        }
+    }
+
     pub fn toggle_bypass(&mut self) {
         self.bypassed = !self.bypassed;
     }
@@ -189,20 +303,47 @@ impl Eq {
         self.bypassed || self.is_band_effectively_muted(idx)
     }
 
-    pub fn to_module_args(&self, rate: u32) -> ModuleArgs {
-        Module::from_kinds(
-            &format!("{}-{}", self.name, self.filters.len()),
+    /// Generate `bq_raw` module args with a coefficient set for each of the given sample rates.
+    /// PipeWire picks the set matching the rate the graph is running at.
+    pub fn to_module_args(&self, rates: impl IntoIterator<Item = u32>) -> ModuleArgs {
+        let rates = rates.into_iter().collect::<Vec<_>>();
+        let module = Module::from_kinds(
+            &self.module_name(),
             self.preamp,
             self.filters.iter().map(|band| NodeKind::Raw {
                 config: RawNodeConfig {
-                    coefficients: vec![RateAndBiquadCoefficients {
-                        rate,
-                        coefficients: band.biquad_coeffs(rate as f64),
-                    }],
+                    coefficients: rates
+                        .iter()
+                        .map(|&rate| RateAndBiquadCoefficients {
+                            rate,
+                            coefficients: band.biquad_coeffs_with(self.formula, rate as f64),
+                        })
+                        .collect(),
                 },
             }),
-        )
-        .args
+        );
+
+        match &self.source {
+            Some(source) => {
+                module
+                    .into_source(source.device.clone().map(TargetObject::NodeName))
+                    .args
+            }
+            None => module.args,
+        }
+    }
+
+    /// Modules are loaded per band count, so the name of the module includes it
+    pub fn module_name(&self) -> String {
+        format!("{}-{}", self.name, self.filters.len())
+    }
+
+    pub fn profile(&self) -> Profile {
+        Profile {
+            preamp: self.preamp,
+            filters: self.filters.clone(),
+            graphic: self.graphic,
+        }
     }
 
     /// Save current EQ configuration to a PipeWire filter-chain config file using param_eq
@@ -237,23 +378,7 @@ impl Eq {
 
                 pw_util::to_spa_json(&config)
             }
-            Format::Apo => apo::Config {
-                preamp: self.preamp,
-                filters: self
-                    .filters
-                    .iter()
-                    .enumerate()
-                    .map(|(i, filter)| apo::Filter {
-                        number: (i + 1) as u32,
-                        enabled: !filter.muted,
-                        filter_type: filter.filter_type,
-                        frequency: filter.frequency,
-                        gain: filter.gain,
-                        q: filter.q,
-                    })
-                    .collect(),
-            }
-            .to_string(),
+            Format::Apo => self.profile().to_apo().to_string(),
         };
 
         if let Some(parent) = path.parent()
@@ -280,6 +405,8 @@ impl Eq {
         }
     }
 
+    /// Build update for a filter band. The coefficients are computed for `sample_rate`, which must
+    /// be the rate the graph is currently running at as live updates replace the active coefficients.
     pub fn build_filter_update(&self, filter_idx: usize, sample_rate: u32) -> UpdateFilter {
         // Locally copy the band to apply solo/bypass muting for coefficient updates
         // This is necessary to get the correct biquad coefficients
@@ -291,40 +418,311 @@ impl Eq {
             frequency: Some(band.frequency),
             gain: Some(gain),
             q: Some(band.q),
-            coeffs: Some(band.biquad_coeffs(sample_rate as f64)),
+            coeffs: Some(band.biquad_coeffs_with(self.formula, sample_rate as f64)),
+        }
+    }
+
+    /// Updates for the preamp and all bands, see [`Eq::build_filter_update`]
+    pub fn build_updates(&self, sample_rate: u32) -> Vec<(FilterId, UpdateFilter)> {
+        let mut updates = Vec::with_capacity(self.filters.len() + 1);
+        updates.push((FilterId::Preamp, self.build_preamp_update()));
+        for idx in 0..self.filters.len() {
+            let id = FilterId::Index(NonZero::new(idx + 1).unwrap());
+            updates.push((id, self.build_filter_update(idx, sample_rate)));
         }
+        updates
+    }
+
+    /// Bands with the solo state applied to their mute flag
+    fn audible_bands(&self) -> impl Iterator<Item = Filter> + '_ {
+        let solo_idx = self.soloed_index();
+        self.filters.iter().enumerate().map(move |(idx, band)| {
+            let mut band = *band;
+            if let Some(solo_idx) = solo_idx {
+                band.muted = idx != solo_idx;
+            }
+            band
+        })
+    }
+
+    /// Logarithmically spaced frequency points from 20 Hz to 20 kHz
+    pub fn curve_frequencies(num_points: usize) -> impl Iterator<Item = f64> {
+        let log_min = 20_f64.log10();
+        let log_max = 20000_f64.log10();
+
+        (0..num_points).map(move |i| {
+            let t = i as f64 / (num_points - 1) as f64;
+            10_f64.powf(log_min + t * (log_max - log_min))
+        })
     }
 
     /// Generate frequency response curve data for visualization
     /// Returns Vec of (frequency, magnitude_db) pairs
     pub fn frequency_response_curve(&self, num_points: usize, sample_rate: f64) -> Vec<(f64, f64)> {
-        // Generate logarithmically spaced frequency points from 20 Hz to 20 kHz
-        let log_min = 20_f64.log10();
-        let log_max = 20000_f64.log10();
-        let solo_idx = self.soloed_index();
+        Self::curve_frequencies(num_points)
+            .map(|freq| (freq, self.magnitude_db_at(freq, sample_rate)))
+            .collect()
+    }
 
-        (0..num_points)
-            .map(|i| {
-                let t = i as f64 / (num_points - 1) as f64;
-                let log_freq = log_min + t * (log_max - log_min);
-                let freq = 10_f64.powf(log_freq);
+    /// Magnitude response of all bands in series at a given frequency, excluding the preamp
+    pub fn magnitude_db_at(&self, freq: f64, sample_rate: f64) -> f64 {
+        // Sum magnitude response from all bands
+        self.audible_bands()
+            .map(|band| band.magnitude_db_at_with(self.formula, freq, sample_rate))
+            .sum()
+    }
 
-                // Sum magnitude response from all bands
-                let total_db: f64 = self
-                    .filters
-                    .iter()
-                    .enumerate()
-                    .map(|(idx, band)| {
-                        let mut band = *band;
-                        if let Some(solo_idx) = solo_idx {
-                            band.muted = idx != solo_idx;
-                        }
-                        band.magnitude_db_at(freq, sample_rate)
-                    })
-                    .sum();
+    /// Magnitude response of a single band on its own, ignoring mute and solo
+    pub fn band_response_curve(
+        &self,
+        idx: usize,
+        num_points: usize,
+        sample_rate: f64,
+    ) -> Vec<(f64, f64)> {
+        let band = Filter {
+            muted: false,
+            ..self.filters[idx]
+        };
+        Self::curve_frequencies(num_points)
+            .map(|freq| {
+                (
+                    freq,
+                    band.magnitude_db_at_with(self.formula, freq, sample_rate),
+                )
+            })
+            .collect()
+    }
+
+    /// Where a band is marked on the chart as (frequency, dB).
+    /// Bands with a gain are marked at it, others on their own response at the corner frequency.
+    pub fn band_marker(&self, idx: usize, sample_rate: f64) -> (f64, f64) {
+        let band = Filter {
+            muted: false,
+            ..self.filters[idx]
+        };
+        let freq = band
+            .frequency
+            .clamp(*FREQUENCY_RANGE.start(), *FREQUENCY_RANGE.end());
+        if band.filter_type.uses_gain() {
+            (freq, band.gain)
+        } else {
+            (
+                freq,
+                band.magnitude_db_at_with(self.formula, freq, sample_rate),
+            )
+        }
+    }
+
+    /// Complex frequency response of all bands in series at a given frequency, excluding the preamp
+    pub fn response_at(&self, freq: f64, sample_rate: f64) -> Complex64 {
+        self.audible_bands()
+            .map(|band| band.response_at_with(self.formula, freq, sample_rate))
+            .product()
+    }
 
-                (freq, total_db)
+    /// Returns Vec of (frequency, phase_degrees) pairs, with the phase wrapped to (-180, 180]
+    pub fn phase_response_curve(&self, num_points: usize, sample_rate: f64) -> Vec<(f64, f64)> {
+        Self::curve_frequencies(num_points)
+            .map(|freq| (freq, self.response_at(freq, sample_rate).arg().to_degrees()))
+            .collect()
+    }
+
+    /// Returns Vec of (frequency, group_delay_ms) pairs
+    pub fn group_delay_curve(&self, num_points: usize, sample_rate: f64) -> Vec<(f64, f64)> {
+        Self::curve_frequencies(num_points)
+            .map(|freq| {
+                let delay: f64 = self
+                    .audible_bands()
+                    .map(|band| band.group_delay_at_with(self.formula, freq, sample_rate))
+                    .sum();
+                (freq, delay * 1000.0)
             })
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pw_util::module::NodeKind;
+
+    use super::{Eq, FilterType};
+    use crate::filter::Filter;
+    use crate::graphic::{Bands, Graphic, QMode};
+
+    #[test]
+    fn test_auto_preamp_accounts_for_overlapping_bands() {
+        let peak = |frequency| Filter {
+            frequency,
+            gain: 6.0,
+            q: 1.0,
+            ..Default::default()
+        };
+        let mut eq = Eq::new("test", [peak(1000.0), peak(1200.0)]);
+        // `Eq::new` only considers the largest band gain
+        assert_eq!(eq.preamp, -6.0);
+
+        eq.auto_preamp = true;
+        eq.auto_preamp_margin = 0.5;
+        eq.update_auto_preamp(48000.0);
+        assert!(eq.preamp < -11.0, "{}", eq.preamp);
+        assert!((eq.preamp + eq.peak_gain_db(48000.0) + 0.5).abs() < 1e-9);
+
+        // Soloing a band is for listening to it, not for changing the headroom
+        let preamp = eq.preamp;
+        eq.toggle_solo();
+        eq.update_auto_preamp(48000.0);
+        assert_eq!(eq.preamp, preamp);
+        eq.toggle_solo();
+
+        // A pure cut never needs a negative preamp
+        eq.filters = vec![Filter {
+            gain: -6.0,
+            ..Default::default()
+        }];
+        eq.update_auto_preamp(48000.0);
+        assert_eq!(eq.preamp, -0.5);
+    }
+
+    #[test]
+    fn test_band_markers() {
+        let mut eq = Eq::new(
+            "test",
+            [
+                Filter {
+                    frequency: 1000.0,
+                    gain: -4.0,
+                    muted: true,
+                    ..Default::default()
+                },
+                Filter {
+                    filter_type: FilterType::LowPass,
+                    frequency: 10000.0,
+                    q: std::f64::consts::FRAC_1_SQRT_2,
+                    ..Default::default()
+                },
+            ],
+        );
+
+        // Muted bands are still marked at their gain
+        assert_eq!(eq.band_marker(0, 48000.0), (1000.0, -4.0));
+
+        // Bands without a gain are marked on their own response
+        let (freq, db) = eq.band_marker(1, 48000.0);
+        assert_eq!(freq, 10000.0);
+        assert!((db + 3.0).abs() < 0.5, "{db}");
+
+        // Out of range frequencies are pinned to the edge of the chart
+        eq.filters[1].frequency = 50000.0;
+        assert_eq!(eq.band_marker(1, 96000.0).0, 20000.0);
+    }
+
+    #[test]
+    fn test_module_args_contain_all_rates() {
+        let eq = Eq::new(
+            "test",
+            [Filter {
+                frequency: 10000.0,
+                gain: 3.0,
+                ..Default::default()
+            }],
+        );
+
+        let rates = [44100, 48000, 96000];
+        let args = eq.to_module_args(rates);
+        let NodeKind::Raw { config } = &args.filter_graph.nodes[1].kind else {
+            panic!("expected bq_raw node");
+        };
+
+        assert_eq!(
+            config
+                .coefficients
+                .iter()
+                .map(|c| c.rate)
+                .collect::<Vec<_>>(),
+            rates
+        );
+
+        for c in &config.coefficients {
+            assert_eq!(
+                Some(c.coefficients),
+                eq.build_filter_update(0, c.rate).coeffs
+            );
+        }
+
+        assert_ne!(
+            config.coefficients[0].coefficients,
+            config.coefficients[1].coefficients
+        );
+    }
+
+    #[test]
+    fn test_adjust_gain_is_clamped_and_ignored_for_gainless_types() {
+        let mut eq = Eq::new("test", [Filter::default()]);
+        eq.adjust_gain(|g| g + 100.0);
+        assert_eq!(eq.filters[0].gain, 12.0);
+        eq.adjust_gain(|g| g - 100.0);
+        assert_eq!(eq.filters[0].gain, -12.0);
+
+        eq.filters[0].filter_type = FilterType::LowPass;
+        eq.filters[0].gain = 0.0;
+        eq.adjust_gain(|g| g + 3.0);
+        assert_eq!(eq.filters[0].gain, 0.0);
+    }
+
+    #[test]
+    fn test_adjustments_leave_other_parameters_alone() {
+        let mut eq = Eq::new(
+            "test",
+            [Filter {
+                frequency: 22000.0,
+                gain: -15.0,
+                q: 15.0,
+                ..Filter::default()
+            }],
+        );
+
+        eq.adjust_gain(|g| g + 0.1);
+        assert_eq!(eq.filters[0].gain, -12.0);
+        assert_eq!(eq.filters[0].q, 15.0);
+        assert_eq!(eq.filters[0].frequency, 22000.0);
+
+        eq.filters[0].gain = -15.0;
+        eq.adjust_q(|q| q - 1.0);
+        assert_eq!(eq.filters[0].q, 10.0);
+        assert_eq!(eq.filters[0].gain, -15.0);
+        assert_eq!(eq.filters[0].frequency, 22000.0);
+    }
+
+    #[test]
+    fn test_graphic_mode_fixes_frequency_and_type() {
+        let mut eq = Eq::new("test", [Filter::default()]);
+        eq.set_graphic(Some(Graphic::new(Bands::Ten, QMode::Proportional)));
+        assert_eq!(eq.filters.len(), 10);
+
+        eq.selected_idx = 5;
+        eq.add_filter();
+        eq.delete_selected_filter();
+        eq.adjust_freq(|f| f * 2.0);
+        eq.adjust_q(|q| q * 2.0);
+        eq.cycle_filter_type(super::Rotation::Clockwise);
+        assert_eq!(eq.filters.len(), 10);
+        assert_eq!(eq.filters[5].frequency, 1000.0);
+        assert_eq!(eq.filters[5].filter_type, FilterType::Peaking);
+
+        // The Q follows the gain, which is limited to the slider range
+        let q = eq.filters[5].q;
+        eq.adjust_gain(|_| 20.0);
+        assert_eq!(eq.filters[5].gain, 12.0);
+        assert!(eq.filters[5].q > q);
+
+        // Changing the Q mode keeps the gains
+        eq.set_graphic(Some(Graphic::new(Bands::Ten, QMode::Constant)));
+        assert_eq!(eq.filters[5].gain, 12.0);
+        assert_eq!(eq.filters[5].q, 2f64.sqrt());
+
+        // Leaving graphic mode keeps the bands
+        eq.set_graphic(None);
+        eq.adjust_freq(|f| f * 2.0);
+        assert_eq!(eq.filters[5].frequency, 2000.0);
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::VecDeque;
+use std::mem;
+
+use super::action::EqAction;
+use super::eq::Eq;
+
+/// Consecutive edits with the same key are coalesced into a single undo step,
+/// e.g. holding down the key to adjust the gain of a band.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum EditKey {
+    Frequency(usize),
+    Gain(usize),
+    Q(usize),
+    Preamp,
+    /// Dragging a band on the chart, which adjusts several parameters at once
+    Drag(usize),
+}
+
+impl EditKey {
+    pub fn for_action(action: EqAction, selected_idx: usize) -> Option<Self> {
+        match action {
+            EqAction::AdjustFrequency(_) => Some(EditKey::Frequency(selected_idx)),
+            EqAction::AdjustGain(_) => Some(EditKey::Gain(selected_idx)),
+            EqAction::AdjustQ(_) => Some(EditKey::Q(selected_idx)),
+            EqAction::AdjustPreamp(_) => Some(EditKey::Preamp),
+            _ => None,
+        }
+    }
+}
+
+/// Undo/redo stacks of `Eq` snapshots
+pub(super) struct History {
+    undo: VecDeque<Eq>,
+    redo: Vec<Eq>,
+    /// Key of the most recent edit, if it can be coalesced with the next one
+    last_key: Option<EditKey>,
+    capacity: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            last_key: None,
+            capacity,
+        }
+    }
+
+    /// Record the state before an edit. Does nothing if `after` is the same as `before`.
+    pub fn record(&mut self, before: &Eq, after: &Eq, key: Option<EditKey>) {
+        if !is_edit(before, after) {
+            return;
+        }
+
+        self.redo.clear();
+
+        if key.is_some() && key == self.last_key {
+            // The state before the first edit of this sequence is already on the stack
+            return;
+        }
+
+        self.last_key = key;
+        if self.undo.len() == self.capacity {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(before.clone());
+    }
+
+    /// Start a new undo step for the next edit even if it could be coalesced
+    pub fn seal(&mut self) {
+        self.last_key = None;
+    }
+
+    /// Restore the previous snapshot into `current`, returns false if there is nothing to undo
+    pub fn undo(&mut self, current: &mut Eq) -> bool {
+        let Some(prev) = self.undo.pop_back() else {
+            return false;
+        };
+
+        self.last_key = None;
+        self.redo.push(mem::replace(current, prev));
+        true
+    }
+
+    /// Restore the next snapshot into `current`, returns false if there is nothing to redo
+    pub fn redo(&mut self, current: &mut Eq) -> bool {
+        let Some(next) = self.redo.pop() else {
+            return false;
+        };
+
+        self.last_key = None;
+        self.undo.push_back(mem::replace(current, next));
+        true
+    }
+}
+
+/// Selection and view state are not edits
+fn is_edit(before: &Eq, after: &Eq) -> bool {
+    before.filters != after.filters
+        || before.preamp != after.preamp
+        || before.bypassed != after.bypassed
+        || before.soloed_index() != after.soloed_index()
+        || before.auto_preamp != after.auto_preamp
+        || before.graphic != after.graphic
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EditKey, History};
+    use crate::filter::Filter;
+    use crate::tui::eq::Eq;
+
+    fn eq() -> Eq {
+        Eq::new("test", [Filter::default(), Filter::default()])
+    }
+
+    #[test]
+    fn test_coalesce_adjustments_of_same_band() {
+        let mut history = History::default();
+        let mut eq = eq();
+        let original = eq.clone();
+
+        for _ in 0..5 {
+            let before = eq.clone();
+            eq.adjust_gain(|g| g + 0.1);
+            history.record(&before, &eq, Some(EditKey::Gain(0)));
+        }
+
+        let before = eq.clone();
+        eq.select_next_filter();
+        eq.adjust_gain(|g| g + 0.1);
+        history.record(&before, &eq, Some(EditKey::Gain(1)));
+
+        // Selection changes alone are not recorded
+        let before = eq.clone();
+        eq.select_prev_filter();
+        history.record(&before, &eq, None);
+
+        let edited = eq.clone();
+        assert!(history.undo(&mut eq));
+        assert_eq!(eq.filters[1].gain, 0.0);
+        assert!((eq.filters[0].gain - 0.5).abs() < 1e-9);
+
+        assert!(history.undo(&mut eq));
+        assert_eq!(eq.filters, original.filters);
+        assert!(!history.undo(&mut eq));
+
+        assert!(history.redo(&mut eq));
+        assert!(history.redo(&mut eq));
+        assert_eq!(eq.filters, edited.filters);
+        assert!(!history.redo(&mut eq));
+    }
+
+    #[test]
+    fn test_new_edit_clears_redo() {
+        let mut history = History::default();
+        let mut eq = eq();
+
+        let before = eq.clone();
+        eq.delete_selected_filter();
+        history.record(&before, &eq, None);
+        assert!(history.undo(&mut eq));
+        assert_eq!(eq.filters.len(), 2);
+
+        let before = eq.clone();
+        eq.toggle_mute();
+        history.record(&before, &eq, None);
+        assert!(!history.redo(&mut eq));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut history = History::new(2);
+        let mut eq = eq();
+
+        for i in 0..3 {
+            let before = eq.clone();
+            eq.adjust_q(|q| q + 1.0);
+            history.record(&before, &eq, Some(EditKey::Q(i)));
+        }
+
+        assert!(history.undo(&mut eq));
+        assert!(history.undo(&mut eq));
+        assert!(!history.undo(&mut eq));
+        assert!((eq.filters[0].q - (Filter::default().q + 1.0)).abs() < 1e-9);
+    }
+}
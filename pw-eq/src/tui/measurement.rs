@@ -0,0 +1,57 @@
+use std::path::Path;
+
+use anyhow::Context as _;
+use autoeq_api::Measurement;
+
+/// Frequency the measurement and target are aligned at
+pub const NORMALIZE_FREQUENCY: f64 = 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurveKind {
+    Measurement,
+    Target,
+}
+
+impl CurveKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            CurveKind::Measurement => "measurement",
+            CurveKind::Target => "target",
+        }
+    }
+}
+
+/// A measurement or target loaded from a file
+#[derive(Debug, Clone)]
+pub struct ReferenceCurve {
+    /// File stem, shown in the chart legend
+    pub name: String,
+    pub data: Measurement,
+}
+
+impl ReferenceCurve {
+    pub async fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let data = Measurement::parse(&text)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        let name = path.file_stem().map_or_else(
+            || path.display().to_string(),
+            |stem| stem.to_string_lossy().into_owned(),
+        );
+        Ok(Self { name, data })
+    }
+
+    /// Level relative to the level at `NORMALIZE_FREQUENCY`
+    pub fn normalized_level_at(&self, frequency: f64) -> f64 {
+        self.data.level_at(frequency) - self.data.level_at(NORMALIZE_FREQUENCY)
+    }
+
+    pub fn normalized_curve(&self, frequencies: impl IntoIterator<Item = f64>) -> Vec<(f64, f64)> {
+        frequencies
+            .into_iter()
+            .map(|freq| (freq, self.normalized_level_at(freq)))
+            .collect()
+    }
+}
@@ -0,0 +1,141 @@
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use ratatui::{layout::Position, prelude::Backend};
+use std::{io, ops::ControlFlow};
+
+use super::{
+    App, InputMode, Tab,
+    action::{Adjustment, EqAction},
+};
+
+/// A band marker being dragged on the chart
+#[derive(Debug, Clone, Copy)]
+pub(super) struct MouseDrag {
+    pub band_idx: usize,
+    /// Row of the previous drag event, vertical movement is applied relative to it
+    row: u16,
+}
+
+/// How far from a band marker a click still picks it, in cells
+const PICK_COLUMNS: u16 = 2;
+const PICK_ROWS: u16 = 1;
+
+/// Q multiplier per scroll step
+const SCROLL_Q_FACTOR: f64 = 1.1;
+
+impl<B> App<B>
+where
+    B: Backend + io::Write,
+    B::Error: Send + Sync + 'static,
+{
+    /// Mouse input is translated into `EqAction`s so it goes through the same sync path as keys
+    pub(super) fn handle_mouse(&mut self, event: MouseEvent) -> io::Result<ControlFlow<()>> {
+        if self.tab != Tab::Eq || self.input_mode != InputMode::Eq {
+            return Ok(ControlFlow::Continue(()));
+        }
+
+        let pos = Position::new(event.column, event.row);
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some(idx) = self.table_row_at(pos) {
+                    return self.perform_eq_action(EqAction::SelectIndex(idx));
+                }
+
+                if let Some(idx) = self.marker_at(pos) {
+                    // Each drag is a single undo step
+                    self.history.seal();
+                    self.mouse_drag = Some(MouseDrag {
+                        band_idx: idx,
+                        row: event.row,
+                    });
+                    return self.perform_eq_action(EqAction::SelectIndex(idx));
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                let (Some(drag), Some(chart)) = (self.mouse_drag, &self.chart_geometry) else {
+                    return Ok(ControlFlow::Continue(()));
+                };
+
+                if self.eq.selected_idx != drag.band_idx {
+                    return Ok(ControlFlow::Continue(()));
+                }
+
+                let frequency = chart.frequency_at(event.column);
+                // Rows grow downwards
+                let gain_delta = (drag.row as f64 - event.row as f64) * chart.value_per_row();
+                self.mouse_drag = Some(MouseDrag {
+                    row: event.row,
+                    ..drag
+                });
+
+                if let ControlFlow::Break(()) =
+                    self.perform_eq_action(EqAction::AdjustFrequency(Adjustment::Set(frequency)))?
+                {
+                    return Ok(ControlFlow::Break(()));
+                }
+                if gain_delta != 0.0 && self.eq.filters[drag.band_idx].filter_type.uses_gain() {
+                    return self
+                        .perform_eq_action(EqAction::AdjustGain(Adjustment::Delta(gain_delta)));
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.mouse_drag = None,
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                let over_chart = self
+                    .chart_geometry
+                    .as_ref()
+                    .is_some_and(|chart| chart.graph.contains(pos));
+                let over_table = self.table_area.is_some_and(|area| area.contains(pos));
+                if !over_chart && !over_table {
+                    return Ok(ControlFlow::Continue(()));
+                }
+
+                // Scrolling over a band picks it first
+                if let Some(idx) = self.marker_at(pos).or_else(|| self.table_row_at(pos))
+                    && let ControlFlow::Break(()) =
+                        self.perform_eq_action(EqAction::SelectIndex(idx))?
+                {
+                    return Ok(ControlFlow::Break(()));
+                }
+
+                let factor = if event.kind == MouseEventKind::ScrollUp {
+                    SCROLL_Q_FACTOR
+                } else {
+                    1.0 / SCROLL_Q_FACTOR
+                };
+                return self.perform_eq_action(EqAction::AdjustQ(Adjustment::Multiplier(factor)));
+            }
+            _ => {}
+        }
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Band index of the table row under the cursor
+    fn table_row_at(&self, pos: Position) -> Option<usize> {
+        let area = self.table_area?;
+        // Skip the top border and the header row
+        let first_row = area.y + 2;
+        if !area.contains(pos) || pos.y < first_row || pos.y + 1 >= area.bottom() {
+            return None;
+        }
+
+        // Rows above the offset are scrolled out of view
+        let idx = self.table_state.offset() + (pos.y - first_row) as usize;
+        (idx < self.eq.filters.len()).then_some(idx)
+    }
+
+    /// Band index of the chart marker closest to the cursor
+    fn marker_at(&self, pos: Position) -> Option<usize> {
+        let chart = self.chart_geometry.as_ref()?;
+        chart
+            .markers
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, marker)| {
+                let (col, row) = (*marker)?;
+                let (dx, dy) = (col.abs_diff(pos.x), row.abs_diff(pos.y));
+                (dx <= PICK_COLUMNS && dy <= PICK_ROWS).then_some((idx, dx + dy))
+            })
+            .min_by_key(|&(_, distance)| distance)
+            .map(|(idx, _)| idx)
+    }
+}
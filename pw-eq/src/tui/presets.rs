@@ -0,0 +1,52 @@
+use tokio::sync::mpsc;
+
+use crate::preset::{Library, PresetInfo};
+
+use super::Notif;
+
+#[derive(Debug, Default)]
+pub struct PresetBrowser {
+    pub presets: Vec<PresetInfo>,
+    pub selected_index: usize,
+    pub loading: bool,
+}
+
+impl PresetBrowser {
+    /// List the presets again, user presets may have been saved since the last time
+    pub fn load_data(&mut self, notifs_tx: mpsc::Sender<Notif>) {
+        self.loading = true;
+        tokio::spawn(async move {
+            let presets = match Library::user() {
+                Ok(library) => library.list().await,
+                Err(err) => Err(err),
+            };
+            let notif = match presets {
+                Ok(presets) => Notif::PresetsListed(presets),
+                Err(err) => Notif::Error(err),
+            };
+            let _ = notifs_tx.send(notif).await;
+        });
+    }
+
+    pub fn on_data_loaded(&mut self, presets: Vec<PresetInfo>) {
+        self.presets = presets;
+        self.loading = false;
+        if self.selected_index >= self.presets.len() {
+            self.selected_index = 0;
+        }
+    }
+
+    pub fn selected(&self) -> Option<&PresetInfo> {
+        self.presets.get(self.selected_index)
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected_index + 1 < self.presets.len() {
+            self.selected_index += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected_index = self.selected_index.saturating_sub(1);
+    }
+}
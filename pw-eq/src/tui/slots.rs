@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use std::mem;
+
+use super::eq::Eq;
+use super::history::History;
+
+/// Named snapshots of the EQ for comparing candidate curves.
+/// The live EQ belongs to the active slot, its stored copy is only updated on store or swap.
+pub(super) struct Slots {
+    slots: BTreeMap<String, Eq>,
+    /// Undo history of the slots that were live, so that undo never crosses a swap
+    histories: BTreeMap<String, History>,
+    active: String,
+    /// Slot that `ToggleAB` swaps with
+    alternate: String,
+}
+
+impl Default for Slots {
+    fn default() -> Self {
+        Self {
+            slots: BTreeMap::new(),
+            histories: BTreeMap::new(),
+            active: "A".to_string(),
+            alternate: "B".to_string(),
+        }
+    }
+}
+
+impl Slots {
+    pub fn active(&self) -> &str {
+        &self.active
+    }
+
+    pub fn alternate(&self) -> &str {
+        &self.alternate
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Eq> {
+        self.slots.get(name)
+    }
+
+    /// Store a copy of the live EQ, defaults to the active slot
+    pub fn store(&mut self, name: Option<&str>, live: &Eq) {
+        let name = name.unwrap_or(&self.active);
+        self.slots.insert(name.to_string(), live.clone());
+    }
+
+    /// Replace the live EQ with a copy of the slot, discarding unstored changes.
+    /// Recalling the active slot can be undone, another slot is made active with its own history.
+    pub fn recall(&mut self, name: &str, live: &mut Eq, history: &mut History) -> bool {
+        let Some(eq) = self.slots.get(name) else {
+            return false;
+        };
+
+        let before = mem::replace(live, eq.clone());
+        if name == self.active {
+            history.record(&before, live, None);
+        } else {
+            let incoming = self.histories.remove(name).unwrap_or_default();
+            let outgoing = mem::replace(history, incoming);
+            self.histories.insert(self.active.clone(), outgoing);
+            self.set_active(name);
+        }
+        true
+    }
+
+    /// Store the live EQ and its history in the active slot and make the given slot live.
+    /// An empty slot starts out as a copy of the live EQ with an empty history.
+    pub fn swap(&mut self, name: &str, live: &mut Eq, history: &mut History) {
+        if name == self.active {
+            return;
+        }
+
+        let incoming = self.slots.remove(name).unwrap_or_else(|| live.clone());
+        let outgoing = mem::replace(live, incoming);
+        self.slots.insert(self.active.clone(), outgoing);
+        let incoming = self.histories.remove(name).unwrap_or_default();
+        let outgoing = mem::replace(history, incoming);
+        self.histories.insert(self.active.clone(), outgoing);
+        self.set_active(name);
+    }
+
+    fn set_active(&mut self, name: &str) {
+        if name != self.active {
+            self.alternate = mem::replace(&mut self.active, name.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Slots;
+    use crate::filter::Filter;
+    use crate::tui::eq::Eq;
+    use crate::tui::history::History;
+
+    #[test]
+    fn test_toggle_keeps_edits_of_both_slots() {
+        let mut slots = Slots::default();
+        let mut live = Eq::new("test", [Filter::default()]);
+        let mut history = History::default();
+
+        // B starts as a copy of A
+        slots.swap("B", &mut live, &mut history);
+        assert_eq!(slots.active(), "B");
+        live.adjust_gain(|_| 3.0);
+
+        slots.swap("A", &mut live, &mut history);
+        assert_eq!(slots.active(), "A");
+        assert_eq!(live.filters[0].gain, 0.0);
+        live.adjust_gain(|_| -3.0);
+
+        assert_eq!(slots.alternate(), "B");
+        slots.swap("B", &mut live, &mut history);
+        assert_eq!(slots.active(), "B");
+        assert_eq!(live.filters[0].gain, 3.0);
+
+        slots.swap("A", &mut live, &mut history);
+        assert_eq!(live.filters[0].gain, -3.0);
+    }
+
+    #[test]
+    fn test_swap_keeps_history_per_slot() {
+        let mut slots = Slots::default();
+        let mut live = Eq::new("test", [Filter::default()]);
+        let mut history = History::default();
+
+        let before = live.clone();
+        live.adjust_gain(|_| 3.0);
+        history.record(&before, &live, None);
+
+        // B has no edits to undo
+        slots.swap("B", &mut live, &mut history);
+        assert!(!history.undo(&mut live));
+        assert_eq!(live.filters[0].gain, 3.0);
+
+        slots.swap("A", &mut live, &mut history);
+        assert!(history.undo(&mut live));
+        assert_eq!(live.filters[0].gain, 0.0);
+    }
+
+    #[test]
+    fn test_recall_keeps_history_per_slot() {
+        let mut slots = Slots::default();
+        let mut live = Eq::new("test", [Filter::default()]);
+        let mut history = History::default();
+
+        slots.store(Some("B"), &live);
+        let before = live.clone();
+        live.adjust_gain(|_| 3.0);
+        history.record(&before, &live, None);
+
+        // B has no edits to undo
+        assert!(slots.recall("B", &mut live, &mut history));
+        assert!(!history.undo(&mut live));
+        assert_eq!(live.filters[0].gain, 0.0);
+
+        // Undo in A stays within A, and recalling the active slot is an edit of its own
+        slots.swap("A", &mut live, &mut history);
+        assert_eq!(live.filters[0].gain, 0.0);
+        slots.store(None, &live);
+        live.adjust_gain(|_| -3.0);
+        assert!(slots.recall("A", &mut live, &mut history));
+        assert_eq!(live.filters[0].gain, 0.0);
+        assert!(history.undo(&mut live));
+        assert_eq!(live.filters[0].gain, -3.0);
+        // The edit made in A before B was recalled
+        assert!(history.undo(&mut live));
+        assert_eq!(live.filters[0].gain, 0.0);
+        assert!(!history.undo(&mut live));
+    }
+
+    #[test]
+    fn test_recall_discards_unstored_changes() {
+        let mut slots = Slots::default();
+        let mut live = Eq::new("test", [Filter::default()]);
+        let mut history = History::default();
+
+        slots.store(Some("flat"), &live);
+        live.adjust_gain(|_| 3.0);
+        assert!(slots.recall("flat", &mut live, &mut history));
+        assert_eq!(live.filters[0].gain, 0.0);
+        assert_eq!(slots.active(), "flat");
+        assert_eq!(slots.alternate(), "A");
+
+        assert!(!slots.recall("missing", &mut live, &mut history));
+        assert_eq!(slots.active(), "flat");
+    }
+}
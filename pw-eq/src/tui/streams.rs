@@ -0,0 +1,40 @@
+use crate::routing::Stream;
+
+/// Application streams reported by the PipeWire thread
+#[derive(Debug, Default)]
+pub struct StreamList {
+    pub streams: Vec<Stream>,
+    pub selected_index: usize,
+}
+
+impl StreamList {
+    pub fn on_streams_changed(&mut self, streams: Vec<Stream>) {
+        // Keep the same stream selected as streams come and go
+        let selected = self.selected().map(|stream| stream.node_id);
+        self.streams = streams;
+        self.selected_index = selected
+            .and_then(|node_id| {
+                self.streams
+                    .iter()
+                    .position(|stream| stream.node_id == node_id)
+            })
+            .unwrap_or(
+                self.selected_index
+                    .min(self.streams.len().saturating_sub(1)),
+            );
+    }
+
+    pub fn selected(&self) -> Option<&Stream> {
+        self.streams.get(self.selected_index)
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected_index + 1 < self.streams.len() {
+            self.selected_index += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected_index = self.selected_index.saturating_sub(1);
+    }
+}
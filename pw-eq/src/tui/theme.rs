@@ -25,6 +25,18 @@ pub(crate) struct Theme {
     pub status_ok: Color,
     pub status_error: Color,
     pub chart: Color,
+    /// Phase or group delay curve
+    pub chart_secondary: Color,
+    pub band_marker: Color,
+    pub band_marker_selected: Color,
+    /// Individual band responses beneath the summed curve
+    pub band_curve: Color,
+    /// Spectrum analyzer behind the response curve
+    pub spectrum: Color,
+    pub measurement: Color,
+    pub target: Color,
+    /// Measurement with the EQ applied
+    pub predicted: Color,
     pub border: Color,
 }
 
@@ -59,6 +71,14 @@ impl Theme {
             status_ok: Color::Rgb(0x83, 0x94, 0x96),
             status_error: Color::Rgb(0xdc, 0x32, 0x2f),
             chart: Color::Rgb(0x2a, 0xa1, 0x98),
+            chart_secondary: Color::Rgb(0x6c, 0x71, 0xc4),
+            band_marker: Color::Rgb(0x93, 0xa1, 0xa1),
+            band_marker_selected: Color::Rgb(0xd3, 0x36, 0x82),
+            band_curve: Color::Rgb(0x83, 0x94, 0x96),
+            spectrum: Color::Rgb(0x65, 0x7b, 0x83),
+            measurement: Color::Rgb(0xb5, 0x89, 0x00),
+            target: Color::Rgb(0x85, 0x99, 0x00),
+            predicted: Color::Rgb(0x26, 0x8b, 0xd2),
             border: Color::Rgb(0x58, 0x6e, 0x75),
         }
     }
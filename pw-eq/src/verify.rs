@@ -0,0 +1,285 @@
+//! Measure the response of a running EQ with a log sweep and compare it with its filters.
+
+use std::time::Duration;
+
+use num_complex::Complex64;
+use pw_util::module::FilterType;
+use rustfft::FftPlanner;
+
+use crate::filter::Filter;
+
+const SWEEP_START: f64 = 10.0;
+const SWEEP_END: f64 = 20000.0;
+/// Fade at either end of the sweep in seconds, to avoid clicks
+const FADE: f64 = 0.01;
+/// Silence after the sweep in seconds, so the capture receives the end of the response
+const TAIL: f64 = 1.0;
+/// Width of the bands the measured response is averaged over, in octaves
+const SMOOTHING: f64 = 1.0 / 24.0;
+const POINTS_PER_OCTAVE: f64 = 24.0;
+/// Frequencies where the expected response is lower than this are left out of the comparison,
+/// the measurement of deep cuts is dominated by noise
+const FLOOR_DB: f64 = -30.0;
+
+/// Exponential sine sweep from `SWEEP_START` to `SWEEP_END`
+pub fn log_sweep(duration: f64, sample_rate: u32, level_db: f64) -> Vec<f32> {
+    let amplitude = 10f64.powf(level_db / 20.0);
+    let len = (duration * sample_rate as f64) as usize;
+    let ratio = (SWEEP_END / SWEEP_START).ln();
+    let fade = (FADE * sample_rate as f64).max(1.0);
+
+    (0..len)
+        .map(|i| {
+            let t = i as f64 / sample_rate as f64;
+            let phase = 2.0 * std::f64::consts::PI * SWEEP_START * duration / ratio
+                * ((t / duration * ratio).exp() - 1.0);
+            let envelope = (i as f64 / fade).min((len - i) as f64 / fade).min(1.0);
+            (amplitude * envelope * phase.sin()) as f32
+        })
+        .collect()
+}
+
+/// Magnitude response of a system derived from a sweep and its recorded output
+pub struct Response {
+    /// Power gain of each FFT bin
+    power: Vec<f64>,
+    bin_width: f64,
+    sample_rate: u32,
+}
+
+impl Response {
+    /// Deconvolve the recording by the sweep, the delay between them does not matter
+    pub fn from_sweep(sweep: &[f32], recorded: &[f32], sample_rate: u32) -> Self {
+        let size = (sweep.len() + recorded.len()).next_power_of_two();
+        let fft = FftPlanner::new().plan_fft_forward(size);
+        let spectrum = |signal: &[f32]| {
+            let mut buffer = signal
+                .iter()
+                .map(|&sample| Complex64::new(sample as f64, 0.0))
+                .chain(std::iter::repeat(Complex64::default()))
+                .take(size)
+                .collect::<Vec<_>>();
+            fft.process(&mut buffer);
+            buffer
+        };
+
+        let (x, y) = (spectrum(sweep), spectrum(recorded));
+
+        // Regularize the division outside of the swept range where the sweep has no energy
+        let max = x.iter().map(|bin| bin.norm_sqr()).fold(0.0, f64::max);
+        let epsilon = max * 1e-8;
+        let power = x
+            .iter()
+            .zip(&y)
+            .take(size / 2 + 1)
+            .map(|(x, y)| {
+                let x = x.norm_sqr();
+                y.norm_sqr() * x / (x + epsilon).powi(2)
+            })
+            .collect();
+
+        Self {
+            power,
+            bin_width: sample_rate as f64 / size as f64,
+            sample_rate,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Level in dB averaged over a fraction of an octave centred on the frequency
+    pub fn level_db_at(&self, freq: f64) -> f64 {
+        let last_bin = self.power.len() - 1;
+        let bin_at = |freq: f64| ((freq / self.bin_width).round() as usize).min(last_bin);
+        let half_band = 2f64.powf(SMOOTHING / 2.0);
+        let (lo, hi) = (bin_at(freq / half_band), bin_at(freq * half_band));
+        let power = self.power[lo..=hi].iter().sum::<f64>() / (hi - lo + 1) as f64;
+        10.0 * power.log10()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BandDeviation {
+    pub filter: Filter,
+    /// Largest difference between the measured and expected response around the band,
+    /// `None` if the band is muted or outside of the measured range
+    pub max_deviation_db: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub sample_rate: u32,
+    /// Level of the measurement relative to the expected response, e.g. due to the sink volume.
+    /// Deviations are relative to this offset.
+    pub offset_db: f64,
+    pub bands: Vec<BandDeviation>,
+}
+
+impl Report {
+    pub fn max_deviation_db(&self) -> f64 {
+        self.bands
+            .iter()
+            .filter_map(|band| band.max_deviation_db)
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Compare a measured response with the response of the preamp and filters at the measured rate
+pub fn compare(response: &Response, preamp_db: f64, filters: &[Filter]) -> Report {
+    let sample_rate = response.sample_rate() as f64;
+    let (lo, hi) = (20.0, (0.9 * SWEEP_END).min(0.45 * sample_rate));
+    let n = ((hi / lo).log2() * POINTS_PER_OCTAVE).ceil() as usize;
+
+    // (frequency, measured - expected) where the expected response is above the floor
+    let differences = (0..=n)
+        .map(|i| lo * (hi / lo).powf(i as f64 / n as f64))
+        .filter_map(|freq| {
+            let expected = preamp_db
+                + filters
+                    .iter()
+                    .map(|filter| filter.magnitude_db_at(freq, sample_rate))
+                    .sum::<f64>();
+            (expected - preamp_db > FLOOR_DB).then(|| (freq, response.level_db_at(freq) - expected))
+        })
+        .collect::<Vec<_>>();
+
+    let offset_db = median(differences.iter().map(|&(_, diff)| diff));
+
+    let bands = filters
+        .iter()
+        .map(|&filter| {
+            let (lo, hi) = band_region(&filter);
+            let max_deviation_db = if filter.muted {
+                None
+            } else {
+                differences
+                    .iter()
+                    .filter(|(freq, _)| (lo..=hi).contains(freq))
+                    .map(|(_, diff)| (diff - offset_db).abs())
+                    .reduce(f64::max)
+            };
+            BandDeviation {
+                filter,
+                max_deviation_db,
+            }
+        })
+        .collect();
+
+    Report {
+        sample_rate: response.sample_rate(),
+        offset_db,
+        bands,
+    }
+}
+
+/// Frequency range where the band has a significant effect
+fn band_region(filter: &Filter) -> (f64, f64) {
+    let octaves = match filter.filter_type {
+        FilterType::Peaking | FilterType::BandPass | FilterType::Notch => {
+            (2.0 / std::f64::consts::LN_2 * (1.0 / (2.0 * filter.q)).asinh()).max(1.0 / 3.0)
+        }
+        FilterType::LowShelf
+        | FilterType::HighShelf
+        | FilterType::LowPass
+        | FilterType::HighPass => 2.0,
+    };
+    let half = 2f64.powf(octaves / 2.0);
+    (filter.frequency / half, filter.frequency * half)
+}
+
+fn median(values: impl Iterator<Item = f64>) -> f64 {
+    let mut values = values.collect::<Vec<_>>();
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    values[values.len() / 2]
+}
+
+/// Play a sweep into the sink of a running EQ and measure the response at its output.
+/// The sweep is played at `playback_rate`, the response is measured at the rate of the graph.
+pub fn measure(
+    sink: &str,
+    output: &str,
+    duration: f64,
+    level_db: f64,
+    playback_rate: u32,
+) -> anyhow::Result<Response> {
+    let mut signal = log_sweep(duration, playback_rate, level_db);
+    signal.resize(signal.len() + (TAIL * playback_rate as f64) as usize, 0.0);
+
+    let timeout = Duration::from_secs_f64(duration + TAIL + 5.0);
+    let (recorded, sample_rate) =
+        crate::pw::play_and_record(sink, output, signal, playback_rate, timeout)?;
+    anyhow::ensure!(
+        !recorded.is_empty(),
+        "no audio was captured from `{output}`"
+    );
+
+    // PipeWire resamples the sweep to the rate of the graph, which is the same sweep
+    // sampled at that rate
+    let reference = log_sweep(duration, sample_rate, level_db);
+    Ok(Response::from_sweep(&reference, &recorded, sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use pw_util::module::FilterType;
+
+    use super::{Response, compare, log_sweep};
+    use crate::filter::Filter;
+    use crate::render::Processor;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn filters() -> Vec<Filter> {
+        vec![
+            Filter::new(FilterType::LowShelf, 100.0, 4.0, 0.7).unwrap(),
+            Filter::new(FilterType::Peaking, 1000.0, -6.0, 2.0).unwrap(),
+            Filter::new(FilterType::Peaking, 10000.0, 5.0, 1.5).unwrap(),
+            Filter {
+                muted: true,
+                ..Filter::new(FilterType::Peaking, 3000.0, 6.0, 1.0).unwrap()
+            },
+        ]
+    }
+
+    /// Run the sweep through the EQ with coefficients computed at `coefficient_rate`,
+    /// delayed and attenuated as it would be by the graph and the sink volume
+    fn record(sweep: &[f32], coefficient_rate: u32) -> Vec<f32> {
+        let mut processor = Processor::new(-3.0, &filters(), coefficient_rate, 1);
+        let mut recorded = vec![0.0; 1000];
+        recorded.extend(
+            sweep
+                .iter()
+                .chain(&[0.0; 4800])
+                .map(|&sample| (processor.process(0, sample as f64) * 0.5) as f32),
+        );
+        recorded
+    }
+
+    #[test]
+    fn test_sweep_matches_filters() {
+        let sweep = log_sweep(1.0, SAMPLE_RATE, -12.0);
+        let response = Response::from_sweep(&sweep, &record(&sweep, SAMPLE_RATE), SAMPLE_RATE);
+        let report = compare(&response, -3.0, &filters());
+
+        // Halving the amplitude is about -6 dB
+        assert!((report.offset_db + 6.02).abs() < 0.1, "{report:?}");
+        assert!(report.max_deviation_db() < 0.5, "{report:?}");
+        assert_eq!(report.bands[3].max_deviation_db, None);
+    }
+
+    #[test]
+    fn test_sweep_detects_wrong_sample_rate() {
+        let sweep = log_sweep(1.0, SAMPLE_RATE, -12.0);
+        let response = Response::from_sweep(&sweep, &record(&sweep, 44100), SAMPLE_RATE);
+        let report = compare(&response, -3.0, &filters());
+
+        // The bands shift up in frequency, which is most visible at the top
+        let deviation = report.bands[2].max_deviation_db.unwrap();
+        assert!(deviation > 1.0, "{report:?}");
+    }
+}
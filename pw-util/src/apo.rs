@@ -18,6 +18,8 @@ pub struct Filter {
 pub struct Config {
     pub preamp: f64,
     pub filters: Vec<Filter>,
+    /// Points of a `GraphicEQ:` line as (frequency, gain), sorted by frequency
+    pub graphic_eq: Option<Vec<(f64, f64)>>,
 }
 
 impl fmt::Display for Config {
@@ -43,6 +45,13 @@ impl fmt::Display for Config {
                 filter.q
             )?;
         }
+        if let Some(points) = &self.graphic_eq {
+            let points = points
+                .iter()
+                .map(|(freq, gain)| format!("{freq} {gain:.1}"))
+                .collect::<Vec<_>>();
+            writeln!(f, "GraphicEQ: {}", points.join("; "))?;
+        }
         Ok(())
     }
 }
@@ -53,9 +62,11 @@ impl FromStr for Config {
     fn from_str(content: &str) -> Result<Self> {
         let mut preamp = 0.0;
         let mut filters = Vec::new();
+        let mut graphic_eq = None;
 
-        for line in content.lines() {
+        for (idx, line) in content.lines().enumerate() {
             let line = line.trim();
+            let lineno = idx + 1;
 
             if line.is_empty() || line.starts_with('#') {
                 continue;
@@ -70,20 +81,37 @@ impl FromStr for Config {
                         .trim_end_matches("db")
                         .trim();
                     preamp = value_str
-                        .parse()
-                        .context(format!("Invalid preamp value: {}", value_str))?;
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|preamp| preamp.is_finite())
+                        .with_context(|| {
+                            format!("Invalid preamp value on line {lineno}: {value_str}")
+                        })?;
                 }
                 continue;
             }
 
             // Parse filter line: "Filter 1: ON PK Fc 46 Hz Gain 0.8 dB Q 2.9"
             if line.starts_with("Filter") {
-                let filter = parse_filter_line(line)?;
+                let filter = parse_filter_line(line)
+                    .with_context(|| format!("Invalid filter on line {lineno}: {line}"))?;
                 filters.push(filter);
             }
+
+            // Parse graphic EQ line: "GraphicEQ: 25 -1.2; 40 0.5; 63 1.1"
+            if let Some(points) = line.strip_prefix("GraphicEQ:") {
+                anyhow::ensure!(graphic_eq.is_none(), "Duplicate GraphicEQ on line {lineno}");
+                let points = parse_graphic_eq(points)
+                    .with_context(|| format!("Invalid GraphicEQ on line {lineno}"))?;
+                graphic_eq = Some(points);
+            }
         }
 
-        Ok(Config { preamp, filters })
+        Ok(Config {
+            preamp,
+            filters,
+            graphic_eq,
+        })
     }
 }
 
@@ -97,6 +125,28 @@ impl Config {
     }
 }
 
+fn parse_graphic_eq(points: &str) -> Result<Vec<(f64, f64)>> {
+    let mut points = points
+        .split(';')
+        .map(str::trim)
+        .filter(|point| !point.is_empty())
+        .map(|point| {
+            let values = point
+                .split_whitespace()
+                .map(|value| value.parse::<f64>().ok().filter(|value| value.is_finite()))
+                .collect::<Option<Vec<_>>>();
+            match values.as_deref() {
+                Some(&[freq, gain]) if freq > 0.0 => Ok((freq, gain)),
+                _ => anyhow::bail!("expected a positive frequency and a gain, got `{point}`"),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    anyhow::ensure!(!points.is_empty(), "no points");
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(points)
+}
+
 fn parse_filter_line(line: &str) -> Result<Filter> {
     // Split by ':'
     let parts: Vec<&str> = line.split(':').collect();
@@ -178,6 +228,8 @@ fn parse_filter_line(line: &str) -> Result<Filter> {
         }
     }
 
+    filter_type.validate(frequency, gain, q)?;
+
     Ok(Filter {
         number,
         enabled,
@@ -227,4 +279,46 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_parse_invalid_filter_reports_line() {
+        let content = "Preamp: -6.2 dB\nFilter 1: ON PK Fc 46 Hz Gain 0.8 dB Q 2.9\nFilter 2: ON PK Fc 1000 Hz Gain 2 dB Q 0\n";
+        let err = content.parse::<Config>().unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "Invalid filter on line 3: Filter 2: ON PK Fc 1000 Hz Gain 2 dB Q 0: Q must be positive for Peaking filters, got 0"
+        );
+
+        let err = "Filter 1: ON LSC Fc 0 Hz Gain 2 dB Q 0.7"
+            .parse::<Config>()
+            .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "Invalid filter on line 1: Filter 1: ON LSC Fc 0 Hz Gain 2 dB Q 0.7: frequency must be positive, got 0 Hz"
+        );
+    }
+
+    #[test]
+    fn test_graphic_eq_roundtrip() {
+        let content = "Preamp: -3.0 dB\nGraphicEQ: 1000 -2; 31.5 1.5;63 0\n";
+        let config = content.parse::<Config>().unwrap();
+        assert!(config.filters.is_empty());
+        assert_eq!(
+            config.graphic_eq.as_deref(),
+            Some(&[(31.5, 1.5), (63.0, 0.0), (1000.0, -2.0)][..])
+        );
+
+        let s = config.to_string();
+        assert_eq!(
+            s,
+            "Preamp: -3.0 dB\nGraphicEQ: 31.5 1.5; 63 0.0; 1000 -2.0\n"
+        );
+        assert_eq!(s.parse::<Config>().unwrap().graphic_eq, config.graphic_eq);
+
+        let err = "GraphicEQ: 31.5 1.5; 63".parse::<Config>().unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "Invalid GraphicEQ on line 1: expected a positive frequency and a gain, got `63`"
+        );
+    }
 }
@@ -1,10 +1,10 @@
 use anyhow::Context as _;
 
 use crate::apo;
-use std::{fmt, path::Path};
+use std::{fmt, ops::RangeInclusive, path::Path};
 
 // Property to mark nodes as managed by pw-eq
-// Ensure this matches the field name in CaptureProps
+// Ensure this matches the field name in CaptureProps and PlaybackProps
 pub const MANAGED_PROP: &str = "pweq.managed";
 pub const FILTER_PREFIX: &str = "pweq.filter_";
 
@@ -112,19 +112,47 @@ impl Module {
                     links,
                 },
                 playback_props: PlaybackProps {
-                    node_name: format!("effect_input.pw-eq.{name}"),
+                    node_name: output_node_name(name),
                     target_object: None,
                     node_passive: false,
+                    media_class: None,
+                    pweq_managed: false,
                 },
                 capture_props: CaptureProps {
-                    node_name: format!("effect_output.pw-eq.{name}"),
-                    media_class: "Audio/Sink".to_string(),
+                    node_name: sink_node_name(name),
+                    media_class: Some("Audio/Sink".to_string()),
+                    node_passive: false,
+                    target_object: None,
                     pweq_managed: true,
                 },
             },
         }
     }
 
+    /// Equalize a capture device rather than what is played into a sink. The module is then an
+    /// `Audio/Source` for applications to record from, fed from the device with the given target
+    /// or the default source.
+    pub fn into_source(mut self, device: Option<TargetObject>) -> Self {
+        let name = self.args.media_name.clone();
+        self.args.node_description = format!("{name} microphone equalizer");
+        self.args.capture_props = CaptureProps {
+            node_name: source_capture_node_name(&name),
+            media_class: None,
+            // Don't keep the device running when nothing records from the source
+            node_passive: true,
+            target_object: device,
+            pweq_managed: false,
+        };
+        self.args.playback_props = PlaybackProps {
+            node_name: source_node_name(&name),
+            node_passive: false,
+            target_object: None,
+            media_class: Some("Audio/Source".to_string()),
+            pweq_managed: true,
+        };
+        self
+    }
+
     pub fn from_apo(name: &str, apo: &apo::Config) -> Self {
         let kinds = apo.filters.iter().map(|filter| {
             let control = Control {
@@ -147,6 +175,26 @@ impl Module {
     }
 }
 
+/// Name of the `Audio/Sink` node that applications play into
+pub fn sink_node_name(name: &str) -> String {
+    format!("effect_output.pw-eq.{name}")
+}
+
+/// Name of the stream node that plays the equalized audio to the target device
+pub fn output_node_name(name: &str) -> String {
+    format!("effect_input.pw-eq.{name}")
+}
+
+/// Name of the `Audio/Source` node that applications record from
+pub fn source_node_name(name: &str) -> String {
+    format!("effect_source.pw-eq.{name}")
+}
+
+/// Name of the stream node that records from the capture device
+pub fn source_capture_node_name(name: &str) -> String {
+    format!("effect_capture.pw-eq.{name}")
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ModuleArgs {
     #[serde(rename = "node.description")]
@@ -172,6 +220,20 @@ pub struct PlaybackProps {
     pub node_passive: bool,
     #[serde(rename = "target.object", skip_serializing_if = "Option::is_none")]
     pub target_object: Option<TargetObject>,
+    /// `Audio/Source` for a microphone EQ
+    #[serde(
+        default,
+        rename = "media.class",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub media_class: Option<String>,
+    /// Set on the side of a microphone EQ that applications use, see [`MANAGED_PROP`]
+    #[serde(
+        default,
+        rename = "pweq.managed",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub pweq_managed: bool,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -185,10 +247,28 @@ pub enum TargetObject {
 pub struct CaptureProps {
     #[serde(rename = "node.name")]
     pub node_name: String,
-    #[serde(rename = "media.class")]
-    pub media_class: String,
+    /// `Audio/Sink`, unset for a microphone EQ which captures from a device
+    #[serde(
+        default,
+        rename = "media.class",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub media_class: Option<String>,
+    #[serde(
+        default,
+        rename = "node.passive",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub node_passive: bool,
+    /// Capture device of a microphone EQ
+    #[serde(rename = "target.object", skip_serializing_if = "Option::is_none")]
+    pub target_object: Option<TargetObject>,
     // Ensure this rename matches the constant MANAGED_PROP
-    #[serde(default, rename = "pweq.managed")]
+    #[serde(
+        default,
+        rename = "pweq.managed",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
     pub pweq_managed: bool,
 }
 
@@ -314,6 +394,83 @@ pub enum FilterType {
     HighShelf,
 }
 
+/// Editable frequency range in Hz, shared by all filter types
+pub const FREQUENCY_RANGE: RangeInclusive<f64> = 20.0..=20000.0;
+
+impl FilterType {
+    /// Whether the gain parameter has any effect on the response
+    pub fn uses_gain(self) -> bool {
+        matches!(
+            self,
+            FilterType::LowShelf | FilterType::HighShelf | FilterType::Peaking
+        )
+    }
+
+    /// Editable Q range. Passes and shelves become degenerate well before resonant filters do.
+    pub fn q_range(self) -> RangeInclusive<f64> {
+        match self {
+            FilterType::LowPass
+            | FilterType::HighPass
+            | FilterType::LowShelf
+            | FilterType::HighShelf => 0.1..=10.0,
+            FilterType::Peaking | FilterType::BandPass | FilterType::Notch => 0.001..=10.0,
+        }
+    }
+
+    /// Editable gain range in dB, or `None` if the filter type ignores gain
+    pub fn gain_range(self) -> Option<RangeInclusive<f64>> {
+        self.uses_gain().then_some(-12.0..=12.0)
+    }
+
+    /// Check that the parameters describe a filter that biquad coefficients can be computed for.
+    /// Values outside the editable ranges are accepted as long as they are meaningful.
+    pub fn validate(self, frequency: f64, gain: f64, q: f64) -> Result<(), FilterError> {
+        for (param, value) in [("frequency", frequency), ("gain", gain), ("Q", q)] {
+            if !value.is_finite() {
+                return Err(FilterError::NonFinite { param, value });
+            }
+        }
+
+        if frequency <= 0.0 {
+            return Err(FilterError::InvalidFrequency(frequency));
+        }
+
+        if q <= 0.0 {
+            return Err(FilterError::InvalidQ {
+                filter_type: self,
+                q,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterError {
+    NonFinite { param: &'static str, value: f64 },
+    InvalidFrequency(f64),
+    InvalidQ { filter_type: FilterType, q: f64 },
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::NonFinite { param, value } => {
+                write!(f, "{param} must be finite, got {value}")
+            }
+            FilterError::InvalidFrequency(freq) => {
+                write!(f, "frequency must be positive, got {freq} Hz")
+            }
+            FilterError::InvalidQ { filter_type, q } => {
+                write!(f, "Q must be positive for {filter_type:?} filters, got {q}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Control {
     pub freq: f64,
@@ -386,8 +543,8 @@ mod tests {
     use crate::{
         apo::{self},
         module::{
-            BiquadCoefficients, Control, FilterType, NodeKind, ParamEqConfig, ParamEqFilter,
-            RateAndBiquadCoefficients, RawNodeConfig,
+            BiquadCoefficients, Control, FilterType, Module, NodeKind, ParamEqConfig,
+            ParamEqFilter, RateAndBiquadCoefficients, RawNodeConfig, TargetObject,
         },
         to_spa_json,
     };
@@ -574,6 +731,59 @@ mod tests {
         .assert_eq(&out);
     }
 
+    #[test]
+    fn test_generate_source_config() {
+        let config = Config {
+            context_modules: vec![Module::from_kinds("mic-eq", -1.0, []).into_source(Some(
+                TargetObject::NodeName("alsa_input.usb-mic".to_string()),
+            ))],
+        };
+
+        expect![[r#"
+            {
+                context.modules = [
+                    {
+                        name = "libpipewire-module-filter-chain"
+                        args = {
+                            node.description = "mic-eq microphone equalizer"
+                            media.name = "mic-eq"
+                            filter.graph = {
+                                nodes = [
+                                    {
+                                        type = "builtin"
+                                        name = "pweq.filter_preamp"
+                                        label = "bq_highshelf"
+                                        control = {
+                                            freq = 0.0
+                                            q = 0.0
+                                            gain = -1.0
+                                        }
+                                    }
+                                ]
+                            }
+                            audio.channels = 2
+                            audio_position = [
+                                "FL"
+                                "FR"
+                            ]
+                            playback.props = {
+                                node.name = "effect_source.pw-eq.mic-eq"
+                                node.passive = false
+                                media.class = "Audio/Source"
+                                pweq.managed = true
+                            }
+                            capture.props = {
+                                node.name = "effect_capture.pw-eq.mic-eq"
+                                node.passive = true
+                                target.object = "alsa_input.usb-mic"
+                            }
+                        }
+                    }
+                ]
+            }"#]]
+        .assert_eq(&to_spa_json(&config));
+    }
+
     #[test]
     fn test_generate_config_from_apo() {
         let config = apo::Config {
@@ -596,6 +806,7 @@ mod tests {
                     q: 0.667,
                 },
             ],
+            graphic_eq: None,
         };
 
         let out = to_spa_json(&Config::from_apo("test-eq", &config));